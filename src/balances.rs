@@ -1,6 +1,14 @@
 
 use std::collections::BTreeMap;
-use num::traits::{CheckedSub, CheckedAdd, Zero};
+use std::marker::PhantomData;
+use num::traits::{CheckedSub, CheckedAdd, PrimInt, Zero};
+use crate::codec::{read_variant, Decode, DecodeError, Encode};
+use crate::metadata::{errors, variant, PalletInfo, VariantMetadata};
+use crate::migrations::{MigrationError, OnRuntimeUpgrade, StorageVersion};
+use crate::support::{
+    storage_key, with_transaction, DispatchError, GetWeight, StorageEntries, StorageEntry,
+    Transactional, Weight,
+};
 
 // pub struct Pallet {
 //     balances: BTreeMap<String, u128>,
@@ -8,7 +16,73 @@ use num::traits::{CheckedSub, CheckedAdd, Zero};
 //     fee_recipient: Option<String>,
 // }
 pub trait Config: crate::system::Config {
-    type Balance: CheckedAdd + CheckedSub + Zero + Copy + PartialOrd;  // Added PartialOrd
+    type Balance: CheckedAdd + CheckedSub + Zero + Copy + PartialOrd + Encode + Decode;  // Added PartialOrd
+    // How the fee of a transfer is worked out from its amount and the base fee
+    type FeeStrategy: FeeStrategy<Self::Balance>;
+}
+
+// Works out the fee of transferring `amount`, given the pallet's base fee
+// Built-in strategies are types, so a runtime picks one in its `Config` and their
+// parameters are constants; the base fee stays chain state that can be changed
+pub trait FeeStrategy<Balance> {
+    fn fee(amount: Balance, base_fee: Balance) -> Balance;
+}
+
+// Every transfer pays the base fee, whatever its amount
+pub struct Flat;
+
+// Transfers pay `PERCENT` percent of their amount, but never less than the base fee
+pub struct Percentage<const PERCENT: u8>;
+
+// Transfers of less than `THRESHOLD` pay the fee of strategy `Below`, larger ones that of `Above`
+// Nesting them gives any number of tiers
+pub struct Tiered<const THRESHOLD: u64, Below, Above>(PhantomData<(Below, Above)>);
+
+// The fee of strategy `S`, but never more than `MAX`
+pub struct Capped<S, const MAX: u64>(PhantomData<S>);
+
+// A fee parameter as a balance - one too large for the balance type is its largest value
+fn fee_constant<Balance: PrimInt>(value: u64) -> Balance {
+    Balance::from(value).unwrap_or_else(Balance::max_value)
+}
+
+impl<Balance> FeeStrategy<Balance> for Flat {
+    fn fee(_amount: Balance, base_fee: Balance) -> Balance {
+        base_fee
+    }
+}
+
+impl<Balance: PrimInt, const PERCENT: u8> FeeStrategy<Balance> for Percentage<PERCENT> {
+    fn fee(amount: Balance, base_fee: Balance) -> Balance {
+        // Whole hundreds and the remainder are scaled apart, so large amounts cannot overflow
+        let (hundred, percent) = (fee_constant::<Balance>(100), fee_constant::<Balance>(PERCENT.into()));
+        let fee = (amount / hundred)
+            .checked_mul(&percent)
+            .unwrap_or_else(Balance::max_value)
+            .saturating_add(amount % hundred * percent / hundred);
+        fee.max(base_fee)
+    }
+}
+
+impl<Balance, Below, Above, const THRESHOLD: u64> FeeStrategy<Balance> for Tiered<THRESHOLD, Below, Above>
+where
+    Balance: PrimInt,
+    Below: FeeStrategy<Balance>,
+    Above: FeeStrategy<Balance>,
+{
+    fn fee(amount: Balance, base_fee: Balance) -> Balance {
+        if amount < fee_constant(THRESHOLD) {
+            Below::fee(amount, base_fee)
+        } else {
+            Above::fee(amount, base_fee)
+        }
+    }
+}
+
+impl<Balance: PrimInt, S: FeeStrategy<Balance>, const MAX: u64> FeeStrategy<Balance> for Capped<S, MAX> {
+    fn fee(amount: Balance, base_fee: Balance) -> Balance {
+        S::fee(amount, base_fee).min(fee_constant(MAX))
+    }
 }
 
 // enum Result<T, E> {
@@ -24,6 +98,10 @@ pub enum BalancesError {
     OverflowInCalculation,
     OverflowInTransfer,
     InvalidAmount,
+    ExistentialDeposit,
+    KeepAlive,
+    InsufficientReserved,
+    LiquidityRestrictions,
 }
 
 impl std::fmt::Display for BalancesError {
@@ -36,14 +114,304 @@ impl std::fmt::Display for BalancesError {
             }
             BalancesError::OverflowInTransfer => write!(f, "Overflow in transfer calculation"),
             BalancesError::InvalidAmount => write!(f, "Invalid amount specified"),
+            BalancesError::ExistentialDeposit => {
+                write!(f, "Receiving account would hold less than the existential deposit")
+            }
+            BalancesError::KeepAlive => write!(f, "Transfer would reap the sending account"),
+            BalancesError::InsufficientReserved => write!(f, "Not enough reserved balance"),
+            BalancesError::LiquidityRestrictions => write!(f, "Funds are locked and cannot be used for this"),
+        }
+    }
+}
+
+// Where repatriated reserved funds end up in the beneficiary's account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BalanceStatus {
+    Free,
+    Reserved,
+}
+
+impl Encode for BalanceStatus {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.push(match self {
+            BalanceStatus::Free => 0,
+            BalanceStatus::Reserved => 1,
+        });
+    }
+}
+
+impl Decode for BalanceStatus {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(BalanceStatus::Free),
+            1 => Ok(BalanceStatus::Reserved),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+// Names a lock, so whoever placed it can change or remove it later
+pub type LockIdentifier = [u8; 8];
+
+// What the funds under a lock cannot be used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reasons {
+    Fee,  // Paying fees and tips
+    Misc, // Anything else: transfers and reserves
+    All,
+}
+
+impl Reasons {
+    // Whether a lock for these reasons stops funds being used for `reason`
+    fn covers(self, reason: Reasons) -> bool {
+        self == Reasons::All || self == reason
+    }
+
+    fn union(self, other: Reasons) -> Reasons {
+        if self == other { self } else { Reasons::All }
+    }
+}
+
+// Funds that stay in an account's free balance but cannot be used for `reasons`
+// An account's locks overlap: the largest one that applies is what is held back
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceLock<Balance> {
+    pub id: LockIdentifier,
+    pub amount: Balance,
+    pub reasons: Reasons,
+}
+
+impl Encode for Reasons {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.push(match self {
+            Reasons::Fee => 0,
+            Reasons::Misc => 1,
+            Reasons::All => 2,
+        });
+    }
+}
+
+impl Decode for Reasons {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(Reasons::Fee),
+            1 => Ok(Reasons::Misc),
+            2 => Ok(Reasons::All),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl<Balance: Encode> Encode for BalanceLock<Balance> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.id.encode_to(dest);
+        self.amount.encode_to(dest);
+        self.reasons.encode_to(dest);
+    }
+}
+
+impl<Balance: Decode> Decode for BalanceLock<Balance> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            id: Decode::decode(input)?,
+            amount: Decode::decode(input)?,
+            reasons: Decode::decode(input)?,
+        })
+    }
+}
+
+// Balances events - collected by the runtime into the system pallet
+#[derive(Debug, Clone)]
+pub enum BalancesEvent<T: Config> {
+    Transfer {
+        from: T::AccountId,
+        to: T::AccountId,
+        amount: T::Balance,
+    },
+    FeePaid {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    BalanceSet {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    // Funds too small to keep an account alive were burned
+    DustLost {
+        account: T::AccountId,
+        amount: T::Balance,
+    },
+    // An account fell below the existential deposit and was removed
+    AccountReaped {
+        who: T::AccountId,
+    },
+    Minted {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    Burned {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    Reserved {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    Unreserved {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+    ReserveRepatriated {
+        from: T::AccountId,
+        to: T::AccountId,
+        amount: T::Balance,
+        destination_status: BalanceStatus,
+    },
+    // Reserved funds were taken as a penalty and destroyed
+    Slashed {
+        who: T::AccountId,
+        amount: T::Balance,
+    },
+}
+
+impl<T: Config> Encode for BalancesEvent<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            BalancesEvent::Transfer { from, to, amount } => {
+                dest.push(0);
+                from.encode_to(dest);
+                to.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::FeePaid { who, amount } => {
+                dest.push(1);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::BalanceSet { who, amount } => {
+                dest.push(2);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::DustLost { account, amount } => {
+                dest.push(3);
+                account.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::AccountReaped { who } => {
+                dest.push(4);
+                who.encode_to(dest);
+            }
+            BalancesEvent::Minted { who, amount } => {
+                dest.push(5);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::Burned { who, amount } => {
+                dest.push(6);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::Reserved { who, amount } => {
+                dest.push(7);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::Unreserved { who, amount } => {
+                dest.push(8);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            BalancesEvent::ReserveRepatriated { from, to, amount, destination_status } => {
+                dest.push(9);
+                from.encode_to(dest);
+                to.encode_to(dest);
+                amount.encode_to(dest);
+                destination_status.encode_to(dest);
+            }
+            BalancesEvent::Slashed { who, amount } => {
+                dest.push(10);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
         }
     }
 }
-#[derive(Debug)]
+
+impl<T: Config> Decode for BalancesEvent<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(BalancesEvent::Transfer {
+                from: Decode::decode(input)?,
+                to: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            1 => Ok(BalancesEvent::FeePaid {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            2 => Ok(BalancesEvent::BalanceSet {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            3 => Ok(BalancesEvent::DustLost {
+                account: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            4 => Ok(BalancesEvent::AccountReaped {
+                who: Decode::decode(input)?,
+            }),
+            5 => Ok(BalancesEvent::Minted {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            6 => Ok(BalancesEvent::Burned {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            7 => Ok(BalancesEvent::Reserved {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            8 => Ok(BalancesEvent::Unreserved {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            9 => Ok(BalancesEvent::ReserveRepatriated {
+                from: Decode::decode(input)?,
+                to: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+                destination_status: Decode::decode(input)?,
+            }),
+            10 => Ok(BalancesEvent::Slashed {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl From<BalancesError> for DispatchError {
+    fn from(error: BalancesError) -> Self {
+        DispatchError::module("Balances", error)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Pallet<T: Config> {  
-    balances: BTreeMap<T::AccountId, T::Balance>,  
+    balances: BTreeMap<T::AccountId, T::Balance>,  // Free balances
+    // Funds still owned by an account but set aside, e.g. staked; they cannot be spent
+    // or transferred until unreserved, and keep the account alive while there are any
+    reserved: BTreeMap<T::AccountId, T::Balance>,
+    locks: BTreeMap<T::AccountId, Vec<BalanceLock<T::Balance>>>,
     base_fee: T::Balance,                         
     fee_recipient: Option<T::AccountId>,          
+    // The smallest balance an account may hold: below it the account is reaped
+    existential_deposit: T::Balance,
+    // Every token in existence: the sum of all free and reserved balances
+    total_issuance: T::Balance,
+    pub events: Vec<BalancesEvent<T>>,
 }
 
 impl<T: Config> Pallet<T> { 
@@ -57,8 +425,13 @@ impl<T: Config> Pallet<T> {
     pub fn new() -> Self {
         Self {
             balances: BTreeMap::new(),
+            reserved: BTreeMap::new(),
+            locks: BTreeMap::new(),
             base_fee: T::Balance::zero(),  // Start with zero fee using generic type
             fee_recipient: None,
+            existential_deposit: T::Balance::zero(),
+            total_issuance: T::Balance::zero(),
+            events: Vec::new(),
         }
     }
 
@@ -72,8 +445,13 @@ impl<T: Config> Pallet<T> {
      pub fn new_with_fee_config(base_fee: T::Balance, fee_recipient: Option<T::AccountId>) -> Self {
         Self {
             balances: BTreeMap::new(),
+            reserved: BTreeMap::new(),
+            locks: BTreeMap::new(),
             base_fee,
             fee_recipient,
+            existential_deposit: T::Balance::zero(),
+            total_issuance: T::Balance::zero(),
+            events: Vec::new(),
         }
     }
 
@@ -98,6 +476,39 @@ impl<T: Config> Pallet<T> {
         self.fee_recipient = recipient;
     }
 
+    pub fn set_existential_deposit(&mut self, deposit: T::Balance) {
+        self.existential_deposit = deposit;
+    }
+
+    pub fn existential_deposit(&self) -> T::Balance {
+        self.existential_deposit
+    }
+
+    // Whether `who` would be too poor to exist with a free balance of `balance`
+    // Without an existential deposit every account exists, even an empty one, so none is ever reaped
+    // Any reserved funds or locks keep an account alive too
+    fn below_minimum(&self, who: &T::AccountId, balance: T::Balance) -> bool {
+        balance < self.existential_deposit
+            && self.reserved_balance(who).is_zero()
+            && self.locks(who).is_empty()
+    }
+
+    // Remove `who` if their balance is below the existential deposit
+    // Whatever is left is dust: it is burned along with the account
+    // An account holding a lock is never reaped, so its locks are never lost
+    fn reap_if_dust(&mut self, who: &T::AccountId) {
+        if !self.below_minimum(who, self.balance(who)) {
+            return;
+        }
+        if let Some(dust) = self.balances.remove(who) {
+            self.reduce_issuance(dust);
+            if !dust.is_zero() {
+                self.events.push(BalancesEvent::DustLost { account: who.clone(), amount: dust });
+            }
+            self.events.push(BalancesEvent::AccountReaped { who: who.clone() });
+        }
+    }
+
     // fn calculate_fee(&self, _amount: u128) -> u128 {
     //     if _amount > 100 {
     //         _amount / 10
@@ -105,8 +516,9 @@ impl<T: Config> Pallet<T> {
     //         self.base_fee
     //     }
     // }
-    fn calculate_fee(&self, amount: T::Balance) -> T::Balance {
-        self.base_fee
+    // The fee of transferring `amount`, as the runtime's fee strategy works it out
+    pub fn calculate_fee(&self, amount: T::Balance) -> T::Balance {
+        T::FeeStrategy::fee(amount, self.base_fee)
     }
 
     // fn handle_fee_payment(&mut self, who: &String, fee: u128) -> Result<(), BalancesError> {
@@ -128,7 +540,12 @@ impl<T: Config> Pallet<T> {
     //     }
     //     Ok(())
     // }
+      // The fee goes to the fee recipient, or is burned when there is none
+      // A fee too small to bring the recipient up to the existential deposit is burned too
       fn handle_fee_payment(&mut self, who: &T::AccountId, fee: T::Balance) -> Result<(), BalancesError> {
+        if fee.is_zero() {
+            return Ok(());
+        }
         let payer_balance = self.balance(who);
         
         // Check if payer has enough balance for fee
@@ -137,25 +554,287 @@ impl<T: Config> Pallet<T> {
             .ok_or(BalancesError::InsufficientFunds)?;
 
         // Deduct fee from payer
+        self.ensure_can_withdraw(who, Reasons::Fee, new_balance)?;
         self.balances.insert(who.clone(), new_balance);
+        self.events.push(BalancesEvent::FeePaid { who: who.clone(), amount: fee });
 
-        // Add fee to recipient if one is set
-        if let Some(ref recipient) = self.fee_recipient {
-            let recipient_balance = self.balance(recipient);
-            let new_recipient_balance = recipient_balance
+        // Add fee to recipient if one is set and can hold it
+        if let Some(recipient) = self.fee_recipient.clone() {
+            let new_recipient_balance = self
+                .balance(&recipient)
                 .checked_add(&fee)
                 .ok_or(BalancesError::OverflowInCalculation)?;
-            self.balances.insert(recipient.clone(), new_recipient_balance);
+            if !self.below_minimum(&recipient, new_recipient_balance) {
+                self.balances.insert(recipient, new_recipient_balance);
+                return Ok(());
+            }
+        }
+
+        self.reduce_issuance(fee);
+        self.events.push(BalancesEvent::Burned { who: who.clone(), amount: fee });
+        Ok(())
+    }
+
+    // Take tokens that left an account without going anywhere else out of the issuance
+    // Issuance always covers every balance, unless the state is already inconsistent -
+    // which the runtime's issuance check reports
+    fn reduce_issuance(&mut self, amount: T::Balance) {
+        self.total_issuance = self
+            .total_issuance
+            .checked_sub(&amount)
+            .unwrap_or_else(T::Balance::zero);
+    }
+
+    // Create `amount` new tokens in `who`'s account
+    pub fn mint(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let total_issuance = self
+            .total_issuance
+            .checked_add(&amount)
+            .ok_or(BalancesError::OverflowInCalculation)?;
+        let new_balance = self
+            .balance(who)
+            .checked_add(&amount)
+            .ok_or(BalancesError::OverflowInTransfer)?;
+        if self.below_minimum(who, new_balance) {
+            return Err(BalancesError::ExistentialDeposit);
+        }
+
+        self.balances.insert(who.clone(), new_balance);
+        self.total_issuance = total_issuance;
+        self.events.push(BalancesEvent::Minted { who: who.clone(), amount });
+        Ok(())
+    }
+
+    // Destroy `amount` of `who`'s tokens, reaping the account if too little is left
+    pub fn burn(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let new_balance = self
+            .balance(who)
+            .checked_sub(&amount)
+            .ok_or(BalancesError::InsufficientBalance)?;
+
+        self.balances.insert(who.clone(), new_balance);
+        self.reduce_issuance(amount);
+        self.events.push(BalancesEvent::Burned { who: who.clone(), amount });
+        self.reap_if_dust(who);
+        Ok(())
+    }
+
+    pub fn reserved_balance(&self, who: &T::AccountId) -> T::Balance {
+        *self.reserved.get(who).unwrap_or(&T::Balance::zero())
+    }
+
+    /// Get every account's reserved balance, ordered by account
+    pub fn all_reserved(&self) -> &BTreeMap<T::AccountId, T::Balance> {
+        &self.reserved
+    }
+
+    // Set `amount` of `who`'s free balance aside
+    pub fn reserve(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        let new_free = self
+            .balance(who)
+            .checked_sub(&amount)
+            .ok_or(BalancesError::InsufficientBalance)?;
+        self.ensure_can_withdraw(who, Reasons::Misc, new_free)?;
+        let new_reserved = self
+            .reserved_balance(who)
+            .checked_add(&amount)
+            .ok_or(BalancesError::OverflowInCalculation)?;
+        self.balances.insert(who.clone(), new_free);
+        self.reserved.insert(who.clone(), new_reserved);
+        self.events.push(BalancesEvent::Reserved { who: who.clone(), amount });
+        Ok(())
+    }
+
+    // Return `amount` of `who`'s reserved funds to their free balance
+    pub fn unreserve(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        let new_free = self
+            .balance(who)
+            .checked_add(&amount)
+            .ok_or(BalancesError::OverflowInTransfer)?;
+        self.take_reserved(who, amount)?;
+        self.balances.insert(who.clone(), new_free);
+        self.events.push(BalancesEvent::Unreserved { who: who.clone(), amount });
+        self.reap_if_dust(who);
+        Ok(())
+    }
+
+    // Destroy up to `amount` of `who`'s reserved funds as a penalty
+    // Returns how much was slashed, which is less than `amount` if less was reserved
+    pub fn slash_reserved(&mut self, who: &T::AccountId, amount: T::Balance) -> T::Balance {
+        let reserved = self.reserved_balance(who);
+        let slashed = if amount < reserved { amount } else { reserved };
+        if slashed.is_zero() {
+            return slashed;
+        }
+        // Never more than is reserved, so this cannot fail
+        let _ = self.take_reserved(who, slashed);
+        self.reduce_issuance(slashed);
+        self.events.push(BalancesEvent::Slashed { who: who.clone(), amount: slashed });
+        self.reap_if_dust(who);
+        slashed
+    }
+
+    // Move `amount` of `slashed`'s reserved funds to `beneficiary`, as free or reserved funds
+    // Funds moved to a free balance must leave the beneficiary above the existential deposit
+    pub fn repatriate_reserved(
+        &mut self,
+        slashed: &T::AccountId,
+        beneficiary: &T::AccountId,
+        amount: T::Balance,
+        status: BalanceStatus,
+    ) -> Result<(), BalancesError> {
+        with_transaction(self, |pallet| {
+            pallet.take_reserved(slashed, amount)?;
+            // Read after the reserve is taken, so repatriating to oneself moves the funds
+            // between one's own balances
+            match status {
+                BalanceStatus::Free => {
+                    let new_free = pallet
+                        .balance(beneficiary)
+                        .checked_add(&amount)
+                        .ok_or(BalancesError::OverflowInTransfer)?;
+                    if pallet.below_minimum(beneficiary, new_free) {
+                        return Err(BalancesError::ExistentialDeposit);
+                    }
+                    pallet.balances.insert(beneficiary.clone(), new_free);
+                }
+                BalanceStatus::Reserved => {
+                    let new_reserved = pallet
+                        .reserved_balance(beneficiary)
+                        .checked_add(&amount)
+                        .ok_or(BalancesError::OverflowInTransfer)?;
+                    pallet.reserved.insert(beneficiary.clone(), new_reserved);
+                    // An account is reaped through its free balance entry, so one that
+                    // only holds reserved funds still needs one
+                    pallet.balances.entry(beneficiary.clone()).or_insert_with(T::Balance::zero);
+                }
+            }
+            pallet.events.push(BalancesEvent::ReserveRepatriated {
+                from: slashed.clone(),
+                to: beneficiary.clone(),
+                amount,
+                destination_status: status,
+            });
+            pallet.reap_if_dust(slashed);
+            Ok(())
+        })
+    }
+
+    // Lock `amount` of `who`'s free balance under `id`, replacing any lock already there
+    // A lock may be larger than the free balance: then none of it can be used
+    pub fn set_lock(&mut self, id: LockIdentifier, who: &T::AccountId, amount: T::Balance, reasons: Reasons) {
+        if amount.is_zero() {
+            self.remove_lock(id, who);
+            return;
+        }
+        let lock = BalanceLock { id, amount, reasons };
+        let locks = self.locks.entry(who.clone()).or_default();
+        match locks.iter_mut().find(|lock| lock.id == id) {
+            Some(existing) => *existing = lock,
+            None => locks.push(lock),
+        }
+    }
+
+    // Like `set_lock`, but never shrinks a lock already under `id`: the larger amount
+    // is kept and the reasons of both apply
+    pub fn extend_lock(&mut self, id: LockIdentifier, who: &T::AccountId, amount: T::Balance, reasons: Reasons) {
+        let (amount, reasons) = match self.locks(who).iter().find(|lock| lock.id == id) {
+            Some(existing) if existing.amount > amount => (existing.amount, existing.reasons.union(reasons)),
+            Some(existing) => (amount, existing.reasons.union(reasons)),
+            None => (amount, reasons),
+        };
+        self.set_lock(id, who, amount, reasons);
+    }
+
+    pub fn remove_lock(&mut self, id: LockIdentifier, who: &T::AccountId) {
+        if let Some(locks) = self.locks.get_mut(who) {
+            locks.retain(|lock| lock.id != id);
+            if locks.is_empty() {
+                self.locks.remove(who);
+            }
+        }
+    }
+
+    pub fn locks(&self, who: &T::AccountId) -> &[BalanceLock<T::Balance>] {
+        self.locks.get(who).map_or(&[], Vec::as_slice)
+    }
+
+    // How much of `who`'s free balance the locks hold back from being used for `reason`
+    pub fn locked_balance(&self, who: &T::AccountId, reason: Reasons) -> T::Balance {
+        self.locks(who)
+            .iter()
+            .filter(|lock| lock.reasons.covers(reason))
+            .fold(T::Balance::zero(), |largest, lock| if lock.amount > largest { lock.amount } else { largest })
+    }
+
+    // Free funds `who` can transfer or reserve
+    pub fn usable_balance(&self, who: &T::AccountId) -> T::Balance {
+        self.balance(who)
+            .checked_sub(&self.locked_balance(who, Reasons::Misc))
+            .unwrap_or_else(T::Balance::zero)
+    }
+
+    // Check that using free funds for `reason`, leaving `new_balance`, keeps every lock covered
+    fn ensure_can_withdraw(&self, who: &T::AccountId, reason: Reasons, new_balance: T::Balance) -> Result<(), BalancesError> {
+        if new_balance < self.locked_balance(who, reason) {
+            return Err(BalancesError::LiquidityRestrictions);
+        }
+        Ok(())
+    }
+
+    // Take `amount` out of `who`'s reserved funds, dropping the entry once nothing is left
+    fn take_reserved(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        let new_reserved = self
+            .reserved_balance(who)
+            .checked_sub(&amount)
+            .ok_or(BalancesError::InsufficientReserved)?;
+        if new_reserved.is_zero() {
+            self.reserved.remove(who);
+        } else {
+            self.reserved.insert(who.clone(), new_reserved);
         }
-        
         Ok(())
     }
 
+    // Every token in existence, free or reserved
+    pub fn total_issuance(&self) -> T::Balance {
+        self.total_issuance
+    }
+
+    // Only for migrating chains saved before the issuance was tracked, whose issuance
+    // has to be counted from every token held at the time
+    pub fn set_total_issuance(&mut self, total_issuance: T::Balance) {
+        self.total_issuance = total_issuance;
+    }
+
+    // Only for migrating chains saved before funds could be reserved: add funds that were
+    // held outside the pallet, and already counted in the issuance, as reserved funds
+    pub fn import_reserves(&mut self, reserves: impl IntoIterator<Item = (T::AccountId, T::Balance)>) {
+        for (who, amount) in reserves {
+            let reserved = self.reserved_balance(&who).checked_add(&amount).unwrap_or(amount);
+            self.reserved.insert(who.clone(), reserved);
+            self.balances.entry(who).or_insert_with(T::Balance::zero);
+        }
+    }
+
     // pub fn set_balance(&mut self, who: &String, amount: u128) {
     //     self.balances.insert(who.clone(), amount);
     // }
-     pub fn set_balance(&mut self, who: &T::AccountId, amount: T::Balance) {
-        self.balances.insert(who.clone(), amount);
+     // Set `who`'s balance outright, minting or burning the difference
+     pub fn set_balance(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), BalancesError> {
+        let current = self.balance(who);
+        match amount.checked_sub(&current) {
+            Some(increase) => self.mint(who, increase)?,
+            None => self.burn(who, current.checked_sub(&amount).unwrap_or_else(T::Balance::zero))?,
+        }
+        self.events.push(BalancesEvent::BalanceSet { who: who.clone(), amount });
+        Ok(())
     }
 
     // pub fn balance(&self, who: &String) -> u128 {
@@ -165,6 +844,36 @@ impl<T: Config> Pallet<T> {
         *self.balances.get(who).unwrap_or(&T::Balance::zero())
     }
 
+    /// Get every account balance, ordered by account
+    pub fn all_balances(&self) -> &BTreeMap<T::AccountId, T::Balance> {
+        &self.balances
+    }
+
+    /// Whether `who` can pay `fee` out of funds that are not locked against fees
+    pub fn can_pay_fee(&self, who: &T::AccountId, fee: T::Balance) -> bool {
+        self.balance(who)
+            .checked_sub(&fee)
+            .is_some_and(|new_balance| new_balance >= self.locked_balance(who, Reasons::Fee))
+    }
+
+    /// Charge `who` the fee of an extrinsic, tip included
+    /// It goes to the fee recipient like the fee of a transfer
+    pub fn pay_fee(&mut self, who: &T::AccountId, fee: T::Balance) -> Result<(), BalancesError> {
+        if fee.is_zero() {
+            return Ok(());
+        }
+        with_transaction(self, |pallet| {
+            pallet.handle_fee_payment(who, fee)?;
+            pallet.reap_if_dust(who);
+            Ok(())
+        })
+    }
+
+    /// Take the events emitted since they were last taken
+    pub fn take_events(&mut self) -> Vec<BalancesEvent<T>> {
+        std::mem::take(&mut self.events)
+    }
+
     //Implemented the Balances Error here
     // pub fn get_transfer_cost(&self, amount: u128) -> Result<u128, BalancesError> {
     //     let fee = self.calculate_fee(amount);
@@ -213,53 +922,333 @@ impl<T: Config> Pallet<T> {
 
     //     Ok(())
     // }
+    // Transfers may leave the sender below the existential deposit, which reaps them,
+    // but the receiver must end up holding at least the existential deposit
     pub fn transfer(
         &mut self,
         sender: T::AccountId,
         receiver: T::AccountId,
         amount: T::Balance,
     ) -> Result<(), BalancesError> {
-        let fee = self.calculate_fee(amount);
-        let sender_balance = self.balance(&sender);
-        let receiver_balance = self.balance(&receiver);
+        self.do_transfer(sender, receiver, amount, false, self.calculate_fee(amount))
+    }
 
-        // Check if sender has enough balance for transfer + fee
-        let total_needed = amount
-            .checked_add(&fee)
-            .ok_or(BalancesError::OverflowInCalculation)?;
-        
-        if sender_balance < total_needed {
-            return Err(BalancesError::InsufficientBalance);
-        }
+    // Like `transfer`, but fails instead of reaping the sender
+    pub fn transfer_keep_alive(
+        &mut self,
+        sender: T::AccountId,
+        receiver: T::AccountId,
+        amount: T::Balance,
+    ) -> Result<(), BalancesError> {
+        self.do_transfer(sender, receiver, amount, true, self.calculate_fee(amount))
+    }
 
-        // Calculate new balances
-        let new_sender_balance = sender_balance
-            .checked_sub(&amount)
-            .ok_or(BalancesError::InsufficientFunds)?;
-        let new_receiver_balance = receiver_balance
-            .checked_add(&amount)
-            .ok_or(BalancesError::OverflowInTransfer)?;
+    // Like `transfer` or `transfer_keep_alive`, for a transfer whose fee was already paid
+    // That is the case for transfers made by an extrinsic, which pays its fee up front
+    pub fn transfer_prepaid(
+        &mut self,
+        sender: T::AccountId,
+        receiver: T::AccountId,
+        amount: T::Balance,
+        keep_alive: bool,
+    ) -> Result<(), BalancesError> {
+        self.do_transfer(sender, receiver, amount, keep_alive, T::Balance::zero())
+    }
+
+    fn do_transfer(
+        &mut self,
+        sender: T::AccountId,
+        receiver: T::AccountId,
+        amount: T::Balance,
+        keep_alive: bool,
+        fee: T::Balance,
+    ) -> Result<(), BalancesError> {
+        // The balances are updated before the fee is paid, so both run as one storage
+        // transaction: if paying the fee fails, the transfer is undone as well
+        with_transaction(self, |pallet| {
+            let sender_balance = pallet.balance(&sender);
+
+            // Check if sender has enough balance for transfer + fee
+            let total_needed = amount
+                .checked_add(&fee)
+                .ok_or(BalancesError::OverflowInCalculation)?;
 
-        // Update balances
-        self.balances.insert(sender.clone(), new_sender_balance);
-        self.balances.insert(receiver, new_receiver_balance);
+            if sender_balance < total_needed {
+                return Err(BalancesError::InsufficientBalance);
+            }
+            if keep_alive && pallet.below_minimum(&sender, sender_balance.checked_sub(&total_needed).unwrap_or_else(T::Balance::zero)) {
+                return Err(BalancesError::KeepAlive);
+            }
 
-        // Handle fee payment
-        self.handle_fee_payment(&sender, fee)?;
+            // Update balances
+            // The receiver's balance is read after the sender's is written, so sending
+            // to oneself leaves the balance as it was rather than creating tokens
+            let new_sender_balance = sender_balance
+                .checked_sub(&amount)
+                .ok_or(BalancesError::InsufficientFunds)?;
+            pallet.ensure_can_withdraw(&sender, Reasons::Misc, new_sender_balance)?;
+            pallet.balances.insert(sender.clone(), new_sender_balance);
+            let new_receiver_balance = pallet
+                .balance(&receiver)
+                .checked_add(&amount)
+                .ok_or(BalancesError::OverflowInTransfer)?;
+            if pallet.below_minimum(&receiver, new_receiver_balance) {
+                return Err(BalancesError::ExistentialDeposit);
+            }
+            pallet.balances.insert(receiver.clone(), new_receiver_balance);
+            pallet.events.push(BalancesEvent::Transfer {
+                from: sender.clone(),
+                to: receiver,
+                amount,
+            });
 
-        Ok(())
+            // Pay the fee, then reap the sender if too little is left
+            pallet.handle_fee_payment(&sender, fee)?;
+            pallet.reap_if_dust(&sender);
+
+            Ok(())
+        })
     }
 }
 
 // Enum for calls
+#[derive(Debug, Clone)]
 pub enum Call<T: Config> {
     Transfer {
         to: T::AccountId,
         amount: T::Balance,
     },
+    TransferKeepAlive {
+        to: T::AccountId,
+        amount: T::Balance,
+    },
+}
+
+// Weights of the balances operations
+pub mod weights {
+    use crate::support::Weight;
+
+    pub const TRANSFER: Weight = 100;
+}
+
+impl<T: Config> GetWeight for Call<T> {
+    fn weight(&self) -> Weight {
+        match self {
+            Call::Transfer { .. } | Call::TransferKeepAlive { .. } => weights::TRANSFER,
+        }
+    }
+}
+
+// Dispatched calls can change any account balance and emit events
+// Events are only ever appended, so dropping the ones after the checkpoint reverts them
+impl<T: Config> Transactional for Pallet<T> {
+    type Checkpoint = (
+        BTreeMap<T::AccountId, T::Balance>,
+        BTreeMap<T::AccountId, T::Balance>,
+        BTreeMap<T::AccountId, Vec<BalanceLock<T::Balance>>>,
+        T::Balance,
+        usize,
+    );
+
+    fn checkpoint(&self) -> Self::Checkpoint {
+        (
+            self.balances.clone(),
+            self.reserved.clone(),
+            self.locks.clone(),
+            self.total_issuance,
+            self.events.len(),
+        )
+    }
+
+    fn restore(&mut self, checkpoint: Self::Checkpoint) {
+        let (balances, reserved, locks, total_issuance, event_count) = checkpoint;
+        self.balances = balances;
+        self.reserved = reserved;
+        self.locks = locks;
+        self.total_issuance = total_issuance;
+        self.events.truncate(event_count);
+    }
+}
+
+// The balances pallet commits to every free and reserved balance, every account's locks
+// and the total issuance
+impl<T: Config> StorageEntries for Pallet<T> {
+    fn storage_entries(&self) -> Vec<StorageEntry> {
+        let issuance = (storage_key("Balances", "TotalIssuance", &()), self.total_issuance.encode());
+        let reserved = self
+            .reserved
+            .iter()
+            .map(|(who, reserved)| (storage_key("Balances", "Reserved", who), reserved.encode()));
+        let locks = self
+            .locks
+            .iter()
+            .map(|(who, locks)| (storage_key("Balances", "Locks", who), locks.encode()));
+        self.balances
+            .iter()
+            .map(|(who, balance)| (storage_key("Balances", "Balance", who), balance.encode()))
+            .chain(reserved)
+            .chain(locks)
+            .chain(std::iter::once(issuance))
+            .collect()
+    }
+}
+
+// The whole pallet is encoded when the runtime state is saved to disk
+// Events are not saved - they only live until the block that emitted them is done
+impl<T: Config> Encode for Pallet<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.balances.encode_to(dest);
+        self.base_fee.encode_to(dest);
+        self.fee_recipient.encode_to(dest);
+        self.existential_deposit.encode_to(dest);
+        self.total_issuance.encode_to(dest);
+        self.reserved.encode_to(dest);
+        self.locks.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for Pallet<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            balances: Decode::decode(input)?,
+            base_fee: Decode::decode(input)?,
+            fee_recipient: Decode::decode(input)?,
+            existential_deposit: Decode::decode(input)?,
+            total_issuance: Decode::decode(input)?,
+            reserved: Decode::decode(input)?,
+            locks: Decode::decode(input)?,
+            events: Vec::new(),
+        })
+    }
+}
+
+impl<T: Config> PalletInfo for Pallet<T> {
+    fn calls() -> Vec<VariantMetadata> {
+        vec![
+            variant(
+                0,
+                "Transfer",
+                &[("to", "AccountId"), ("amount", "Balance")],
+                "Send `amount` to `to`; the sender also pays the transaction fee",
+            ),
+            variant(
+                1,
+                "TransferKeepAlive",
+                &[("to", "AccountId"), ("amount", "Balance")],
+                "Like Transfer, but fails rather than leave the sender below the existential deposit",
+            ),
+        ]
+    }
+
+    fn events() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "Transfer", &[("from", "AccountId"), ("to", "AccountId"), ("amount", "Balance")], "Funds were transferred"),
+            variant(1, "FeePaid", &[("who", "AccountId"), ("amount", "Balance")], "A fee or tip was paid"),
+            variant(2, "BalanceSet", &[("who", "AccountId"), ("amount", "Balance")], "A balance was set directly"),
+            variant(3, "DustLost", &[("account", "AccountId"), ("amount", "Balance")], "Funds below the existential deposit were burned"),
+            variant(4, "AccountReaped", &[("who", "AccountId")], "An account fell below the existential deposit and was removed"),
+            variant(5, "Minted", &[("who", "AccountId"), ("amount", "Balance")], "New tokens were created"),
+            variant(6, "Burned", &[("who", "AccountId"), ("amount", "Balance")], "Tokens were destroyed"),
+            variant(7, "Reserved", &[("who", "AccountId"), ("amount", "Balance")], "Free funds were set aside"),
+            variant(8, "Unreserved", &[("who", "AccountId"), ("amount", "Balance")], "Reserved funds were made free again"),
+            variant(
+                9,
+                "ReserveRepatriated",
+                &[("from", "AccountId"), ("to", "AccountId"), ("amount", "Balance"), ("destination_status", "BalanceStatus")],
+                "Reserved funds were moved to another account",
+            ),
+            variant(10, "Slashed", &[("who", "AccountId"), ("amount", "Balance")], "Reserved funds were destroyed as a penalty"),
+        ]
+    }
+
+    fn errors() -> Vec<VariantMetadata> {
+        errors(&[
+            BalancesError::InsufficientBalance,
+            BalancesError::InsufficientFunds,
+            BalancesError::OverflowInCalculation,
+            BalancesError::OverflowInTransfer,
+            BalancesError::InvalidAmount,
+            BalancesError::ExistentialDeposit,
+            BalancesError::KeepAlive,
+            BalancesError::InsufficientReserved,
+            BalancesError::LiquidityRestrictions,
+        ])
+    }
+}
+
+impl<T: Config> OnRuntimeUpgrade for Pallet<T> {
+    const NAME: &'static str = "Balances";
+    const STORAGE_VERSION: StorageVersion = 5;
+
+    // Version 2 added the existential deposit after the fee recipient
+    // Chains saved before then had none, which a deposit of zero keeps as it was
+    //
+    // Version 3 added the total issuance after it. It is left at zero here: staked tokens
+    // count too, so the runtime fills it in once every pallet is loaded
+    //
+    // Version 4 added reserved balances at the end. They start out empty; the runtime
+    // reserves what was already staked, which used to leave the balances pallet entirely
+    //
+    // Version 5 added locks at the end. They start out empty too; the runtime moves
+    // reserved stakes into staking locks
+    fn on_runtime_upgrade(from: StorageVersion, state: Vec<u8>) -> Result<Vec<u8>, MigrationError> {
+        match from {
+            1 | 2 => {
+                let mut state = state;
+                T::Balance::zero().encode_to(&mut state);
+                Ok(state)
+            }
+            3 => {
+                let mut state = state;
+                BTreeMap::<T::AccountId, T::Balance>::new().encode_to(&mut state);
+                Ok(state)
+            }
+            4 => {
+                let mut state = state;
+                BTreeMap::<T::AccountId, Vec<BalanceLock<T::Balance>>>::new().encode_to(&mut state);
+                Ok(state)
+            }
+            _ => Err(MigrationError::NoMigration(Self::NAME, from)),
+        }
+    }
+}
+
+// Calls are encoded as a variant index followed by their fields
+impl<T: Config> Encode for Call<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Call::Transfer { to, amount } => {
+                dest.push(0);
+                to.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            Call::TransferKeepAlive { to, amount } => {
+                dest.push(1);
+                to.encode_to(dest);
+                amount.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Config> Decode for Call<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(Call::Transfer {
+                to: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            1 => Ok(Call::TransferKeepAlive {
+                to: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
 }
 
 // Implement dispatch for the pallet
+// Dispatched calls come from extrinsics, which have paid their fee before being dispatched
 impl<T: Config> crate::support::Dispatch for Pallet<T> {
     type Call = Call<T>;
     type Caller = T::AccountId;
@@ -271,8 +1260,10 @@ impl<T: Config> crate::support::Dispatch for Pallet<T> {
     ) -> crate::support::DispatchResult {
         match call {
             Call::Transfer { to, amount } => {
-                self.transfer(caller, to, amount)
-                    .map_err(|_| "Transfer failed")?;
+                self.transfer_prepaid(caller, to, amount, false)?;
+            }
+            Call::TransferKeepAlive { to, amount } => {
+                self.transfer_prepaid(caller, to, amount, true)?;
             }
         }
         Ok(())
@@ -288,10 +1279,12 @@ mod tests {
         type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = ();
     }
 
     impl Config for TestConfig {
         type Balance = u128;  // Use u128 for balances in tests
+        type FeeStrategy = Flat;
     }
 
     #[test]
@@ -299,7 +1292,7 @@ mod tests {
         let mut balances = Pallet::<TestConfig>::new();
 
         assert_eq!(balances.balance(&"alice".to_string()), 0);
-        balances.set_balance(&"alice".to_string(), 100);
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
         assert_eq!(balances.balance(&"alice".to_string()), 100);
         assert_eq!(balances.balance(&"bob".to_string()), 0);
     }
@@ -322,7 +1315,7 @@ mod tests {
             Err(BalancesError::InsufficientBalance)
         );
 
-        balances.set_balance(&"alice".to_string(), 100);
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
         assert_eq!(
             balances.transfer("alice".to_string(), "bob".to_string(), 51),
             Ok(())
@@ -374,8 +1367,8 @@ mod tests {
      fn transfer_with_fee_recipient() {
         let mut balances = Pallet::<TestConfig>::new_with_fee_config(5, Some("treasury".to_string()));
 
-        balances.set_balance(&"alice".to_string(), 100);
-        balances.set_balance(&"treasury".to_string(), 10);
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+        balances.set_balance(&"treasury".to_string(), 10).unwrap();
 
         assert_eq!(
             balances.transfer("alice".to_string(), "bob".to_string(), 30),
@@ -407,4 +1400,249 @@ mod tests {
     //     // Treasury: 10 + 5 = 15
     //     assert_eq!(balances.balance(&"treasury".to_string()), 15);
     // }
+
+    #[test]
+    fn transfer_with_fee_recipient_emits_events() {
+        let mut balances = Pallet::<TestConfig>::new_with_fee_config(5, Some("treasury".to_string()));
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+        balances.set_balance(&"treasury".to_string(), 10).unwrap();
+        balances.transfer("alice".to_string(), "bob".to_string(), 30).unwrap();
+
+        // The balance setup (each minting, then setting) is followed by the transfer and its fee
+        let events = balances.take_events();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(
+            &events[4],
+            BalancesEvent::Transfer { from, to, amount: 30 } if from == "alice" && to == "bob"
+        ));
+        assert!(matches!(&events[5], BalancesEvent::FeePaid { who, amount: 5 } if who == "alice"));
+        // The fee changed hands, so no tokens were created or destroyed
+        assert_eq!(balances.total_issuance(), 110);
+        assert!(balances.take_events().is_empty());
+    }
+
+    #[test]
+    fn failed_fee_payment_reverts_transfer() {
+        let mut balances = Pallet::<TestConfig>::new_with_fee_config(5, Some("treasury".to_string()));
+
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+        // The treasury cannot receive the fee without overflowing
+        // Issuance could never allow that, so the balance is written directly
+        balances.balances.insert("treasury".to_string(), u128::MAX);
+
+        assert_eq!(
+            balances.transfer("alice".to_string(), "bob".to_string(), 30),
+            Err(BalancesError::OverflowInCalculation)
+        );
+
+        // Nothing from the half-done transfer is left behind
+        assert_eq!(balances.balance(&"alice".to_string()), 100);
+        assert_eq!(balances.balance(&"bob".to_string()), 0);
+        assert_eq!(balances.all_balances().len(), 2);
+        // Only the two BalanceSet events remain
+        assert_eq!(balances.take_events().len(), 2);
+    }
+
+    #[test]
+    fn accounts_below_the_existential_deposit_are_reaped() {
+        let mut balances = Pallet::<TestConfig>::new_with_fee_config(1, Some("treasury".to_string()));
+        balances.set_existential_deposit(10);
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+        balances.take_events();
+
+        // bob would not hold enough to exist
+        assert_eq!(
+            balances.transfer("alice".to_string(), "bob".to_string(), 5),
+            Err(BalancesError::ExistentialDeposit)
+        );
+        // alice would be left with 4, which only a plain transfer may do
+        assert_eq!(
+            balances.transfer_keep_alive("alice".to_string(), "bob".to_string(), 95),
+            Err(BalancesError::KeepAlive)
+        );
+        assert_eq!(balances.transfer("alice".to_string(), "bob".to_string(), 95), Ok(()));
+
+        // The fee is too small for the treasury to exist on, so it is burned,
+        // and alice's last 4 tokens are burned along with her account
+        assert_eq!(balances.balance(&"bob".to_string()), 95);
+        assert_eq!(balances.all_balances().len(), 1);
+        assert_eq!(balances.total_issuance(), 95);
+        let events = balances.take_events();
+        assert!(matches!(events[0], BalancesEvent::Transfer { amount: 95, .. }));
+        assert!(matches!(events[1], BalancesEvent::FeePaid { amount: 1, .. }));
+        assert!(matches!(&events[2], BalancesEvent::Burned { who, amount: 1 } if who == "alice"));
+        assert!(matches!(&events[3], BalancesEvent::DustLost { account, amount: 4 } if account == "alice"));
+        assert!(matches!(&events[4], BalancesEvent::AccountReaped { who } if who == "alice"));
+        assert_eq!(events.len(), 5);
+
+        // Setting a balance to zero burns all of it and reaps the account
+        balances.set_balance(&"bob".to_string(), 0).unwrap();
+        assert!(balances.all_balances().is_empty());
+        assert_eq!(balances.total_issuance(), 0);
+        assert!(matches!(balances.take_events()[1], BalancesEvent::AccountReaped { .. }));
+    }
+
+    #[test]
+    fn reserved_funds_stay_with_their_owner() {
+        let mut balances = Pallet::<TestConfig>::new();
+        balances.set_existential_deposit(10);
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        balances.mint(&alice, 100).unwrap();
+
+        // Reserved funds cannot be spent, but keep alice's account alive
+        balances.reserve(&alice, 95).unwrap();
+        assert_eq!(balances.reserve(&alice, 10), Err(BalancesError::InsufficientBalance));
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 50),
+            Err(BalancesError::InsufficientBalance)
+        );
+        assert_eq!((balances.balance(&alice), balances.reserved_balance(&alice)), (5, 95));
+        assert_eq!(balances.unreserve(&alice, 100), Err(BalancesError::InsufficientReserved));
+        balances.unreserve(&alice, 15).unwrap();
+        assert_eq!((balances.balance(&alice), balances.reserved_balance(&alice)), (20, 80));
+
+        // Slashing takes no more than is reserved, and destroys it
+        assert_eq!(balances.slash_reserved(&alice, 30), 30);
+        assert_eq!(balances.total_issuance(), 70);
+
+        // Repatriated funds go to bob, who needs enough free funds to exist
+        assert_eq!(
+            balances.repatriate_reserved(&alice, &bob, 5, BalanceStatus::Free),
+            Err(BalancesError::ExistentialDeposit)
+        );
+        balances.repatriate_reserved(&alice, &bob, 20, BalanceStatus::Free).unwrap();
+        balances.repatriate_reserved(&alice, &bob, 5, BalanceStatus::Reserved).unwrap();
+        assert_eq!((balances.balance(&bob), balances.reserved_balance(&bob)), (20, 5));
+
+        // Once the reserve is gone, alice's free funds are dust again
+        assert_eq!(balances.slash_reserved(&alice, 100), 25);
+        assert_eq!(balances.balance(&alice), 20);
+        balances.burn(&alice, 15).unwrap();
+        assert!(!balances.all_balances().contains_key(&alice));
+        assert!(balances.all_reserved().keys().eq([&bob]));
+        assert_eq!(balances.total_issuance(), 25);
+    }
+
+    #[test]
+    fn locked_funds_stay_put() {
+        let mut balances = Pallet::<TestConfig>::new_with_fee_config(1, None);
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        balances.mint(&alice, 100).unwrap();
+
+        // Overlapping locks hold back the largest of them
+        balances.set_lock(*b"staking ", &alice, 60, Reasons::Misc);
+        balances.set_lock(*b"vesting ", &alice, 40, Reasons::All);
+        assert_eq!(balances.usable_balance(&alice), 40);
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 41),
+            Err(BalancesError::LiquidityRestrictions)
+        );
+        assert_eq!(balances.reserve(&alice, 41), Err(BalancesError::LiquidityRestrictions));
+
+        // The fee may come out of funds only locked against transfers
+        balances.transfer(alice.clone(), bob.clone(), 40).unwrap();
+        assert_eq!(balances.balance(&alice), 59);
+        assert_eq!(balances.pay_fee(&alice, 19), Ok(()));
+        assert_eq!(balances.pay_fee(&alice, 1), Err(BalancesError::LiquidityRestrictions));
+
+        // Extending keeps the larger amount and both sets of reasons
+        balances.extend_lock(*b"staking ", &alice, 10, Reasons::Fee);
+        assert_eq!(balances.locks(&alice)[0], BalanceLock { id: *b"staking ", amount: 60, reasons: Reasons::All });
+        balances.remove_lock(*b"staking ", &alice);
+        balances.set_lock(*b"vesting ", &alice, 0, Reasons::All);
+        assert!(balances.locks(&alice).is_empty());
+        assert_eq!(balances.usable_balance(&alice), 40);
+    }
+
+    #[test]
+    fn locked_accounts_are_never_reaped() {
+        let mut balances = Pallet::<TestConfig>::new();
+        balances.set_existential_deposit(10);
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        balances.mint(&alice, 100).unwrap();
+        balances.set_lock(*b"staking ", &alice, 5, Reasons::All);
+
+        // alice is left with less than the deposit, but her lock keeps the account and the lock alive
+        balances.transfer(alice.clone(), bob.clone(), 95).unwrap();
+        assert_eq!(balances.balance(&alice), 5);
+        assert_eq!(balances.locked_balance(&alice, Reasons::Fee), 5);
+        assert!(!balances
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, BalancesEvent::AccountReaped { .. })));
+
+        // Once the lock is gone the dust can be reaped as usual
+        balances.remove_lock(*b"staking ", &alice);
+        balances.burn(&alice, 1).unwrap();
+        assert!(!balances.all_balances().contains_key(&alice));
+    }
+
+    // A runtime whose transfers pay 10% of their amount, at most 20 tokens
+    struct PercentageConfig;
+
+    impl crate::system::Config for PercentageConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+    }
+
+    impl Config for PercentageConfig {
+        type Balance = u128;
+        type FeeStrategy = Capped<Percentage<10>, 20>;
+    }
+
+    #[test]
+    fn built_in_fee_strategies() {
+        assert_eq!(<Flat as FeeStrategy<u128>>::fee(1_000_000, 3), 3);
+
+        // A percentage of the amount, with the base fee as the least a transfer pays
+        assert_eq!(<Percentage<10> as FeeStrategy<u128>>::fee(1_000, 3), 100);
+        assert_eq!(<Percentage<10> as FeeStrategy<u128>>::fee(10, 3), 3);
+        assert_eq!(<Percentage<10> as FeeStrategy<u128>>::fee(u128::MAX, 0), u128::MAX / 10);
+
+        type Tiers = Tiered<100, Flat, Tiered<1_000, Percentage<5>, Percentage<1>>>;
+        assert_eq!(<Tiers as FeeStrategy<u128>>::fee(99, 3), 3);
+        assert_eq!(<Tiers as FeeStrategy<u128>>::fee(100, 3), 5);
+        assert_eq!(<Tiers as FeeStrategy<u128>>::fee(999, 3), 49);
+        assert_eq!(<Tiers as FeeStrategy<u128>>::fee(1_000, 3), 10);
+
+        assert_eq!(<Capped<Percentage<10>, 20> as FeeStrategy<u128>>::fee(100, 3), 10);
+        assert_eq!(<Capped<Percentage<10>, 20> as FeeStrategy<u128>>::fee(1_000, 3), 20);
+        assert_eq!(<Capped<Flat, { u64::MAX }> as FeeStrategy<u8>>::fee(1, 7), 7);
+    }
+
+    #[test]
+    fn transfers_pay_the_fee_of_the_runtime_strategy() {
+        let mut balances = Pallet::<PercentageConfig>::new_with_fee_config(1, None);
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        balances.mint(&alice, 1_000).unwrap();
+
+        assert_eq!(balances.get_transfer_cost(50), Ok(55));
+        assert_eq!(balances.get_transfer_cost(500), Ok(520));
+        assert_eq!(balances.get_transfer_cost(u128::MAX), Err(BalancesError::OverflowInCalculation));
+
+        balances.transfer(alice.clone(), bob.clone(), 500).unwrap();
+        assert_eq!(balances.balance(&alice), 480);
+        // What is left cannot cover 470 tokens plus their fee of 20
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 470), Err(BalancesError::InsufficientBalance));
+        assert_eq!(balances.total_issuance(), 980);
+    }
+
+    #[test]
+    fn dispatch_reports_the_balances_error() {
+        use crate::support::Dispatch;
+        let mut balances = Pallet::<TestConfig>::new();
+        balances.set_balance(&"alice".to_string(), 10).unwrap();
+
+        let result = balances.dispatch(
+            "alice".to_string(),
+            Call::Transfer { to: "bob".to_string(), amount: 20 },
+        );
+        assert_eq!(result, Err(DispatchError::from(BalancesError::InsufficientBalance)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Balances::InsufficientBalance: Insufficient balance"
+        );
+    }
 }
@@ -0,0 +1,396 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::{history, types, Runtime};
+
+// Every block a node has imported, on every fork, linked to its parent by hash
+//
+// The system pallet only remembers one block per height: the chain its state was built on.
+// The tree keeps each imported block together with the runtime state after executing it,
+// so a block can be imported on top of any block we know, not just the current head.
+//
+// Fork choice: the best block is the highest one; between blocks at the same height
+// the one with the lowest hash wins, so every node picks the same head from the same blocks
+//
+// Finality: the block of the best chain `FINALITY_DEPTH` below the best block is final,
+// and so is every block below it. Forks that do not build on the last final block are dropped,
+// so no fork can ever revert it. Below it only the blocks are kept, not the states after them
+pub struct BlockTree {
+    blocks: BTreeMap<[u8; 32], TreeBlock>,
+    best: [u8; 32],
+    finalized: [u8; 32], // The last final block
+}
+
+pub const FINALITY_DEPTH: types::BlockNumber = 8;
+
+struct TreeBlock {
+    block: types::Block,
+    // The state after executing the block, until a block above it is final
+    // Its block history is left out - the tree keeps the blocks itself
+    state: Option<Runtime>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    AlreadyImported,        // The block is already in the tree
+    UnknownParent,          // The block does not build on any block in the tree
+    FinalizedParent,        // The block builds below the last final block
+    Invalid(&'static str),  // Executing the block on its parent's state failed
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::AlreadyImported => write!(f, "Block is already imported"),
+            ImportError::UnknownParent => write!(f, "Parent block is unknown"),
+            ImportError::FinalizedParent => write!(f, "Parent block is already final"),
+            ImportError::Invalid(e) => write!(f, "Invalid block: {}", e),
+        }
+    }
+}
+
+// The blocks between two blocks of the tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeRoute {
+    pub common_ancestor: [u8; 32],
+    pub retracted: Vec<[u8; 32]>, // Blocks leaving the chain, from the old head downwards
+    pub enacted: Vec<[u8; 32]>,   // Blocks joining the chain, from the ancestor upwards
+}
+
+// What importing a block did to the best chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportOutcome {
+    NotBest,             // The block is on a fork that is not the best chain
+    Extended,            // The block was built on the head and became the new head
+    Reorganized(TreeRoute), // The best chain switched to the block's fork
+}
+
+impl BlockTree {
+    // Start a tree whose root is the head of `runtime`, usually a freshly built genesis
+    // Only the root's header is known, so it is stored with an empty body
+    pub fn new(mut runtime: Runtime) -> Self {
+        let header = runtime
+            .system
+            .get_header(runtime.system.block_number())
+            .cloned()
+            .expect("the runtime's head is finalized");
+        let hash = header.hash();
+        runtime.history = history::BlockHistory::new();
+        let root = TreeBlock {
+            block: types::Block {
+                header,
+                extrinsics: Vec::new(),
+            },
+            state: Some(runtime),
+        };
+        Self {
+            blocks: BTreeMap::from([(hash, root)]),
+            best: hash,
+            finalized: hash,
+        }
+    }
+
+    // Execute `block` on its parent's state, add it to the tree and apply the fork choice rule
+    // When the block's fork takes over, the best state reverts to the common ancestor and
+    // continues with the new branch - the route says which blocks left and joined the chain
+    pub fn import(&mut self, block: types::Block) -> Result<ImportOutcome, ImportError> {
+        let hash = block.header.hash();
+        if self.blocks.contains_key(&hash) {
+            return Err(ImportError::AlreadyImported);
+        }
+        let parent = self
+            .blocks
+            .get(&block.header.parent_hash)
+            .ok_or(ImportError::UnknownParent)?;
+        let parent_state = parent.state.as_ref().ok_or(ImportError::FinalizedParent)?;
+
+        let mut state = parent_state.clone();
+        state.execute_block(block.clone()).map_err(ImportError::Invalid)?;
+        state.history = history::BlockHistory::new();
+        let number = block.header.block_number;
+        self.blocks.insert(hash, TreeBlock { block, state: Some(state) });
+
+        let best_number = self.best_number();
+        if number < best_number || (number == best_number && hash > self.best) {
+            return Ok(ImportOutcome::NotBest);
+        }
+
+        let old_best = self.best;
+        self.best = hash;
+        self.finalize();
+        if self.blocks[&hash].block.header.parent_hash == old_best {
+            Ok(ImportOutcome::Extended)
+        } else {
+            Ok(ImportOutcome::Reorganized(self.tree_route(old_best, hash)))
+        }
+    }
+
+    // Move finality up the best chain to `FINALITY_DEPTH` below the best block
+    // Every block that does not build on the new final block is dropped, along with
+    // the states of the blocks below it, which nothing can be built on any more
+    fn finalize(&mut self) {
+        let best_number = self.best_number();
+        if best_number <= self.number(self.finalized) + FINALITY_DEPTH {
+            return;
+        }
+        let mut finalized = self.best;
+        while self.number(finalized) > best_number - FINALITY_DEPTH {
+            finalized = self.parent(finalized);
+        }
+        self.finalized = finalized;
+
+        let mut below = BTreeSet::new();
+        let mut hash = finalized;
+        while let Some(entry) = self.blocks.get(&hash) {
+            if hash != finalized {
+                below.insert(hash);
+            }
+            hash = entry.block.header.parent_hash;
+        }
+
+        let dropped: Vec<[u8; 32]> = self
+            .blocks
+            .keys()
+            .filter(|hash| !below.contains(*hash) && !self.builds_on(**hash, finalized))
+            .copied()
+            .collect();
+        for hash in dropped {
+            self.blocks.remove(&hash);
+        }
+        for hash in below {
+            if let Some(entry) = self.blocks.get_mut(&hash) {
+                entry.state = None;
+            }
+        }
+    }
+
+    // Whether block `hash` is block `ancestor` or one of its descendants
+    fn builds_on(&self, mut hash: [u8; 32], ancestor: [u8; 32]) -> bool {
+        let ancestor_number = self.number(ancestor);
+        while self.number(hash) > ancestor_number {
+            hash = self.parent(hash);
+        }
+        hash == ancestor
+    }
+
+    fn number(&self, hash: [u8; 32]) -> types::BlockNumber {
+        self.blocks[&hash].block.header.block_number
+    }
+
+    fn parent(&self, hash: [u8; 32]) -> [u8; 32] {
+        self.blocks[&hash].block.header.parent_hash
+    }
+
+    // The route from block `from` to block `to` through their common ancestor
+    // Both must be in the tree
+    pub fn tree_route(&self, from: [u8; 32], to: [u8; 32]) -> TreeRoute {
+        let number = |hash: [u8; 32]| self.blocks[&hash].block.header.block_number;
+        let mut retracted = Vec::new();
+        let mut enacted = Vec::new();
+        let (mut from, mut to) = (from, to);
+
+        // Walk down the higher branch until both sides are at the same height,
+        // then down both until they meet
+        while from != to {
+            if number(from) >= number(to) {
+                retracted.push(from);
+                from = self.blocks[&from].block.header.parent_hash;
+            } else {
+                enacted.push(to);
+                to = self.blocks[&to].block.header.parent_hash;
+            }
+        }
+        enacted.reverse();
+
+        TreeRoute {
+            common_ancestor: from,
+            retracted,
+            enacted,
+        }
+    }
+
+    pub fn best_hash(&self) -> [u8; 32] {
+        self.best
+    }
+
+    pub fn best_number(&self) -> types::BlockNumber {
+        self.blocks[&self.best].block.header.block_number
+    }
+
+    // The state after the best block - what the node's chain looks like now, without its block history
+    pub fn best_state(&self) -> &Runtime {
+        self.blocks[&self.best]
+            .state
+            .as_ref()
+            .expect("the best block is never final")
+    }
+
+    pub fn block(&self, hash: &[u8; 32]) -> Option<&types::Block> {
+        self.blocks.get(hash).map(|entry| &entry.block)
+    }
+
+    // Blocks nothing has been built on yet: the heads of every fork
+    pub fn leaves(&self) -> Vec<[u8; 32]> {
+        let parents: Vec<[u8; 32]> = self
+            .blocks
+            .values()
+            .map(|entry| entry.block.header.parent_hash)
+            .collect();
+        self.blocks
+            .keys()
+            .filter(|hash| !parents.contains(hash))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::{GenesisAccount, GenesisConfig};
+    use crate::{balances, crypto, support, RuntimeCall};
+
+    fn genesis() -> Runtime {
+        GenesisConfig {
+            accounts: vec![GenesisAccount::development("alice", 1000)],
+            ..GenesisConfig::default()
+        }
+        .build()
+        .unwrap()
+    }
+
+    // Author a block on `author` paying `amount` from alice to `to`
+    fn author(author: &mut Runtime, to: &str, amount: types::Balance) -> types::Block {
+        let extrinsic = support::Extrinsic::new_signed(
+            "alice".to_string(),
+            RuntimeCall::Balances(balances::Call::Transfer { to: to.to_string(), amount }),
+            author.system.account_nonce(&"alice".to_string()),
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+        author.author_block(vec![extrinsic]).0
+    }
+
+    #[test]
+    fn longer_fork_takes_over() {
+        let mut tree = BlockTree::new(genesis());
+        let genesis_hash = tree.best_hash();
+        let (mut a, mut b) = (genesis(), genesis());
+
+        let a1 = author(&mut a, "bob", 10);
+        let a1_hash = a1.header.hash();
+        assert_eq!(tree.import(a1), Ok(ImportOutcome::Extended));
+
+        // b builds a longer chain next to a's block
+        let b1 = author(&mut b, "charlie", 20);
+        let b2 = author(&mut b, "charlie", 30);
+        let (b1_hash, b2_hash) = (b1.header.hash(), b2.header.hash());
+        let first = tree.import(b1).unwrap();
+        assert_eq!(tree.best_number(), 1);
+        assert_eq!(first == ImportOutcome::NotBest, b1_hash > a1_hash);
+
+        let outcome = tree.import(b2).unwrap();
+        let expected_route = TreeRoute {
+            common_ancestor: genesis_hash,
+            retracted: vec![a1_hash],
+            enacted: vec![b1_hash, b2_hash],
+        };
+        // If b1 already won the tie, b2 simply extends the best chain
+        if first == ImportOutcome::NotBest {
+            assert_eq!(outcome, ImportOutcome::Reorganized(expected_route));
+        } else {
+            assert_eq!(outcome, ImportOutcome::Extended);
+        }
+
+        // The best state is b's: a's transfer is gone, both of b's are applied
+        let state = tree.best_state();
+        assert_eq!(tree.best_hash(), b2_hash);
+        assert_eq!(state.balances.balance(&"bob".to_string()), 0);
+        assert_eq!(state.balances.balance(&"charlie".to_string()), 50);
+        assert_eq!(state.system.current_block_hash(), Some(b2_hash));
+
+        let mut leaves = tree.leaves();
+        leaves.sort();
+        let mut expected = vec![a1_hash, b2_hash];
+        expected.sort();
+        assert_eq!(leaves, expected);
+    }
+
+    #[test]
+    fn equal_height_forks_are_decided_by_hash() {
+        let mut tree = BlockTree::new(genesis());
+        let a1 = author(&mut genesis(), "bob", 10);
+        let b1 = author(&mut genesis(), "charlie", 10);
+        let lowest = a1.header.hash().min(b1.header.hash());
+
+        tree.import(a1.clone()).unwrap();
+        tree.import(b1.clone()).unwrap();
+        assert_eq!(tree.best_hash(), lowest);
+
+        // The order the blocks arrive in makes no difference
+        let mut other = BlockTree::new(genesis());
+        other.import(b1).unwrap();
+        other.import(a1).unwrap();
+        assert_eq!(other.best_hash(), lowest);
+    }
+
+    #[test]
+    fn bad_imports_are_rejected() {
+        let mut tree = BlockTree::new(genesis());
+        let mut a = genesis();
+        let a1 = author(&mut a, "bob", 10);
+        let a2 = author(&mut a, "bob", 10);
+
+        assert_eq!(tree.import(a2), Err(ImportError::UnknownParent));
+        tree.import(a1.clone()).unwrap();
+        assert_eq!(tree.import(a1.clone()), Err(ImportError::AlreadyImported));
+
+        let mut forged = author(&mut genesis(), "charlie", 10);
+        forged.header.state_root = [0u8; 32];
+        assert!(matches!(tree.import(forged), Err(ImportError::Invalid(_))));
+        assert_eq!(tree.best_hash(), a1.header.hash());
+    }
+
+    #[test]
+    fn final_blocks_keep_no_state() {
+        let mut tree = BlockTree::new(genesis());
+        let mut a = genesis();
+        let fork = author(&mut genesis(), "charlie", 10);
+        let mut recent = None;
+        for number in 1..=FINALITY_DEPTH + 1 {
+            let block = author(&mut a, "bob", 10);
+            if number == 2 {
+                recent = Some(a.clone());
+            }
+            tree.import(block).unwrap();
+        }
+
+        // Block 1 is now final, so nothing can be built on genesis, but block 2 is not final yet
+        assert_eq!(tree.import(fork), Err(ImportError::FinalizedParent));
+        let other = author(recent.as_mut().unwrap(), "charlie", 10);
+        assert_eq!(tree.import(other), Ok(ImportOutcome::NotBest));
+
+        // The states in the tree do not keep the blocks again
+        assert_eq!(tree.best_state().balances.balance(&"bob".to_string()), 90);
+        assert!(tree.best_state().history.block_by_number(1).is_none());
+    }
+
+    #[test]
+    fn forks_cannot_revert_final_blocks() {
+        let mut tree = BlockTree::new(genesis());
+        let (mut a, mut b) = (genesis(), genesis());
+
+        // b's fork splits off at genesis and keeps up for a few blocks
+        let fork: Vec<types::Block> = (0..FINALITY_DEPTH + 3).map(|_| author(&mut b, "charlie", 10)).collect();
+        for block in &fork[..3] {
+            tree.import(block.clone()).unwrap();
+        }
+        for _ in 0..FINALITY_DEPTH + 2 {
+            tree.import(author(&mut a, "bob", 10)).unwrap();
+        }
+
+        // a's block 2 is final, and b's fork went with it even though its head is not that far behind
+        assert_eq!(tree.number(tree.finalized), 2);
+        assert_eq!(tree.leaves(), vec![tree.best_hash()]);
+        assert_eq!(tree.import(fork[3].clone()), Err(ImportError::UnknownParent));
+        assert_eq!(tree.best_state().balances.balance(&"bob".to_string()), 100);
+    }
+}
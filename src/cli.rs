@@ -0,0 +1,602 @@
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use crate::codec::{Decode, DecodeError, Encode};
+use crate::genesis::{GenesisConfig, GenesisError};
+use crate::rpc;
+use crate::store::{Store, StoreError};
+use crate::txpool::{PoolError, TransactionValidator};
+use crate::{balances, crypto, hex_encode, staking, support, types, vesting, Runtime, RuntimeCall};
+
+/// A node for the rust state machine that keeps its chain in a data directory
+#[derive(Debug, Parser)]
+#[command(name = "rust-class-state-machine")]
+pub struct Cli {
+    /// Directory the chain is saved in
+    #[arg(long, global = true, default_value = "chain-data")]
+    pub data_dir: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start a new chain from a genesis config
+    Init {
+        /// Genesis config file (.json or .toml) [default: the development accounts and validators]
+        #[arg(long)]
+        genesis: Option<PathBuf>,
+        /// Replace a chain already saved in the data directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// Sign a call with the sender's key and add it to the transaction pool
+    #[command(subcommand)]
+    Submit(SubmitCall),
+    /// Author the next block from the transactions in the pool
+    ProduceBlock,
+    /// Serve JSON-RPC requests, one JSON object per line over TCP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9933")]
+        listen: SocketAddr,
+        /// Also author a block from the pool every this many milliseconds
+        #[arg(long)]
+        block_time: Option<u64>,
+    },
+    /// Read the state of the chain
+    #[command(subcommand)]
+    Query(Query),
+    /// Print the runtime metadata as JSON: pallets, calls, events and errors
+    Metadata,
+    /// Run the scripted walkthrough on a fresh in-memory chain
+    Demo,
+}
+
+// Who signs a submitted call, with which key, and what they pay for it
+// The key must be given explicitly - the development keys are public, so they are only used when asked for
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("key").required(true).args(["seed", "key_file", "dev"])))]
+pub struct Signer {
+    /// Account that signs and pays for the transaction
+    #[arg(long)]
+    pub from: String,
+    /// Secret seed of the signing key, as 32 hex encoded bytes
+    #[arg(long)]
+    pub seed: Option<String>,
+    /// File holding the secret seed of the signing key, as 32 hex encoded bytes
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+    /// Sign with the sender's well-known development key, for development chains only
+    #[arg(long)]
+    pub dev: bool,
+    /// Nonce to sign with [default: the next one not used by the chain or the pool]
+    #[arg(long)]
+    pub nonce: Option<types::Nonce>,
+    /// Extra payment to the block author that raises the transaction's priority
+    #[arg(long, default_value_t = 0)]
+    pub tip: types::Balance,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SubmitCall {
+    /// Transfer funds to another account
+    Transfer {
+        #[command(flatten)]
+        signer: Signer,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: types::Balance,
+        /// Fail rather than leave the sender below the existential deposit
+        #[arg(long)]
+        keep_alive: bool,
+    },
+    /// Stake funds with a validator
+    Stake {
+        #[command(flatten)]
+        signer: Signer,
+        #[arg(long)]
+        validator: String,
+        #[arg(long)]
+        amount: types::Balance,
+    },
+    /// Withdraw everything staked once the unstaking period has passed
+    Unstake {
+        #[command(flatten)]
+        signer: Signer,
+    },
+    /// Claim the staking rewards earned so far
+    Claim {
+        #[command(flatten)]
+        signer: Signer,
+    },
+    /// Unlock the sender's funds that have vested so far
+    Vest {
+        #[command(flatten)]
+        signer: Signer,
+    },
+    /// Transfer funds that are released to the receiver a little every block
+    VestedTransfer {
+        #[command(flatten)]
+        signer: Signer,
+        #[arg(long)]
+        to: String,
+        /// Amount transferred, all of it locked to begin with
+        #[arg(long)]
+        locked: types::Balance,
+        /// Amount released every block
+        #[arg(long)]
+        per_block: types::Balance,
+        /// Block the release starts at [default: straight away]
+        #[arg(long, default_value_t = 0)]
+        starting_block: types::BlockNumber,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Query {
+    /// Free and reserved balance of an account
+    Balance { who: String },
+    /// Next nonce an account must use
+    Nonce { who: String },
+    /// Stake of an account
+    Stake { who: String },
+    /// A validator's stake and commission
+    Validator { who: String },
+    /// Vesting schedules of an account and how much they still lock
+    Vesting { who: String },
+    /// Header of a block [default: the latest block]
+    Block { number: Option<types::BlockNumber> },
+    /// Outcome of an extrinsic in a block
+    Receipt { block: types::BlockNumber, index: u32 },
+    /// Version of the runtime and the storage layout of each pallet
+    Version,
+    /// Total issuance, checked against every token held
+    Issuance,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Store(StoreError),             // The data directory could not be read or written
+    Genesis(GenesisError),         // The genesis config could not be read or built
+    NoChain(PathBuf),              // Nothing has been saved in the data directory yet
+    ChainExists(PathBuf),          // `init` would replace a saved chain
+    Pool(PoolError),               // The transaction pool refused a transaction
+    UnknownBlock(types::BlockNumber), // No block with this number has been produced
+    UnknownExtrinsic(types::BlockNumber, u32), // The block has fewer extrinsics than that
+    Rpc(std::io::Error),           // The RPC server could not listen or accept connections
+    Key(String),                   // The signing key could not be read
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Store(e) => write!(f, "{}", e),
+            CliError::Genesis(e) => write!(f, "{}", e),
+            CliError::NoChain(dir) => {
+                write!(f, "No chain in {} - run `init` first", dir.display())
+            }
+            CliError::ChainExists(dir) => {
+                write!(f, "A chain already exists in {} - pass --force to replace it", dir.display())
+            }
+            CliError::Pool(e) => write!(f, "{}", e),
+            CliError::UnknownBlock(number) => write!(f, "Block #{} has not been produced", number),
+            CliError::UnknownExtrinsic(number, index) => {
+                write!(f, "Block #{} has no extrinsic #{}", number, index)
+            }
+            CliError::Rpc(e) => write!(f, "RPC server failed: {}", e),
+            CliError::Key(reason) => write!(f, "Invalid signing key: {}", reason),
+        }
+    }
+}
+
+impl From<StoreError> for CliError {
+    fn from(error: StoreError) -> Self {
+        CliError::Store(error)
+    }
+}
+
+impl From<GenesisError> for CliError {
+    fn from(error: GenesisError) -> Self {
+        CliError::Genesis(error)
+    }
+}
+
+impl From<PoolError> for CliError {
+    fn from(error: PoolError) -> Self {
+        CliError::Pool(error)
+    }
+}
+
+impl Signer {
+    // The key pair to sign with, from whichever of --seed, --key-file and --dev was given
+    fn pair(&self) -> Result<crypto::Pair, CliError> {
+        if self.dev {
+            return Ok(crypto::Pair::dev(&self.from));
+        }
+        let seed = match (&self.seed, &self.key_file) {
+            (Some(seed), _) => seed.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|e| CliError::Key(format!("cannot read {}: {}", path.display(), e)))?,
+            (None, None) => return Err(CliError::Key("no --seed, --key-file or --dev given".to_string())),
+        };
+        let seed = crate::hex_decode(seed.trim())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| CliError::Key("the seed is not 32 hex encoded bytes".to_string()))?;
+        Ok(crypto::Pair::from_seed(seed))
+    }
+}
+
+// What the CLI keeps in the data directory between runs:
+// the chain, plus the transactions waiting in the pool for a block
+pub struct Node {
+    pub runtime: Runtime,
+    pub pool: types::TransactionPool,
+}
+
+// The pool is saved as its list of transactions and rebuilt by submitting them again
+impl Encode for Node {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.runtime.encode_to(dest);
+        self.pool.transactions().encode_to(dest);
+    }
+}
+
+impl Decode for Node {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let runtime = Runtime::decode(input)?;
+        let mut pool = types::TransactionPool::new();
+        for extrinsic in Vec::<types::Extrinsic>::decode(input)? {
+            // The chain has not changed since they were accepted, so they are accepted again
+            let _ = pool.submit(&runtime, extrinsic);
+        }
+        Ok(Node { runtime, pool })
+    }
+}
+
+impl Node {
+    // A node starting a new chain from `genesis`
+    fn new(genesis: &GenesisConfig) -> Result<Self, CliError> {
+        Ok(Node {
+            runtime: genesis.build()?,
+            pool: types::TransactionPool::new(),
+        })
+    }
+
+    fn load(store: &Store, data_dir: &Path) -> Result<Self, CliError> {
+        store.load()?.ok_or_else(|| CliError::NoChain(data_dir.to_path_buf()))
+    }
+
+    // The next nonce `who` can sign with: after the chain's and after any waiting in the pool
+    fn next_nonce(&self, who: &types::AccountId) -> types::Nonce {
+        self.pool
+            .transactions()
+            .iter()
+            .filter(|extrinsic| extrinsic.caller == *who)
+            .map(|extrinsic| extrinsic.nonce + 1)
+            .fold(self.runtime.account_nonce(who), types::Nonce::max)
+    }
+}
+
+// Run a command against the chain in the data directory
+pub fn run(cli: Cli) -> Result<(), CliError> {
+    // The demo runs in memory, so the data directory is only opened by the other commands
+    let open_store = || Store::open(&cli.data_dir);
+    match cli.command {
+        Command::Demo => crate::run_demo(),
+        Command::Metadata => {
+            let metadata = Runtime::metadata().to_json();
+            println!("{}", serde_json::to_string_pretty(&metadata).expect("metadata is valid JSON"));
+        }
+        Command::Init { genesis, force } => {
+            let store = open_store()?;
+            if !force && store.load::<Node>()?.is_some() {
+                return Err(CliError::ChainExists(cli.data_dir));
+            }
+            let genesis = match genesis {
+                Some(path) => GenesisConfig::from_file(&path)?,
+                None => GenesisConfig::development(),
+            };
+            let node = Node::new(&genesis)?;
+            store.save(&node)?;
+            println!(
+                "🌱 Chain initialized in {} (genesis hash: {})",
+                cli.data_dir.display(),
+                hex_encode(&node.runtime.genesis_hash())
+            );
+        }
+        Command::Submit(call) => {
+            let store = open_store()?;
+            let mut node = Node::load(&store, &cli.data_dir)?;
+            let (signer, call) = match call {
+                SubmitCall::Transfer { signer, to, amount, keep_alive: false } => {
+                    (signer, RuntimeCall::Balances(balances::Call::Transfer { to, amount }))
+                }
+                SubmitCall::Transfer { signer, to, amount, keep_alive: true } => {
+                    (signer, RuntimeCall::Balances(balances::Call::TransferKeepAlive { to, amount }))
+                }
+                SubmitCall::Stake { signer, validator, amount } => {
+                    (signer, RuntimeCall::Staking(staking::Call::Stake { validator, amount }))
+                }
+                SubmitCall::Unstake { signer } => (signer, RuntimeCall::Staking(staking::Call::Unstake)),
+                SubmitCall::Claim { signer } => {
+                    (signer, RuntimeCall::Staking(staking::Call::ClaimRewards))
+                }
+                SubmitCall::Vest { signer } => (signer, RuntimeCall::Vesting(vesting::Call::Vest)),
+                SubmitCall::VestedTransfer { signer, to, locked, per_block, starting_block } => {
+                    let schedule = vesting::VestingInfo { locked, per_block, starting_block };
+                    (signer, RuntimeCall::Vesting(vesting::Call::VestedTransfer { target: to, schedule }))
+                }
+            };
+
+            let pair = signer.pair()?;
+            let nonce = signer.nonce.unwrap_or_else(|| node.next_nonce(&signer.from));
+            let extrinsic = support::Extrinsic::new_signed_with_tip(
+                signer.from.clone(),
+                call,
+                nonce,
+                signer.tip,
+                &pair,
+                node.runtime.genesis_hash(),
+            );
+            let status = node.pool.submit(&node.runtime, extrinsic)?;
+            store.save(&node)?;
+            println!("📥 Transaction from {} with nonce {} is {:?}", signer.from, nonce, status);
+        }
+        Command::ProduceBlock => {
+            let store = open_store()?;
+            let mut node = Node::load(&store, &cli.data_dir)?;
+            let block = node.runtime.author_block_from_pool(&mut node.pool);
+            store.save(&node)?;
+            node.runtime.print_events();
+            println!(
+                "📦 Block #{} produced with {} extrinsics, {} transactions left in the pool",
+                block.header.block_number,
+                block.extrinsics.len(),
+                node.pool.len()
+            );
+            println!("🔗 Block Hash: {}", hex_encode(&block.header.hash()));
+        }
+        Command::Serve { listen, block_time } => {
+            let store = open_store()?;
+            let node = Node::load(&store, &cli.data_dir)?;
+            let listener = TcpListener::bind(listen).map_err(CliError::Rpc)?;
+            println!("🛰️  Serving JSON-RPC on {}", listen);
+            rpc::serve(listener, node, store, block_time.map(Duration::from_millis)).map_err(CliError::Rpc)?;
+        }
+        Command::Query(query) => {
+            let node = Node::load(&open_store()?, &cli.data_dir)?;
+            run_query(&node.runtime, query)?;
+        }
+    }
+    Ok(())
+}
+
+// Print the answer to a query - single values are printed bare so scripts can read them
+fn run_query(runtime: &Runtime, query: Query) -> Result<(), CliError> {
+    match query {
+        Query::Balance { who } => {
+            println!("free: {}", runtime.balances.balance(&who));
+            println!("reserved: {}", runtime.balances.reserved_balance(&who));
+            println!("locked: {}", runtime.balances.locked_balance(&who, balances::Reasons::Misc));
+        }
+        Query::Nonce { who } => println!("{}", runtime.system.account_nonce(&who)),
+        Query::Stake { who } => match runtime.staking.get_stake_info(&who) {
+            Some(info) => {
+                println!("staked: {}", info.staked_amount);
+                println!("validator: {}", info.validator);
+                println!("since block: {}", info.stake_block);
+                println!("rewards claimed: {}", info.total_rewards);
+                println!("rewards pending: {}", runtime.staking.calculate_rewards(&who).unwrap_or(0));
+            }
+            None => println!("{} is not staking", who),
+        },
+        Query::Validator { who } => match runtime.staking.get_validator_info(&who) {
+            Some(info) => {
+                println!("total stake: {}", info.total_stake);
+                println!("commission: {}%", info.commission_rate);
+                println!("active: {}", info.is_active);
+                println!("nominators: {}", info.nominators_count);
+            }
+            None => println!("{} is not a validator", who),
+        },
+        Query::Vesting { who } => {
+            let schedules = runtime.vesting.schedules(&who);
+            if schedules.is_empty() {
+                println!("{} is not vesting", who);
+            }
+            for schedule in schedules {
+                println!(
+                    "schedule: {} locked, {} per block from block {}",
+                    schedule.locked, schedule.per_block, schedule.starting_block
+                );
+            }
+            if !schedules.is_empty() {
+                println!("still locked: {}", runtime.vesting.vesting_balance(&who, runtime.system.block_number()));
+            }
+        }
+        Query::Block { number } => {
+            let number = number.unwrap_or(runtime.system.block_number());
+            let header = runtime
+                .system
+                .get_header(number)
+                .ok_or(CliError::UnknownBlock(number))?;
+            println!("number: {}", header.block_number);
+            println!("hash: {}", hex_encode(&header.hash()));
+            println!("parent hash: {}", hex_encode(&header.parent_hash));
+            println!("state root: {}", hex_encode(&header.state_root));
+            println!("extrinsics root: {}", hex_encode(&header.extrinsics_root));
+            println!("timestamp: {}", header.timestamp);
+            if let Some(block) = runtime.history.block_by_number(number) {
+                println!("extrinsics: {}", block.extrinsics.len());
+            }
+        }
+        Query::Version => {
+            let version = Runtime::version();
+            println!("spec: {} v{}", version.spec_name, version.spec_version);
+            for (pallet, storage_version) in version.storage_versions {
+                println!("{} storage: v{}", pallet, storage_version);
+            }
+        }
+        Query::Issuance => {
+            println!("{}", runtime.balances.total_issuance());
+            if let Err(e) = runtime.check_issuance() {
+                println!("⚠️  {}", e);
+            }
+        }
+        Query::Receipt { block, index } => {
+            let receipt = runtime
+                .history
+                .receipt(block, index)
+                .ok_or(CliError::UnknownExtrinsic(block, index))?;
+            println!("extrinsic hash: {}", hex_encode(&receipt.extrinsic_hash));
+            match &receipt.result {
+                Ok(()) => println!("result: success"),
+                Err(e) => println!("result: failed - {}", e),
+            }
+            println!("fee paid: {}", receipt.fee_paid);
+            println!("events:");
+            for event in &receipt.events {
+                println!("  {:?}", event);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_in(dir: &std::path::Path, args: &[&str]) -> Result<(), CliError> {
+        let mut argv = vec!["node", "--data-dir", dir.to_str().unwrap()];
+        argv.extend_from_slice(args);
+        run(Cli::try_parse_from(argv).unwrap())
+    }
+
+    fn load(dir: &std::path::Path) -> Node {
+        Store::open(dir).unwrap().load().unwrap().unwrap()
+    }
+
+    #[test]
+    fn commands_drive_a_saved_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(run_in(dir.path(), &["query", "balance", "cheryl"]), Err(CliError::NoChain(_))));
+
+        run_in(dir.path(), &["init"]).unwrap();
+        assert!(matches!(run_in(dir.path(), &["init"]), Err(CliError::ChainExists(_))));
+
+        // Two transfers from the same account pick consecutive nonces
+        let transfer = ["submit", "transfer", "--dev", "--from", "cheryl", "--to", "faith", "--amount", "25"];
+        run_in(dir.path(), &transfer).unwrap();
+        run_in(dir.path(), &transfer).unwrap();
+        run_in(
+            dir.path(),
+            &["submit", "stake", "--dev", "--from", "temi", "--validator", "nathaniel", "--amount", "150"],
+        )
+        .unwrap();
+        assert_eq!(load(dir.path()).pool.len(), 3);
+
+        run_in(dir.path(), &["produce-block"]).unwrap();
+        let node = load(dir.path());
+        assert!(node.pool.is_empty());
+        assert_eq!(node.runtime.system.block_number(), 1);
+        assert_eq!(node.runtime.balances.balance(&"faith".to_string()), 150);
+        assert_eq!(node.runtime.system.account_nonce(&"cheryl".to_string()), 2);
+        assert_eq!(node.runtime.staking.get_stake_info(&"temi".to_string()).unwrap().staked_amount, 150);
+        assert_eq!(node.runtime.balances.balance(&"temi".to_string()), 300);
+        assert_eq!(node.runtime.balances.usable_balance(&"temi".to_string()), 150);
+
+        run_in(dir.path(), &["query", "block", "1"]).unwrap();
+        assert!(matches!(run_in(dir.path(), &["query", "block", "2"]), Err(CliError::UnknownBlock(2))));
+        run_in(dir.path(), &["query", "receipt", "1", "2"]).unwrap();
+        assert!(matches!(
+            run_in(dir.path(), &["query", "receipt", "1", "3"]),
+            Err(CliError::UnknownExtrinsic(1, 3))
+        ));
+
+        // Vested funds arrive locked and are released block by block
+        let vested_transfer = [
+            "submit", "vested-transfer", "--dev", "--from", "cheryl", "--to", "faith", "--locked", "100", "--per-block", "40",
+            "--starting-block", "3",
+        ];
+        run_in(dir.path(), &vested_transfer).unwrap();
+        run_in(dir.path(), &["produce-block"]).unwrap();
+        run_in(dir.path(), &["produce-block"]).unwrap();
+        run_in(dir.path(), &["submit", "vest", "--dev", "--from", "faith"]).unwrap();
+        run_in(dir.path(), &["produce-block"]).unwrap();
+        run_in(dir.path(), &["query", "vesting", "faith"]).unwrap();
+        let node = load(dir.path());
+        assert_eq!(node.runtime.balances.balance(&"faith".to_string()), 250);
+        assert_eq!(node.runtime.balances.usable_balance(&"faith".to_string()), 190);
+    }
+
+    #[test]
+    fn init_reads_a_genesis_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let genesis = dir.path().join("genesis.toml");
+        let public_key = hex_encode(&crypto::Pair::dev("alice").public());
+        std::fs::write(
+            &genesis,
+            format!("[[accounts]]\nname = \"alice\"\nbalance = 42\npublic_key = \"{}\"\n", public_key),
+        )
+        .unwrap();
+        let data_dir = dir.path().join("chain");
+
+        run_in(&data_dir, &["init", "--genesis", genesis.to_str().unwrap()]).unwrap();
+        let node = load(&data_dir);
+        assert_eq!(node.runtime.balances.balance(&"alice".to_string()), 42);
+        assert_eq!(node.runtime.system.block_number(), 0);
+
+        let missing = dir.path().join("missing.json");
+        assert!(matches!(
+            run_in(&data_dir, &["init", "--force", "--genesis", missing.to_str().unwrap()]),
+            Err(CliError::Genesis(GenesisError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn refused_transactions_are_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        run_in(dir.path(), &["init"]).unwrap();
+
+        // faith cannot pay a tip larger than faith's balance
+        let result = run_in(
+            dir.path(),
+            &["submit", "transfer", "--dev", "--from", "faith", "--to", "temi", "--amount", "1", "--tip", "1000"],
+        );
+        assert!(matches!(result, Err(CliError::Pool(_))));
+        assert!(load(dir.path()).pool.is_empty());
+    }
+
+    #[test]
+    fn submit_signs_with_the_given_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = hex_encode(&[1u8; 32]);
+        let public_key = hex_encode(&crypto::Pair::from_seed([1u8; 32]).public());
+        let genesis = dir.path().join("genesis.toml");
+        std::fs::write(
+            &genesis,
+            format!("[[accounts]]\nname = \"alice\"\nbalance = 100\npublic_key = \"{}\"\n", public_key),
+        )
+        .unwrap();
+        let key_file = dir.path().join("alice.key");
+        std::fs::write(&key_file, format!("{}\n", seed)).unwrap();
+        let data_dir = dir.path().join("chain");
+        run_in(&data_dir, &["init", "--genesis", genesis.to_str().unwrap()]).unwrap();
+
+        let transfer = ["submit", "transfer", "--from", "alice", "--to", "bob", "--amount", "10"];
+
+        // A key is required, and alice's development key is not hers
+        assert!(Cli::try_parse_from([&["node"][..], &transfer[..]].concat()).is_err());
+        assert!(matches!(run_in(&data_dir, &[&transfer[..], &["--dev"]].concat()), Err(CliError::Pool(_))));
+        assert!(matches!(run_in(&data_dir, &[&transfer[..], &["--seed", "00"]].concat()), Err(CliError::Key(_))));
+
+        run_in(&data_dir, &[&transfer[..], &["--seed", &seed]].concat()).unwrap();
+        run_in(&data_dir, &[&transfer[..], &["--key-file", key_file.to_str().unwrap()]].concat()).unwrap();
+        assert_eq!(load(&data_dir).pool.len(), 2);
+    }
+}
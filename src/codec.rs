@@ -0,0 +1,432 @@
+use std::collections::BTreeMap;
+
+// A small deterministic binary encoding for the types that end up in a block.
+// Hashing needs the same value to always produce the same bytes, so every type
+// that feeds into a block hash implements `Encode`. Anything that is stored or
+// shipped between processes also implements `Decode` to read those bytes back.
+//
+// Encoding rules (modelled on SCALE, the codec used by Substrate):
+// - Integers are written as fixed-width little-endian bytes
+// - `Compact` integers use 1, 2 or 4 bytes for small values (see below)
+// - Strings and vectors are prefixed with their length as a compact integer
+// - Maps are written like a vector of (key, value) pairs in key order
+// - Enums are written as a one-byte variant index followed by the variant's fields
+// - Fixed-size byte arrays (hashes) are written as-is
+pub trait Encode {
+    // Append the encoded form of `self` to `dest`
+    fn encode_to(&self, dest: &mut Vec<u8>);
+
+    // Encode `self` into a fresh byte vector
+    fn encode(&self) -> Vec<u8> {
+        let mut dest = Vec::new();
+        self.encode_to(&mut dest);
+        dest
+    }
+}
+
+// Why a byte string could not be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd,             // The input ran out in the middle of a value
+    InvalidVariant(u8),        // An enum index that no variant uses
+    InvalidUtf8,               // A string that is not valid UTF-8
+    NonCanonicalCompact,       // A compact integer not written in its shortest form
+    Overflow,                  // A compact integer too large for the target type
+    TrailingBytes(usize),      // Bytes left over after decoding a complete value
+    Invalid(String),           // Well-formed bytes describing a value that cannot be used
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            DecodeError::InvalidVariant(index) => write!(f, "Invalid enum variant index {}", index),
+            DecodeError::InvalidUtf8 => write!(f, "String is not valid UTF-8"),
+            DecodeError::NonCanonicalCompact => write!(f, "Compact integer is not in canonical form"),
+            DecodeError::Overflow => write!(f, "Compact integer does not fit the target type"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} bytes left after decoding", count),
+            DecodeError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+pub trait Decode: Sized {
+    // Read a value from the front of `input`, advancing it past the bytes used
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    // Decode a value that must use up all of `bytes`
+    fn decode_all(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(bytes.len()));
+        }
+        Ok(value)
+    }
+}
+
+// Take the next `len` bytes off the front of `input`
+pub fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+// Read an enum variant index
+pub fn read_variant(input: &mut &[u8]) -> Result<u8, DecodeError> {
+    u8::decode(input)
+}
+
+// Integers are encoded as little-endian bytes of their full width
+macro_rules! impl_codec_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode_to(&self, dest: &mut Vec<u8>) {
+                    dest.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let bytes = read_bytes(input, std::mem::size_of::<$t>())?;
+                    let mut array = [0u8; std::mem::size_of::<$t>()];
+                    array.copy_from_slice(bytes);
+                    Ok(<$t>::from_le_bytes(array))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_int!(u8, u16, u32, u64, u128);
+
+// A compact integer - small values take fewer bytes
+// The two low bits of the first byte select the mode:
+// - 0b00: the value is in the upper six bits (values below 2^6)
+// - 0b01: two bytes, the value in the upper 14 bits (values below 2^14)
+// - 0b10: four bytes, the value in the upper 30 bits (values below 2^30)
+// - 0b11: the upper six bits hold the number of value bytes minus four,
+//         followed by the value in that many little-endian bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compact<T>(pub T);
+
+fn encode_compact(value: u128, dest: &mut Vec<u8>) {
+    if value < 1 << 6 {
+        dest.push((value as u8) << 2);
+    } else if value < 1 << 14 {
+        dest.extend_from_slice(&(((value as u16) << 2) | 0b01).to_le_bytes());
+    } else if value < 1 << 30 {
+        dest.extend_from_slice(&(((value as u32) << 2) | 0b10).to_le_bytes());
+    } else {
+        let bytes = value.to_le_bytes();
+        let len = bytes.len() - bytes.iter().rev().take_while(|byte| **byte == 0).count();
+        dest.push((((len - 4) as u8) << 2) | 0b11);
+        dest.extend_from_slice(&bytes[..len]);
+    }
+}
+
+fn decode_compact(input: &mut &[u8]) -> Result<u128, DecodeError> {
+    let first = u8::decode(input)?;
+    let (value, min) = match first & 0b11 {
+        0b00 => return Ok((first >> 2) as u128),
+        0b01 => {
+            let rest = u8::decode(input)?;
+            ((u16::from_le_bytes([first, rest]) >> 2) as u128, 1 << 6)
+        }
+        0b10 => {
+            let rest = read_bytes(input, 3)?;
+            let value = u32::from_le_bytes([first, rest[0], rest[1], rest[2]]) >> 2;
+            (value as u128, 1 << 14)
+        }
+        _ => {
+            let len = (first >> 2) as usize + 4;
+            if len > 16 {
+                return Err(DecodeError::Overflow);
+            }
+            let mut array = [0u8; 16];
+            array[..len].copy_from_slice(read_bytes(input, len)?);
+            if array[len - 1] == 0 {
+                return Err(DecodeError::NonCanonicalCompact);
+            }
+            (u128::from_le_bytes(array), 1 << 30)
+        }
+    };
+    // Every value has exactly one encoding, so the shortest mode must be used
+    if value < min {
+        return Err(DecodeError::NonCanonicalCompact);
+    }
+    Ok(value)
+}
+
+macro_rules! impl_codec_for_compact {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for Compact<$t> {
+                fn encode_to(&self, dest: &mut Vec<u8>) {
+                    encode_compact(self.0 as u128, dest);
+                }
+            }
+
+            impl Decode for Compact<$t> {
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let value = decode_compact(input)?;
+                    <$t>::try_from(value).map(Compact).map_err(|_| DecodeError::Overflow)
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_compact!(u8, u16, u32, u64, u128);
+
+// Lengths of strings, vectors and maps are written as compact integers
+fn encode_len(len: usize, dest: &mut Vec<u8>) {
+    Compact(len as u64).encode_to(dest);
+}
+
+fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    let Compact(len) = Compact::<u64>::decode(input)?;
+    usize::try_from(len).map_err(|_| DecodeError::Overflow)
+}
+
+// The unit type encodes to nothing - used for storage items without a map key
+impl Encode for () {
+    fn encode_to(&self, _dest: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode(_input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(())
+    }
+}
+
+impl Encode for bool {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        encode_len(self.len(), dest);
+        dest.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_str().encode_to(dest);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = read_bytes(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(read_bytes(input, N)?);
+        Ok(array)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        encode_len(self.len(), dest);
+        for item in self {
+            item.encode_to(dest);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_slice().encode_to(dest);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        // Every item takes at least a byte, so don't trust a length the input can't back
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            None => dest.push(0),
+            Some(value) => {
+                dest.push(1);
+                value.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl<T: Encode, E: Encode> Encode for Result<T, E> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                dest.push(0);
+                value.encode_to(dest);
+            }
+            Err(error) => {
+                dest.push(1);
+                error.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Decode, E: Decode> Decode for Result<T, E> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(Ok(T::decode(input)?)),
+            1 => Ok(Err(E::decode(input)?)),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        encode_len(self.len(), dest);
+        for (key, value) in self {
+            key.encode_to(dest);
+            value.encode_to(dest);
+        }
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::decode(input)?;
+            let value = V::decode(input)?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest);
+        self.1.encode_to(dest);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_little_endian() {
+        assert_eq!(1u32.encode(), vec![1, 0, 0, 0]);
+        assert_eq!(258u16.encode(), vec![2, 1]);
+        assert_eq!(u32::decode_all(&[1, 0, 0, 0]), Ok(1));
+    }
+
+    #[test]
+    fn strings_and_vectors_are_length_prefixed() {
+        assert_eq!("ab".to_string().encode(), vec![8, b'a', b'b']);
+        assert_eq!(vec![7u8, 8u8].encode(), vec![8, 7, 8]);
+        // A length prefix keeps ("ab", "c") and ("a", "bc") apart
+        assert_ne!(
+            ("ab".to_string(), "c".to_string()).encode(),
+            ("a".to_string(), "bc".to_string()).encode()
+        );
+    }
+
+    #[test]
+    fn compact_integers_use_the_shortest_mode() {
+        let cases: [(u128, Vec<u8>); 6] = [
+            (0, vec![0x00]),
+            (63, vec![0xfc]),
+            (64, vec![0x01, 0x01]),
+            (16383, vec![0xfd, 0xff]),
+            (16384, vec![0x02, 0x00, 0x01, 0x00]),
+            (1 << 30, vec![0x03, 0x00, 0x00, 0x00, 0x40]),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(Compact(value).encode(), bytes);
+            assert_eq!(Compact::<u128>::decode_all(&bytes), Ok(Compact(value)));
+        }
+        assert_eq!(
+            Compact::<u128>::decode_all(&Compact(u128::MAX).encode()),
+            Ok(Compact(u128::MAX))
+        );
+
+        // 1 written in the two-byte mode is rejected
+        assert_eq!(Compact::<u32>::decode_all(&[0x05, 0x00]), Err(DecodeError::NonCanonicalCompact));
+        assert_eq!(Compact::<u8>::decode_all(&[0x01, 0x04]), Err(DecodeError::Overflow));
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let mut map = BTreeMap::new();
+        map.insert("alice".to_string(), (7u128, Some(true)));
+        map.insert("bob".to_string(), (0u128, None));
+        let value = (map, vec![[9u8; 4]; 70]);
+
+        assert_eq!(Decode::decode_all(&value.encode()), Ok(value));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert_eq!(u32::decode_all(&[1, 0]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(bool::decode_all(&[2]), Err(DecodeError::InvalidVariant(2)));
+        assert_eq!(String::decode_all(&[4, 0xff]), Err(DecodeError::InvalidUtf8));
+        assert_eq!(u8::decode_all(&[1, 2]), Err(DecodeError::TrailingBytes(1)));
+        // A length far beyond the input fails without allocating for it
+        assert_eq!(Vec::<u8>::decode_all(&[0xfe, 0xff, 0xff, 0xff]), Err(DecodeError::UnexpectedEnd));
+    }
+}
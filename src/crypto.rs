@@ -0,0 +1,65 @@
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+
+// Public keys and signatures are passed around as plain byte arrays
+// so they can be encoded and stored like any other value
+pub type PublicKey = [u8; 32];
+pub type Signature = [u8; 64];
+
+// An ed25519 key pair used to sign extrinsics
+#[derive(Debug, Clone)]
+pub struct Pair {
+    signing_key: SigningKey,
+}
+
+impl Pair {
+    // Create a key pair from a 32-byte secret seed
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            signing_key: SigningKey::from_bytes(&seed),
+        }
+    }
+
+    // Well-known development key pair for a named account (like Substrate's //Alice)
+    // The seed is public, so these keys must never hold real funds
+    pub fn dev(name: &str) -> Self {
+        Self::from_seed(crate::support::blake2_256(format!("//{}", name).as_bytes()))
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.signing_key.sign(message).to_bytes()
+    }
+}
+
+// Check that `signature` is a valid signature of `message` by `public`
+pub fn verify(public: &PublicKey, message: &[u8], signature: &Signature) -> bool {
+    let Ok(verifying_key) = VerifyingKey::from_bytes(public) else {
+        return false;
+    };
+    let signature = ed25519_dalek::Signature::from_bytes(signature);
+    verifying_key.verify(message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let alice = Pair::dev("alice");
+        let signature = alice.sign(b"hello");
+
+        assert!(verify(&alice.public(), b"hello", &signature));
+        assert!(!verify(&alice.public(), b"hello!", &signature));
+        assert!(!verify(&Pair::dev("bob").public(), b"hello", &signature));
+    }
+
+    #[test]
+    fn dev_keys_are_deterministic() {
+        assert_eq!(Pair::dev("alice").public(), Pair::dev("alice").public());
+        assert_ne!(Pair::dev("alice").public(), Pair::dev("bob").public());
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use serde::{Deserialize, Deserializer};
+use crate::crypto::{self, PublicKey};
+use crate::types::{AccountId, Balance, BlockNumber};
+use crate::{balances, hex_decode, hex_encode, history, staking, system, vesting, Runtime, MAX_BLOCK_WEIGHT};
+
+// The initial state of a chain, committed to in block 0
+// Every field may be left out of a config file, which leaves that part of the state empty
+// (or, for the staking parameters, at their defaults)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenesisConfig {
+    pub accounts: Vec<GenesisAccount>,
+    pub fees: FeeConfig,
+    // Smallest balance an account may hold; every funded account must hold at least this
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub existential_deposit: Balance,
+    pub staking: StakingConfig,
+    pub vesting: Vec<GenesisVesting>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisAccount {
+    pub name: AccountId,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub balance: Balance,
+    // Hex encoded ed25519 key allowed to sign for the account
+    // Required, so that no account of a chain read from a file is left with a public development key
+    pub public_key: String,
+}
+
+impl GenesisAccount {
+    // An account signed for by its well-known development key, for development chains only
+    pub fn development(name: &str, balance: Balance) -> Self {
+        Self {
+            name: name.to_string(),
+            balance,
+            public_key: hex_encode(&crypto::Pair::dev(name).public()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    // Fee of a transfer, which the runtime's fee strategy may scale with its amount
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub base_fee: Balance,
+    pub recipient: Option<AccountId>, // Without one, fees are burned
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StakingConfig {
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub minimum_stake: Balance,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub reward_rate: Balance, // Rewards per block per 1000 tokens staked
+    pub unstaking_period: BlockNumber,
+    pub max_validators: u32,
+    pub validators: Vec<GenesisValidator>,
+    pub stakes: Vec<GenesisStake>,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            minimum_stake: 100,
+            reward_rate: 5,
+            unstaking_period: 10,
+            max_validators: 10,
+            validators: Vec::new(),
+            stakes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisValidator {
+    pub account: AccountId,
+    pub commission: u8, // Percentage of rewards kept by the validator
+}
+
+// Stakes are bonded in place: the amount stays in the staker's genesis balance under the staking lock
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisStake {
+    pub who: AccountId,
+    pub validator: AccountId,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub amount: Balance,
+}
+
+// Part of an account's genesis balance released over time: nothing until `starting_block`,
+// then `per_block` every block until all of `locked` is free
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisVesting {
+    pub who: AccountId,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub locked: Balance,
+    #[serde(deserialize_with = "deserialize_balance")]
+    pub per_block: Balance,
+    #[serde(default)]
+    pub starting_block: BlockNumber,
+}
+
+#[derive(Debug)]
+pub enum GenesisError {
+    Io(std::io::Error),        // The config file could not be read
+    UnsupportedFormat,         // The config file is neither .json nor .toml
+    Parse(String),             // The config file does not describe a genesis config
+    Invalid(String),           // The config describes a state that cannot be built
+}
+
+impl std::fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenesisError::Io(e) => write!(f, "Could not read genesis config: {}", e),
+            GenesisError::UnsupportedFormat => {
+                write!(f, "Genesis config must be a .json or .toml file")
+            }
+            GenesisError::Parse(e) => write!(f, "Invalid genesis config: {}", e),
+            GenesisError::Invalid(e) => write!(f, "Cannot build genesis state: {}", e),
+        }
+    }
+}
+
+impl GenesisConfig {
+    // Read a config from a JSON or TOML file, chosen by the file extension
+    pub fn from_file(path: &Path) -> Result<Self, GenesisError> {
+        let contents = std::fs::read_to_string(path).map_err(GenesisError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            Some("toml") => Self::from_toml(&contents),
+            _ => Err(GenesisError::UnsupportedFormat),
+        }
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, GenesisError> {
+        serde_json::from_str(contents).map_err(|e| GenesisError::Parse(e.to_string()))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, GenesisError> {
+        toml::from_str(contents).map_err(|e| GenesisError::Parse(e.to_string()))
+    }
+
+    // Funded development accounts and two validators, for local testing
+    pub fn development() -> Self {
+        let accounts = [
+            ("cheryl", 10_000),
+            ("Femi", 500),
+            ("temi", 300),
+            ("nathaniel", 200),
+            ("faith", 100),
+        ];
+        Self {
+            accounts: accounts
+                .into_iter()
+                .map(|(name, balance)| GenesisAccount::development(name, balance))
+                .collect(),
+            fees: FeeConfig::default(),
+            existential_deposit: 0,
+            staking: StakingConfig {
+                validators: vec![
+                    GenesisValidator { account: "cheryl".to_string(), commission: 5 },
+                    GenesisValidator { account: "nathaniel".to_string(), commission: 10 },
+                ],
+                ..StakingConfig::default()
+            },
+            vesting: Vec::new(),
+        }
+    }
+
+    // Build the runtime described by this config and finalize block 0 over its state
+    pub fn build(&self) -> Result<Runtime, GenesisError> {
+        let staking = &self.staking;
+        let mut runtime = Runtime {
+            system: system::Pallet::new(),
+            balances: balances::Pallet::new_with_fee_config(self.fees.base_fee, self.fees.recipient.clone()),
+            staking: staking::Pallet::new_with_config(
+                staking.minimum_stake,
+                staking.reward_rate,
+                staking.unstaking_period,
+                staking.max_validators,
+            ),
+            vesting: vesting::Pallet::new(),
+            history: history::BlockHistory::new(),
+            max_block_weight: MAX_BLOCK_WEIGHT,
+        };
+        runtime.balances.set_existential_deposit(self.existential_deposit);
+
+        let mut names = BTreeSet::new();
+        for account in &self.accounts {
+            if !names.insert(&account.name) {
+                return Err(GenesisError::Invalid(format!("account {} is listed twice", account.name)));
+            }
+            let key = parse_public_key(&account.public_key).ok_or_else(|| {
+                GenesisError::Invalid(format!("public key of {} is not 32 hex encoded bytes", account.name))
+            })?;
+            runtime.system.set_account_key(&account.name, key);
+            // Genesis balances are the chain's first tokens
+            // An account without funds only registers its key
+            runtime
+                .balances
+                .mint(&account.name, account.balance)
+                .map_err(|e| GenesisError::Invalid(format!("balance of {}: {}", account.name, e)))?;
+        }
+
+        for validator in &staking.validators {
+            runtime
+                .staking
+                .add_validator(validator.account.clone(), validator.commission)
+                .into_std_result()
+                .map_err(|e| GenesisError::Invalid(format!("validator {}: {}", validator.account, e)))?;
+        }
+
+        for stake in &staking.stakes {
+            runtime
+                .stake(stake.who.clone(), stake.amount, stake.validator.clone())
+                .map_err(|e| GenesisError::Invalid(format!("stake of {}: {}", stake.who, e)))?;
+        }
+
+        for schedule in &self.vesting {
+            if runtime.balances.balance(&schedule.who) < schedule.locked {
+                return Err(GenesisError::Invalid(format!("{} cannot vest more than their balance", schedule.who)));
+            }
+            let info = vesting::VestingInfo {
+                locked: schedule.locked,
+                per_block: schedule.per_block,
+                starting_block: schedule.starting_block,
+            };
+            runtime
+                .vesting
+                .add_vesting_schedule(&schedule.who, info)
+                .map_err(|e| GenesisError::Invalid(format!("vesting of {}: {}", schedule.who, e)))?;
+            runtime
+                .vest(schedule.who.clone())
+                .map_err(|e| GenesisError::Invalid(format!("vesting of {}: {}", schedule.who, e)))?;
+        }
+
+        runtime.seal_block();
+        Ok(runtime)
+    }
+}
+
+// TOML integers stop at i64, so a balance may also be written as a string of digits
+fn deserialize_balance<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Balance, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Number(u64),
+        Digits(String),
+    }
+
+    match Amount::deserialize(deserializer)? {
+        Amount::Number(amount) => Ok(amount.into()),
+        Amount::Digits(digits) => digits.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+fn parse_public_key(hex: &str) -> Option<PublicKey> {
+    hex_decode(hex)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // alice and bob sign with their development keys
+    const JSON: &str = r#"{
+        "accounts": [
+            {
+                "name": "alice",
+                "balance": 1000,
+                "public_key": "fc51a3dd7f0281b770c4f7a357e5b424c059e1eaf17923ca130613fcd7d576a0"
+            },
+            {
+                "name": "bob",
+                "balance": 500,
+                "public_key": "8a8d315a40a0a8f9c418f595992d7846dc605db384777be0c1ed23881a76b2dc"
+            }
+        ],
+        "fees": { "base_fee": 2, "recipient": "alice" },
+        "staking": {
+            "minimum_stake": 50,
+            "validators": [{ "account": "alice", "commission": 7 }],
+            "stakes": [{ "who": "bob", "validator": "alice", "amount": 200 }]
+        },
+        "vesting": [{ "who": "alice", "locked": 400, "per_block": 100, "starting_block": 2 }]
+    }"#;
+
+    const TOML: &str = r#"
+        [[accounts]]
+        name = "alice"
+        balance = 1000
+        public_key = "fc51a3dd7f0281b770c4f7a357e5b424c059e1eaf17923ca130613fcd7d576a0"
+
+        [[accounts]]
+        name = "bob"
+        balance = "500"
+        public_key = "8a8d315a40a0a8f9c418f595992d7846dc605db384777be0c1ed23881a76b2dc"
+
+        [fees]
+        base_fee = 2
+        recipient = "alice"
+
+        [staking]
+        minimum_stake = 50
+        validators = [{ account = "alice", commission = 7 }]
+        stakes = [{ who = "bob", validator = "alice", amount = 200 }]
+
+        [[vesting]]
+        who = "alice"
+        locked = 400
+        per_block = 100
+        starting_block = 2
+    "#;
+
+    #[test]
+    fn json_and_toml_describe_the_same_config() {
+        let config = GenesisConfig::from_json(JSON).unwrap();
+        assert_eq!(GenesisConfig::from_toml(TOML).unwrap(), config);
+        assert_eq!(config.staking.minimum_stake, 50);
+        // Parameters left out keep their defaults
+        assert_eq!(config.staking.unstaking_period, 10);
+
+        assert!(matches!(
+            GenesisConfig::from_json(r#"{ "acounts": [] }"#),
+            Err(GenesisError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn genesis_state_is_committed_to_in_block_zero() {
+        let runtime = GenesisConfig::from_json(JSON).unwrap().build().unwrap();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        assert_eq!(runtime.system.block_number(), 0);
+        assert_eq!(runtime.balances.balance(&alice), 1000);
+        assert_eq!(runtime.balances.balance(&bob), 500);
+        assert_eq!(runtime.balances.usable_balance(&bob), 300);
+        assert_eq!(runtime.balances.usable_balance(&alice), 600);
+        assert_eq!(runtime.vesting.vesting_balance(&alice, 3), 300);
+        assert_eq!(runtime.balances.get_transaction_fee(), 2);
+        assert_eq!(runtime.staking.get_validator_info(&alice).unwrap().commission_rate, 7);
+        assert_eq!(runtime.staking.get_stake_info(&bob).unwrap().staked_amount, 200);
+        assert_eq!(runtime.system.account_key(&bob), Some(crypto::Pair::dev("bob").public()));
+
+        let header = runtime.system.get_header(0).unwrap();
+        assert_eq!(header.state_root, runtime.state_root());
+        assert_eq!(runtime.genesis_hash(), header.hash());
+
+        // The same config always gives the same chain, a different one a different chain
+        let again = GenesisConfig::from_toml(TOML).unwrap().build().unwrap();
+        assert_eq!(again.genesis_hash(), runtime.genesis_hash());
+        assert_ne!(GenesisConfig::default().build().unwrap().genesis_hash(), runtime.genesis_hash());
+    }
+
+    #[test]
+    fn accounts_can_use_their_own_keys() {
+        let key = crypto::Pair::from_seed([7; 32]).public();
+        let json = format!(r#"{{ "accounts": [{{ "name": "alice", "balance": 1, "public_key": "0x{}" }}] }}"#, hex_encode(&key));
+        let runtime = GenesisConfig::from_json(&json).unwrap().build().unwrap();
+        assert_eq!(runtime.system.account_key(&"alice".to_string()), Some(key));
+
+        let bad = r#"{ "accounts": [{ "name": "alice", "balance": 1, "public_key": "abcd" }] }"#;
+        assert!(matches!(GenesisConfig::from_json(bad).unwrap().build(), Err(GenesisError::Invalid(_))));
+    }
+
+    #[test]
+    fn accounts_without_a_key_are_rejected() {
+        let keyless = r#"{ "accounts": [{ "name": "alice", "balance": 1 }] }"#;
+        assert!(matches!(GenesisConfig::from_json(keyless), Err(GenesisError::Parse(_))));
+        let keyless = "[[accounts]]\nname = \"alice\"\nbalance = 1\n";
+        assert!(matches!(GenesisConfig::from_toml(keyless), Err(GenesisError::Parse(_))));
+    }
+
+    #[test]
+    fn unbuildable_configs_are_rejected() {
+        let mut config = GenesisConfig::from_json(JSON).unwrap();
+        config.staking.stakes[0].validator = "carol".to_string();
+        assert!(matches!(config.build(), Err(GenesisError::Invalid(_))));
+
+        let mut config = GenesisConfig::from_json(JSON).unwrap();
+        config.accounts.push(config.accounts[0].clone());
+        assert!(matches!(config.build(), Err(GenesisError::Invalid(_))));
+
+        let mut config = GenesisConfig::from_json(JSON).unwrap();
+        config.vesting[0].locked = 1001;
+        assert!(matches!(config.build(), Err(GenesisError::Invalid(_))));
+
+        // bob's stake stays in bob's balance under a lock, but that whole balance of 500 is below the deposit
+        let mut config = GenesisConfig::from_json(JSON).unwrap();
+        config.existential_deposit = 501;
+        assert!(matches!(config.build(), Err(GenesisError::Invalid(_))));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use crate::codec::{Decode, DecodeError, Encode};
+use crate::migrations::{OnRuntimeUpgrade, StorageVersion};
+use crate::support::{blake2_256, DispatchResult};
+use crate::{balances, types, RuntimeEvent};
+
+// What happened when an extrinsic of a block was applied
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub extrinsic_hash: [u8; 32],
+    pub result: DispatchResult,   // Whether the call succeeded, and why not
+    pub events: Vec<RuntimeEvent>, // Every event the extrinsic emitted, in order
+    pub fee_paid: types::Balance, // Fees and tip taken from the sender
+}
+
+impl Receipt {
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+// A finalized block as it was executed
+// Extrinsics are kept encoded, so blocks of any kind of transaction can be stored
+#[derive(Debug, Clone)]
+pub struct StoredBlock {
+    pub header: types::Header,
+    pub extrinsics: Vec<Vec<u8>>,
+    pub receipts: Vec<Receipt>, // One per extrinsic, in the same order
+}
+
+// Every block of the chain with the receipts of its extrinsics, indexed by number and hash
+//
+// The system pallet keeps the headers it needs to build the next block; the history keeps
+// everything else a block was made of, so past blocks can be looked up after the fact
+#[derive(Debug, Clone, Default)]
+pub struct BlockHistory {
+    blocks: BTreeMap<types::BlockNumber, StoredBlock>,
+    numbers: BTreeMap<[u8; 32], types::BlockNumber>,
+    // Extrinsics applied so far in the block being built, with their receipts
+    pending: Vec<(Vec<u8>, Receipt)>,
+}
+
+impl BlockHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record an extrinsic applied in the block being built
+    // Its receipt is made from the events the extrinsic emitted
+    pub fn note_extrinsic(&mut self, encoded: Vec<u8>, result: DispatchResult, events: Vec<RuntimeEvent>) {
+        let fee_paid = events
+            .iter()
+            .map(|event| match event {
+                RuntimeEvent::Balances(balances::BalancesEvent::FeePaid { amount, .. }) => *amount,
+                _ => 0,
+            })
+            .sum();
+        let receipt = Receipt {
+            extrinsic_hash: blake2_256(&encoded),
+            result,
+            events,
+            fee_paid,
+        };
+        self.pending.push((encoded, receipt));
+    }
+
+    // Store the block being built under its finalized header
+    pub fn finalize_block(&mut self, header: types::Header) {
+        let (extrinsics, receipts) = std::mem::take(&mut self.pending).into_iter().unzip();
+        self.numbers.insert(header.hash(), header.block_number);
+        self.blocks.insert(
+            header.block_number,
+            StoredBlock {
+                header,
+                extrinsics,
+                receipts,
+            },
+        );
+    }
+
+    pub fn block_by_number(&self, number: types::BlockNumber) -> Option<&StoredBlock> {
+        self.blocks.get(&number)
+    }
+
+    pub fn block_by_hash(&self, hash: &[u8; 32]) -> Option<&StoredBlock> {
+        self.numbers.get(hash).and_then(|number| self.blocks.get(number))
+    }
+
+    // The receipt of extrinsic `index` of block `number`
+    pub fn receipt(&self, number: types::BlockNumber, index: u32) -> Option<&Receipt> {
+        self.block_by_number(number)?.receipts.get(index as usize)
+    }
+}
+
+impl Encode for Receipt {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.extrinsic_hash.encode_to(dest);
+        self.result.encode_to(dest);
+        self.events.encode_to(dest);
+        self.fee_paid.encode_to(dest);
+    }
+}
+
+impl Decode for Receipt {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            extrinsic_hash: Decode::decode(input)?,
+            result: Decode::decode(input)?,
+            events: Decode::decode(input)?,
+            fee_paid: Decode::decode(input)?,
+        })
+    }
+}
+
+impl Encode for StoredBlock {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.header.encode_to(dest);
+        self.extrinsics.encode_to(dest);
+        self.receipts.encode_to(dest);
+    }
+}
+
+impl Decode for StoredBlock {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            header: Decode::decode(input)?,
+            extrinsics: Decode::decode(input)?,
+            receipts: Decode::decode(input)?,
+        })
+    }
+}
+
+// Only the blocks are saved - the hash index is rebuilt from their headers,
+// and the history is only saved between blocks, when nothing is pending
+impl Encode for BlockHistory {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.blocks.encode_to(dest);
+    }
+}
+
+impl Decode for BlockHistory {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let blocks: BTreeMap<types::BlockNumber, StoredBlock> = Decode::decode(input)?;
+        let numbers = blocks
+            .iter()
+            .map(|(number, block)| (block.header.hash(), *number))
+            .collect();
+        Ok(Self {
+            blocks,
+            numbers,
+            pending: Vec::new(),
+        })
+    }
+}
+
+impl OnRuntimeUpgrade for BlockHistory {
+    const NAME: &'static str = "History";
+    const STORAGE_VERSION: StorageVersion = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::{GenesisAccount, GenesisConfig};
+    use crate::support::{DispatchError, Extrinsic};
+    use crate::{crypto, system, Runtime, RuntimeCall};
+
+    fn transfer(runtime: &Runtime, nonce: types::Nonce, amount: types::Balance, tip: types::Balance) -> types::Extrinsic {
+        Extrinsic::new_signed_with_tip(
+            "alice".to_string(),
+            RuntimeCall::Balances(balances::Call::Transfer { to: "bob".to_string(), amount }),
+            nonce,
+            tip,
+            &crypto::Pair::dev("alice"),
+            runtime.genesis_hash(),
+        )
+    }
+
+    #[test]
+    fn blocks_and_receipts_are_kept() {
+        let mut config = GenesisConfig {
+            accounts: vec![GenesisAccount::development("alice", 1000)],
+            ..GenesisConfig::default()
+        };
+        config.fees.base_fee = 2;
+        let mut runtime = config.build().unwrap();
+
+        let paid = transfer(&runtime, 0, 100, 3);
+        let too_much = transfer(&runtime, 1, 5000, 0);
+        let (block, _) = runtime.author_block(vec![paid.clone(), too_much.clone()]);
+        let hash = block.header.hash();
+
+        let history = &runtime.history;
+        let stored = history.block_by_number(1).unwrap();
+        assert_eq!(stored.header, block.header);
+        assert_eq!(stored.extrinsics, vec![paid.encode(), too_much.encode()]);
+        assert_eq!(history.block_by_hash(&hash).unwrap().header, block.header);
+        assert!(history.block_by_number(0).unwrap().extrinsics.is_empty());
+
+        let receipt = history.receipt(1, 0).unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.extrinsic_hash, blake2_256(&paid.encode()));
+        assert_eq!(receipt.fee_paid, 5);
+        assert!(matches!(
+            receipt.events.last(),
+            Some(RuntimeEvent::System(system::SystemEvent::ExtrinsicSuccess))
+        ));
+
+        // A failed call still pays its fee, and reports why it failed
+        let failed = history.receipt(1, 1).unwrap();
+        assert!(matches!(&failed.result, Err(DispatchError::Module { pallet, .. }) if pallet == "Balances"));
+        assert_eq!(failed.fee_paid, 50);
+        assert!(history.receipt(1, 2).is_none());
+
+        // The history survives being saved
+        let decoded = BlockHistory::decode_all(&history.encode()).unwrap();
+        assert_eq!(decoded.block_by_hash(&hash).unwrap().receipts.len(), 2);
+        assert_eq!(decoded.receipt(1, 0).unwrap().fee_paid, 5);
+    }
+}
@@ -1,9 +1,26 @@
 mod balances;
+mod blocktree;
+mod cli;
+mod codec;
+mod crypto;
+mod genesis;
+mod history;
+mod metadata;
+mod migrations;
+mod rpc;
 mod staking;
+mod store;
 mod support;
 mod system;
+mod txpool;
+mod vesting;
 
-use support::Dispatch;
+use codec::{Decode, DecodeError, Encode};
+use migrations::{MigrationError, OnRuntimeUpgrade, RuntimeVersion, VersionedState};
+use support::{
+    with_transaction, Dispatch, GetWeight, InvalidTransaction, StorageEntries, Transactional, Weight,
+};
+use txpool::{TransactionPriority, TransactionValidator, ValidTransaction};
 
 // Type module - this is where we define all the concrete types for our runtime
 mod types {
@@ -13,87 +30,386 @@ mod types {
     pub type Nonce = u32;             // Nonces are 32-bit unsigned integers
     
     // Complex types built from the basic types
-    pub type Extrinsic = crate::support::Extrinsic<AccountId, crate::RuntimeCall>;
+    pub type Extrinsic = crate::support::Extrinsic<AccountId, crate::RuntimeCall, Nonce, Balance>;
     pub type Header = crate::support::Header<BlockNumber>;
     pub type Block = crate::support::Block<Header, Extrinsic>;
+    pub type TransactionPool = crate::txpool::TransactionPool<AccountId, Nonce, Extrinsic>;
 }
 
 // This enum contains all the calls available to our runtime
 // Each pallet contributes its calls here
+#[derive(Debug, Clone)]
 pub enum RuntimeCall {
     Balances(balances::Call<Runtime>),  // Balances pallet calls
     Staking(staking::Call<Runtime>),    // Staking pallet calls
+    Vesting(vesting::Call<Runtime>),    // Vesting pallet calls
+}
+
+// Runtime calls are encoded as the pallet index followed by the pallet call
+impl Encode for RuntimeCall {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            RuntimeCall::Balances(call) => {
+                dest.push(0);
+                call.encode_to(dest);
+            }
+            RuntimeCall::Staking(call) => {
+                dest.push(1);
+                call.encode_to(dest);
+            }
+            RuntimeCall::Vesting(call) => {
+                dest.push(2);
+                call.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl Decode for RuntimeCall {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match codec::read_variant(input)? {
+            0 => Ok(RuntimeCall::Balances(Decode::decode(input)?)),
+            1 => Ok(RuntimeCall::Staking(Decode::decode(input)?)),
+            2 => Ok(RuntimeCall::Vesting(Decode::decode(input)?)),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+// This enum contains all the events emitted in our runtime
+// Each pallet contributes its events here
+#[derive(Debug, Clone)]
+pub enum RuntimeEvent {
+    System(system::SystemEvent),
+    Balances(balances::BalancesEvent<Runtime>),
+    Staking(staking::StakingEvent<Runtime>),
+    Vesting(vesting::VestingEvent<Runtime>),
+}
+
+// Runtime events are encoded as the pallet index followed by the pallet event
+impl Encode for RuntimeEvent {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            RuntimeEvent::System(event) => {
+                dest.push(0);
+                event.encode_to(dest);
+            }
+            RuntimeEvent::Balances(event) => {
+                dest.push(1);
+                event.encode_to(dest);
+            }
+            RuntimeEvent::Staking(event) => {
+                dest.push(2);
+                event.encode_to(dest);
+            }
+            RuntimeEvent::Vesting(event) => {
+                dest.push(3);
+                event.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl Decode for RuntimeEvent {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match codec::read_variant(input)? {
+            0 => Ok(RuntimeEvent::System(Decode::decode(input)?)),
+            1 => Ok(RuntimeEvent::Balances(Decode::decode(input)?)),
+            2 => Ok(RuntimeEvent::Staking(Decode::decode(input)?)),
+            3 => Ok(RuntimeEvent::Vesting(Decode::decode(input)?)),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl From<system::SystemEvent> for RuntimeEvent {
+    fn from(event: system::SystemEvent) -> Self {
+        RuntimeEvent::System(event)
+    }
+}
+
+impl From<balances::BalancesEvent<Runtime>> for RuntimeEvent {
+    fn from(event: balances::BalancesEvent<Runtime>) -> Self {
+        RuntimeEvent::Balances(event)
+    }
+}
+
+impl From<staking::StakingEvent<Runtime>> for RuntimeEvent {
+    fn from(event: staking::StakingEvent<Runtime>) -> Self {
+        RuntimeEvent::Staking(event)
+    }
+}
+
+impl From<vesting::VestingEvent<Runtime>> for RuntimeEvent {
+    fn from(event: vesting::VestingEvent<Runtime>) -> Self {
+        RuntimeEvent::Vesting(event)
+    }
 }
 
 // Our main Runtime struct - this implements the Config traits for all pallets
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Runtime {
     pub system: system::Pallet<Self>,    // Self refers to Runtime
     pub balances: balances::Pallet<Self>,
     pub staking: staking::Pallet<Self>,  // Add staking pallet
+    pub vesting: vesting::Pallet<Self>,
+    pub history: history::BlockHistory,  // Every finalized block with its receipts
+    pub max_block_weight: Weight,        // Most weight the extrinsics of one block may use
 }
 
+// Default weight limit - room for a few dozen typical calls
+const MAX_BLOCK_WEIGHT: Weight = 5_000;
+
+// Staked funds are bonded in place: they stay in the staker's free balance under this lock
+const STAKING_LOCK: balances::LockIdentifier = *b"staking ";
+
+// Funds that have not vested yet are locked the same way, under their own lock
+const VESTING_LOCK: balances::LockIdentifier = *b"vesting ";
+
+// Bumped whenever the runtime's behaviour changes
+const SPEC_VERSION: u32 = 5;
+
 // Implement system::Config for Runtime
 // This tells the system pallet what types to use
 impl system::Config for Runtime {
     type AccountId = types::AccountId;     // Use String for accounts
     type BlockNumber = types::BlockNumber; // Use u32 for block numbers
     type Nonce = types::Nonce;            // Use u32 for nonces
+    type RuntimeEvent = RuntimeEvent;     // Events from every pallet
 }
 
 // Implement balances::Config for Runtime
 // This tells the balances pallet what types to use
 impl balances::Config for Runtime {
     type Balance = types::Balance;  // Use u128 for balances
+    type FeeStrategy = TransferFee;
 }
 
+// Small transfers pay the base fee; from 1000 tokens up they pay 1% of the amount,
+// at least the base fee and at most 100 tokens
+type TransferFee = balances::Tiered<1_000, balances::Flat, balances::Capped<balances::Percentage<1>, 100>>;
+
 // Implement staking::Config for Runtime
 // This tells the staking pallet what types to use
 impl staking::Config for Runtime {
     type Balance = types::Balance;  // Use u128 for staking balances too
 }
 
+impl vesting::Config for Runtime {
+    type Balance = types::Balance;
+    const MIN_VESTED_TRANSFER: types::Balance = 100;
+}
+
 impl Runtime {
-    // Create a new instance of the runtime
+    // Create a new instance of the runtime, with an empty genesis state
+    #[cfg(test)]
     fn new() -> Self {
-        Runtime {
-            system: system::Pallet::new(),   // Create system pallet with Runtime's config
-            balances: balances::Pallet::new(), // Create balances pallet with Runtime's config
-            staking: staking::Pallet::new_with_config(100, 5, 10, 10), // Create staking pallet with config
+        genesis::GenesisConfig::default()
+            .build()
+            .expect("the default genesis config is valid")
+    }
+
+    // The version of this runtime and of the storage layout of each of its pallets
+    fn version() -> RuntimeVersion {
+        RuntimeVersion {
+            spec_name: "rust-class-state-machine",
+            spec_version: SPEC_VERSION,
+            storage_versions: vec![
+                (system::Pallet::<Self>::NAME, system::Pallet::<Self>::STORAGE_VERSION),
+                (balances::Pallet::<Self>::NAME, balances::Pallet::<Self>::STORAGE_VERSION),
+                (staking::Pallet::<Self>::NAME, staking::Pallet::<Self>::STORAGE_VERSION),
+                (vesting::Pallet::<Self>::NAME, vesting::Pallet::<Self>::STORAGE_VERSION),
+                (history::BlockHistory::NAME, history::BlockHistory::STORAGE_VERSION),
+            ],
         }
     }
 
-    fn create_block(&mut self, transactions: Vec<Transaction>) -> BlockResult {
+    // Everything a client needs to build extrinsics and read events, without the source
+    fn metadata() -> metadata::RuntimeMetadata {
+        use metadata::{FieldMetadata, PalletMetadata};
+
+        let field = |name, ty| FieldMetadata { name, ty };
+        metadata::RuntimeMetadata {
+            spec_name: Self::version().spec_name,
+            spec_version: SPEC_VERSION,
+            types: [
+                ("AccountId", "String"),
+                ("Balance", "u128"),
+                ("BalanceStatus", "Enum { Free, Reserved }"),
+                ("BlockNumber", "u32"),
+                ("Nonce", "u32"),
+                ("RuntimeCall", "Enum of the pallets' calls, by call index"),
+                ("DispatchError", "Enum { Module { pallet: String, error: String, message: String }, Other(String) }"),
+                ("ExtrinsicSignature", "{ signer: [u8; 32], signature: [u8; 64] }"),
+                ("VestingInfo", "{ locked: Balance, per_block: Balance, starting_block: BlockNumber }"),
+            ]
+            .into_iter()
+            .collect(),
+            extrinsic: metadata::ExtrinsicMetadata {
+                fields: vec![
+                    field("caller", "AccountId"),
+                    field("call", "RuntimeCall"),
+                    field("nonce", "Nonce"),
+                    field("tip", "Balance"),
+                    field("signature", "Option<ExtrinsicSignature>"),
+                ],
+                signed_payload: vec!["caller", "call", "nonce", "tip", "genesis hash"],
+            },
+            pallets: vec![
+                PalletMetadata::of::<system::Pallet<Self>>(system::Pallet::<Self>::NAME, None, 0),
+                PalletMetadata::of::<balances::Pallet<Self>>(balances::Pallet::<Self>::NAME, Some(0), 1),
+                PalletMetadata::of::<staking::Pallet<Self>>(staking::Pallet::<Self>::NAME, Some(1), 2),
+                PalletMetadata::of::<vesting::Pallet<Self>>(vesting::Pallet::<Self>::NAME, Some(2), 3),
+            ],
+        }
+    }
+
+    // The genesis hash identifies this chain in signed extrinsics
+    fn genesis_hash(&self) -> [u8; 32] {
+        self.system
+            .genesis_hash()
+            .expect("genesis block is finalized when the runtime is created")
+    }
+
+    // Start a new block: bump the block number, notify pallets and record the timestamp
+    fn initialize_block(&mut self, timestamp: u64) {
         self.system.inc_block_number();
-        let current_block = self.system.block_number();
+        self.system.reset_events();
 
         // Notify staking pallet about new block
-        self.staking.on_block(current_block);
+        self.staking.on_block(self.system.block_number());
+
+        self.system.set_timestamp(timestamp);
+
+        // Anything emitted outside a block (like genesis setup) lands in this one
+        self.collect_events(None);
+    }
+
+    // Move the events the pallets emitted into the system pallet's event log
+    fn collect_events(&mut self, extrinsic_index: Option<u32>) {
+        for event in self.balances.take_events() {
+            if let balances::BalancesEvent::AccountReaped { who } = &event {
+                self.on_account_reaped(who);
+            }
+            self.system.deposit_event(extrinsic_index, event);
+        }
+        for event in self.staking.take_events() {
+            self.system.deposit_event(extrinsic_index, event);
+        }
+        for event in self.vesting.take_events() {
+            self.system.deposit_event(extrinsic_index, event);
+        }
+    }
+
+    // A reaped account no longer exists, so the system pallet forgets its nonce too
+    // Its nonce is kept as a floor though: funded again, it carries on from there rather than from 0,
+    // so the extrinsics it signed before cannot be replayed
+    fn on_account_reaped(&mut self, who: &types::AccountId) {
+        self.system.kill_account(who);
+    }
+
+    // Record the events of an applied extrinsic followed by its outcome,
+    // and keep the extrinsic with its receipt in the block history
+    fn note_applied_extrinsic(&mut self, index: u32, encoded: Vec<u8>, result: &support::DispatchResult) {
+        self.collect_events(Some(index));
+        let outcome = match result {
+            Ok(()) => system::SystemEvent::ExtrinsicSuccess,
+            Err(e) => system::SystemEvent::ExtrinsicFailed { error: e.clone() },
+        };
+        self.system.deposit_event(Some(index), outcome);
+
+        let events = self
+            .system
+            .events()
+            .iter()
+            .filter(|record| record.extrinsic_index == Some(index))
+            .map(|record| record.event.clone())
+            .collect();
+        self.history.note_extrinsic(encoded, result.clone(), events);
+    }
+
+    // Timestamp for a block authored now - never earlier than its parent
+    fn next_timestamp(&self) -> u64 {
+        now_millis().max(self.system.timestamp())
+    }
+
+    // Compute the state root: a Merkle root over the storage of every pallet
+    fn state_root(&self) -> [u8; 32] {
+        let mut entries = self.system.storage_entries();
+        entries.extend(self.balances.storage_entries());
+        entries.extend(self.staking.storage_entries());
+        entries.extend(self.vesting.storage_entries());
+        support::state_root(entries)
+    }
+
+    // Record the state root and finalize the block being built
+    fn seal_block(&mut self) -> types::Header {
+        self.collect_events(None);
+        debug_assert_eq!(self.check_issuance(), Ok(()));
+        self.system.note_state_root(self.state_root());
+        self.system.finalize_block();
+        let header = self
+            .system
+            .get_header(self.system.block_number())
+            .cloned()
+            .expect("header is stored when the block is finalized");
+        self.history.finalize_block(header.clone());
+        header
+    }
+
+    fn create_block(&mut self, transactions: Vec<Transaction>) -> BlockResult {
+        self.initialize_block(self.next_timestamp());
+        let current_block = self.system.block_number();
 
         println!("\n=== Creating Block #{} ===", current_block);
 
         let mut successful_transactions = Vec::new();
         let mut failed_transactions = Vec::new();
-
-        // Execute all transactions in the block
-        for transaction in transactions {
-            match self.execute_transaction(transaction.clone()) {
-                Ok(_) => {
+        let mut leftover_transactions = Vec::new();
+
+        // Execute all transactions in the block as signed extrinsics, checked like any other
+        // A transaction whose nonce is ahead of its sender's is held back rather than failed,
+        // and one that does not fit in the block's remaining weight is left for a later block
+        let genesis_hash = self.genesis_hash();
+        let held_transactions = apply_with_hold_back(transactions, |transaction| {
+            match self.apply_extrinsic(transaction.to_extrinsic(genesis_hash)) {
+                Ok(Ok(())) => {
                     successful_transactions.push(transaction);
                     println!("✅ Transaction successful");
+                    Ok(())
                 }
-                Err(e) => {
-                    failed_transactions.push((transaction, e));
+                Ok(Err(e)) => {
+                    failed_transactions.push((transaction, e.to_string()));
                     println!("❌ Transaction failed");
+                    Ok(())
+                }
+                Err(InvalidTransaction::Future) => Err(InvalidTransaction::Future),
+                Err(InvalidTransaction::ExhaustsResources) => {
+                    leftover_transactions.push(transaction);
+                    Err(InvalidTransaction::ExhaustsResources)
+                }
+                Err(e) => {
+                    println!("❌ Transaction rejected: {}", e);
+                    failed_transactions.push((transaction, e.to_string()));
+                    Err(e)
                 }
             }
+        });
+
+        for transaction in &held_transactions {
+            println!("⏸️  Transaction held back until earlier nonces are used: {:?}", transaction);
         }
+        for transaction in &leftover_transactions {
+            println!("⚖️  Transaction left for a later block, this one is full: {:?}", transaction);
+        }
+        let block_weight = self.system.block_weight();
 
         // Finalize the block and generate hash
-        let block_hash = self.system.finalize_block();
+        let header = self.seal_block();
+        let block_hash = header.hash();
         
-        // Print staking events for this block
-        self.print_staking_events();
+        // Print the events of this block
+        self.print_events();
         
         println!("📦 Block #{} finalized", current_block);
         println!("🔗 Block Hash: {:?}", hex_encode(&block_hash[..8]));
@@ -101,135 +417,282 @@ impl Runtime {
         if let Some(parent_hash) = self.system.parent_block_hash() {
             println!("⬆️  Parent Hash: {:?}", hex_encode(&parent_hash[..8]));
         }
+        println!("🌳 State Root: {:?}", hex_encode(&header.state_root[..8]));
 
         BlockResult {
             block_number: current_block,
             block_hash,
+            header,
             successful_transactions: successful_transactions.clone(),
             failed_transactions,
+            held_transactions,
+            leftover_transactions,
+            block_weight,
             transaction_count: successful_transactions.len(),
         }
     }
 
-    fn execute_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
-        match transaction {
-            Transaction::Transfer { from, to, amount } => {
-                self.system.inc_nonce(&from);
-
-                // Attempt the transfer using the generic balances pallet
-                match self.balances.transfer(from.clone(), to.clone(), amount) {
-                    Ok(_) => {
-                        println!("💸 Transfer: {} -> {} (amount: {})", from, to, amount);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        println!(
-                            "💥 Transfer failed: {} -> {} (amount: {}) - Error: {:?}",
-                            from, to, amount, e
-                        );
-                        Err(format!("{:?}", e))
-                    }
-                }
-            }
-            Transaction::SetBalance { who, amount } => {
-                println!("💰 Set balance: {} = {}", who, amount);
-                self.balances.set_balance(&who, amount);
+    // Stake `amount` of `who`'s balance with `validator`
+    // Staking moves funds out of and back into the balances pallet, so the runtime
+    // handles these calls rather than the staking pallet alone
+    fn stake(&mut self, who: String, amount: u128, validator: String) -> support::DispatchResult {
+        // Create a closure that checks balance
+        let balances = &self.balances;
+        let balance_check = |account: &String| -> u128 { balances.usable_balance(account) };
+
+        match self.staking.stake(who.clone(), amount, validator.clone(), balance_check) {
+            Ok(_) => {
+                // The staked amount stays in the account, locked until it is unstaked
+                // Not even fees can be paid from it, which is why unstaking is free
+                self.balances.set_lock(STAKING_LOCK, &who, amount, balances::Reasons::All);
+                println!("🔒 Staked: {} staked {} with validator {}", who, amount, validator);
                 Ok(())
             }
-            Transaction::AddValidator { validator, commission } => {
-                match self.staking.add_validator(validator.clone(), commission) {
-                    staking::Result::Ok(_) => {
-                        println!("✅ Validator added: {} (commission: {}%)", validator, commission);
-                        Ok(())
-                    }
-                    staking::Result::Err(e) => {
-                        println!("❌ Failed to add validator: {} - Error: {:?}", validator, e);
-                        Err(format!("{:?}", e))
-                    }
-                }
+            Err(e) => {
+                println!("❌ Staking failed for {}: {:?}", who, e);
+                Err(e.into())
             }
-            Transaction::Stake { who, amount, validator } => {
-                self.system.inc_nonce(&who);
-
-                // Create a closure that checks balance
-                let balances = &self.balances;
-                let balance_check = |account: &String| -> u128 { balances.balance(account) };
-
-                match self.staking.stake(who.clone(), amount, validator.clone(), balance_check) {
-                    Ok(_) => {
-                        // Deduct the staked amount from balance (with proper error checking)
-                        let current_balance = self.balances.balance(&who);
-                        if current_balance >= amount {
-                            self.balances.set_balance(&who, current_balance - amount);
-                            println!("🔒 Staked: {} staked {} with validator {}", who, amount, validator);
-                            Ok(())
-                        } else {
-                            println!("❌ Insufficient balance after staking validation");
-                            Err("Insufficient balance".to_string())
-                        }
-                    }
-                    Err(e) => {
-                        println!("❌ Staking failed for {}: {:?}", who, e);
-                        Err(format!("{:?}", e))
-                    }
-                }
+        }
+    }
+
+    // Unstake everything `who` has staked and return it to their balance
+    fn unstake(&mut self, who: String) -> support::DispatchResult {
+        match self.staking.unstake(who.clone()) {
+            Ok(amount) => {
+                // The unstaked amount can be used again
+                self.balances.remove_lock(STAKING_LOCK, &who);
+                println!("🔓 Unstaked: {} unstaked {} tokens", who, amount);
+                Ok(())
             }
-            Transaction::Unstake { who } => {
-                self.system.inc_nonce(&who);
+            Err(e) => {
+                println!("❌ Unstaking failed for {}: {:?}", who, e);
+                Err(e.into())
+            }
+        }
+    }
 
-                match self.staking.unstake(who.clone()) {
-                    Ok(amount) => {
-                        // Return the unstaked amount to balance
-                        let current_balance = self.balances.balance(&who);
-                        self.balances.set_balance(&who, current_balance + amount);
-                        println!("🔓 Unstaked: {} unstaked {} tokens", who, amount);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        println!("❌ Unstaking failed for {}: {:?}", who, e);
-                        Err(format!("{:?}", e))
-                    }
-                }
+    // Pay `who` the staking rewards they have earned
+    fn claim_rewards(&mut self, who: String) -> support::DispatchResult {
+        match self.staking.claim_rewards(who.clone()) {
+            Ok(rewards) => {
+                // Rewards are new tokens
+                self.balances.mint(&who, rewards)?;
+                println!("🎁 Rewards claimed: {} received {} tokens", who, rewards);
+                Ok(())
+            }
+            Err(e) => {
+                println!("❌ Failed to claim rewards for {}: {:?}", who, e);
+                Err(e.into())
             }
-            Transaction::ClaimRewards { who } => {
-                self.system.inc_nonce(&who);
+        }
+    }
 
-                match self.staking.claim_rewards(who.clone()) {
-                    Ok(rewards) => {
-                        // Add rewards to balance
-                        let current_balance = self.balances.balance(&who);
-                        self.balances.set_balance(&who, current_balance + rewards);
-                        println!("🎁 Rewards claimed: {} received {} tokens", who, rewards);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        println!("❌ Failed to claim rewards for {}: {:?}", who, e);
-                        Err(format!("{:?}", e))
-                    }
-                }
+    // Unlock whatever has vested of `who`'s funds by the current block
+    // The vesting lock shrinks to what is still locked, and goes once everything has vested
+    fn vest(&mut self, who: String) -> support::DispatchResult {
+        let locked = self.vesting.vest(&who, self.system.block_number())?;
+        // Unlike stakes, vesting funds can still pay fees
+        self.balances.set_lock(VESTING_LOCK, &who, locked, balances::Reasons::Misc);
+        println!("⏳ Vested: {} has {} tokens still vesting", who, locked);
+        Ok(())
+    }
+
+    // Transfer `schedule.locked` from `from` to `target`, locked until it vests on `schedule`
+    fn vested_transfer(
+        &mut self,
+        from: String,
+        target: String,
+        schedule: vesting::VestingInfo<Runtime>,
+    ) -> support::DispatchResult {
+        let amount = schedule.locked;
+        if amount < <Runtime as vesting::Config>::MIN_VESTED_TRANSFER {
+            return Err(vesting::VestingError::AmountLow.into());
+        }
+        self.vesting.add_vesting_schedule(&target, schedule)?;
+        self.balances.transfer_prepaid(from, target.clone(), amount, false)?;
+        self.vest(target)
+    }
+
+    // Check that an extrinsic is signed by the key of its caller
+    // That is the key registered for the caller, or for an account without one,
+    // the key the account is named after
+    fn check_signature(&self, extrinsic: &types::Extrinsic) -> Result<(), InvalidTransaction> {
+        if extrinsic.signature.is_none() {
+            return Err(InvalidTransaction::Unsigned);
+        }
+
+        let signer = extrinsic
+            .verify_signature(self.genesis_hash())
+            .ok_or(InvalidTransaction::BadSignature)?;
+
+        let authorised = match self.system.account_key(&extrinsic.caller) {
+            Some(key) => key == signer,
+            None => extrinsic.caller == key_account(&signer),
+        };
+        if !authorised {
+            return Err(InvalidTransaction::WrongSigner);
+        }
+        Ok(())
+    }
+
+    // Check whether an extrinsic can be included on top of the current state
+    fn validate_extrinsic(&self, extrinsic: &types::Extrinsic) -> Result<(), InvalidTransaction> {
+        self.validate_transaction(extrinsic)?;
+        self.system.check_nonce(&extrinsic.caller, extrinsic.nonce)
+    }
+
+    // The fee of a call: transfers pay what the fee strategy asks for their amount,
+    // every other call pays the base fee
+    fn call_fee(&self, call: &RuntimeCall) -> types::Balance {
+        match call {
+            RuntimeCall::Balances(
+                balances::Call::Transfer { amount, .. } | balances::Call::TransferKeepAlive { amount, .. },
+            ) => self.balances.calculate_fee(*amount),
+            RuntimeCall::Vesting(vesting::Call::VestedTransfer { schedule, .. }) => {
+                self.balances.calculate_fee(schedule.locked)
             }
+            RuntimeCall::Staking(_) | RuntimeCall::Vesting(_) => self.balances.get_transaction_fee(),
         }
     }
 
-    // Execute a block using the support framework
-    fn execute_block(&mut self, block: types::Block) -> support::DispatchResult {
-        self.system.inc_block_number();
+    // Everything an extrinsic pays: the fee of its call and its tip
+    // Unstaking is free once it is allowed, since all of the staker's funds may be locked in the stake
+    fn extrinsic_fee(&self, extrinsic: &types::Extrinsic) -> types::Balance {
+        let call_fee = match &extrinsic.call {
+            RuntimeCall::Staking(staking::Call::Unstake) if self.staking.can_unstake(&extrinsic.caller) => 0,
+            call => self.call_fee(call),
+        };
+        call_fee.saturating_add(extrinsic.tip)
+    }
+
+    // Priority of an extrinsic in the transaction pool: everything it pays, fees and tip
+    fn priority(&self, extrinsic: &types::Extrinsic) -> TransactionPriority {
+        TransactionPriority::try_from(self.extrinsic_fee(extrinsic)).unwrap_or(TransactionPriority::MAX)
+    }
 
-        if self.system.block_number() != block.header.block_number {
+    // Apply a single extrinsic of the block being built and return the outcome of its call
+    // Invalid extrinsics, and ones the block has no weight left for, are rejected
+    // before touching any state; a dispatch error is only logged since the extrinsic itself was valid
+    fn apply_extrinsic(
+        &mut self,
+        extrinsic: types::Extrinsic,
+    ) -> Result<support::DispatchResult, InvalidTransaction> {
+        self.validate_extrinsic(&extrinsic)?;
+        // The weight is only used up once the fee is paid,
+        // so an extrinsic that pays nothing takes no room in the block
+        let weight = extrinsic.call.weight();
+        self.system.check_weight(weight, self.max_block_weight)?;
+        self.balances
+            .pay_fee(&extrinsic.caller, self.extrinsic_fee(&extrinsic))
+            .map_err(|_| InvalidTransaction::Payment)?;
+        self.system.register_weight(weight, self.max_block_weight)?;
+        let encoded = extrinsic.encode();
+        let index = self.system.note_extrinsic(&encoded);
+
+        let support::Extrinsic { caller, call, .. } = extrinsic;
+        self.system.inc_nonce(&caller);
+        let block_number = self.system.block_number();
+
+        // A failed call leaves no trace apart from the used nonce and the fees and tip paid above,
+        // which are kept so that a failing extrinsic still pays for its place in the block
+        let result = with_transaction(self, |runtime| runtime.dispatch(caller, call));
+        self.note_applied_extrinsic(index, encoded, &result);
+        if let Err(e) = &result {
+            eprintln!(
+                "Extrinsic Error\n\tBlock Number: {}\n\tExtrinsic Number: {}\n\tError: {}",
+                block_number, index, e
+            );
+        }
+        Ok(result)
+    }
+
+    // Author a block: execute the extrinsics and fill in the complete header
+    // Invalid extrinsics are left out of the block; extrinsics whose nonce is still
+    // in the future, or that do not fit in the block's weight limit, are returned
+    // so they can be submitted again later
+    fn author_block(
+        &mut self,
+        extrinsics: Vec<types::Extrinsic>,
+    ) -> (types::Block, Vec<types::Extrinsic>) {
+        self.initialize_block(self.next_timestamp());
+
+        let mut included = Vec::new();
+        let mut leftover = Vec::new();
+        let held_back = apply_with_hold_back(extrinsics, |extrinsic| {
+            let result = self.apply_extrinsic(extrinsic.clone()).map(|_| ());
+            match &result {
+                Ok(()) => included.push(extrinsic),
+                Err(InvalidTransaction::Future) => {}
+                Err(InvalidTransaction::ExhaustsResources) => leftover.push(extrinsic),
+                Err(e) => println!("🚫 Extrinsic from {} dropped: {}", extrinsic.caller, e),
+            }
+            result
+        });
+
+        let header = self.seal_block();
+        let block = types::Block {
+            header,
+            extrinsics: included,
+        };
+        leftover.extend(held_back);
+        (block, leftover)
+    }
+
+    // Author a block from the best transactions in the pool,
+    // then evict the ones the block used up or made invalid
+    fn author_block_from_pool(&mut self, pool: &mut types::TransactionPool) -> types::Block {
+        let (block, _) = self.author_block(pool.ready(self));
+        pool.prune(self);
+        block
+    }
+
+    // Execute a block using the support framework
+    // Every header field is checked, so a block that does not build on our chain
+    // or does not match its contents is rejected
+    fn execute_block(&mut self, block: types::Block) -> Result<(), &'static str> {
+        if self.system.block_number() + 1 != block.header.block_number {
             return Err("block number does not match what is expected");
         }
 
-        // Process each extrinsic in the block
-        for (i, support::Extrinsic { caller, call }) in block.extrinsics.into_iter().enumerate() {
-            self.system.inc_nonce(&caller);
-            let _res = self.dispatch(caller, call).map_err(|e| {
+        if self.system.current_block_hash().unwrap_or([0u8; 32]) != block.header.parent_hash {
+            return Err("parent hash does not match the current chain head");
+        }
+
+        if block.header.timestamp < self.system.timestamp() {
+            return Err("block timestamp is earlier than its parent");
+        }
+
+        let extrinsic_hashes: Vec<[u8; 32]> = block
+            .extrinsics
+            .iter()
+            .map(|extrinsic| support::blake2_256(&extrinsic.encode()))
+            .collect();
+        if system::Pallet::<Self>::extrinsics_root(&extrinsic_hashes) != block.header.extrinsics_root {
+            return Err("extrinsics root does not match the block body");
+        }
+
+        // Keep the pre-block state so a rejected block leaves no trace
+        let checkpoint = self.clone();
+        self.initialize_block(block.header.timestamp);
+
+        // Process each extrinsic in the block - a single invalid one invalidates the block
+        for (i, extrinsic) in block.extrinsics.into_iter().enumerate() {
+            if let Err(e) = self.apply_extrinsic(extrinsic) {
                 eprintln!(
-                    "Extrinsic Error\n\tBlock Number: {}\n\tExtrinsic Number: {}\n\tError: {}",
+                    "Invalid Extrinsic\n\tBlock Number: {}\n\tExtrinsic Number: {}\n\tError: {}",
                     block.header.block_number, i, e
-                )
-            });
+                );
+                *self = checkpoint;
+                return Err("block contains an invalid extrinsic");
+            }
         }
 
+        if self.state_root() != block.header.state_root {
+            *self = checkpoint;
+            return Err("state root does not match after execution");
+        }
+
+        self.seal_block();
         Ok(())
     }
 
@@ -251,7 +714,7 @@ impl Runtime {
         for account in accounts {
             let balance = self.balances.balance(&account.to_string());
             if balance > 0 {
-                let nonce = self.system.nonce.get(&account.to_string()).unwrap_or(&0);
+                let nonce = self.system.nonce.get(account).unwrap_or(&0);
                 println!("  {}: {} (nonce: {})", account, balance, nonce);
             }
         }
@@ -291,47 +754,259 @@ impl Runtime {
     }
 
     // Verify Blockchain Integrity
+    // Every token in existence is in an account, free or reserved
+    fn total_held(&self) -> types::Balance {
+        let free: types::Balance = self.balances.all_balances().values().sum();
+        let reserved: types::Balance = self.balances.all_reserved().values().sum();
+        free.saturating_add(reserved)
+    }
+
+    // Check the invariant that the issuance accounts for exactly the tokens held
+    fn check_issuance(&self) -> Result<(), String> {
+        let issuance = self.balances.total_issuance();
+        let held = self.total_held();
+        if issuance == held {
+            Ok(())
+        } else {
+            Err(format!("total issuance is {} but {} tokens are held", issuance, held))
+        }
+    }
+
     fn verify_chain_integrity(&self) -> bool {
         let all_hashes = self.system.all_block_hashes();
 
         for block_num in 1..=self.system.block_number() {
-            if let Some(_current_hash) = all_hashes.get(&block_num) {
+            if !all_hashes.contains_key(&block_num) {
+                println!("❌ Block #{} hash missing!", block_num);
+                return false;
+            }
+
+            // Recompute the hash from the parent hash, number and extrinsics root
+            if self.system.verify_block_hash(block_num) {
                 println!("✅ Block #{} hash verified", block_num);
             } else {
-                println!("❌ Block #{} hash missing!", block_num);
+                println!("❌ Block #{} hash does not match its contents!", block_num);
                 return false;
             }
         }
+        if let Err(e) = self.check_issuance() {
+            println!("❌ {}", e);
+            return false;
+        }
+        println!("🪙 Total issuance of {} tokens accounted for", self.balances.total_issuance());
         println!("🔐 Blockchain integrity verified!");
         true
     }
 
-    /// Print staking events
-    fn print_staking_events(&self) {
-        let events = self.staking.get_events();
+    /// Print the events of the current block
+    fn print_events(&self) {
+        let events = self.system.events();
         if !events.is_empty() {
-            println!("\n📋 Staking Events:");
-            for event in events {
-                match event {
-                    staking::StakingEvent::ValidatorAdded { validator } => {
-                        println!("  • Validator added: {}", validator);
+            println!("\n📋 Events:");
+            for record in events {
+                let phase = match record.extrinsic_index {
+                    Some(index) => format!("extrinsic #{}", index),
+                    None => "block".to_string(),
+                };
+                match &record.event {
+                    RuntimeEvent::System(system::SystemEvent::ExtrinsicSuccess) => {
+                        println!("  • [{}] Extrinsic succeeded", phase);
+                    }
+                    RuntimeEvent::System(system::SystemEvent::ExtrinsicFailed { error }) => {
+                        println!("  • [{}] Extrinsic failed: {}", phase, error);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::Transfer { from, to, amount }) => {
+                        println!("  • [{}] {} transferred {} tokens to {}", phase, from, amount, to);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::FeePaid { who, amount }) => {
+                        println!("  • [{}] {} paid a fee of {} tokens", phase, who, amount);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::BalanceSet { who, amount }) => {
+                        println!("  • [{}] Balance of {} set to {}", phase, who, amount);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::DustLost { account, amount }) => {
+                        println!("  • [{}] {} tokens of dust lost by {}", phase, amount, account);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::AccountReaped { who }) => {
+                        println!("  • [{}] Account {} reaped", phase, who);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::Minted { who, amount }) => {
+                        println!("  • [{}] {} tokens minted to {}", phase, amount, who);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::Burned { who, amount }) => {
+                        println!("  • [{}] {} tokens of {} burned", phase, amount, who);
+                    }
+                    RuntimeEvent::Balances(balances::BalancesEvent::Reserved { who, amount }) => {
+                        println!("  • [{}] {} reserved {} tokens", phase, who, amount);
                     }
-                    staking::StakingEvent::Staked { who, amount, validator } => {
-                        println!("  • {} staked {} tokens with {}", who, amount, validator);
+                    RuntimeEvent::Balances(balances::BalancesEvent::Unreserved { who, amount }) => {
+                        println!("  • [{}] {} unreserved {} tokens", phase, who, amount);
                     }
-                    staking::StakingEvent::Unstaked { who, amount } => {
-                        println!("  • {} unstaked {} tokens", who, amount);
+                    RuntimeEvent::Balances(event) => {
+                        println!("  • [{}] {:?}", phase, event);
                     }
-                    staking::StakingEvent::RewardsPaid { who, amount } => {
-                        println!("  • {} received {} tokens in rewards", who, amount);
+                    RuntimeEvent::Staking(staking::StakingEvent::ValidatorAdded { validator }) => {
+                        println!("  • [{}] Validator added: {}", phase, validator);
+                    }
+                    RuntimeEvent::Staking(staking::StakingEvent::Staked { who, amount, validator }) => {
+                        println!("  • [{}] {} staked {} tokens with {}", phase, who, amount, validator);
+                    }
+                    RuntimeEvent::Staking(staking::StakingEvent::Unstaked { who, amount }) => {
+                        println!("  • [{}] {} unstaked {} tokens", phase, who, amount);
+                    }
+                    RuntimeEvent::Staking(staking::StakingEvent::RewardsPaid { who, amount }) => {
+                        println!("  • [{}] {} received {} tokens in rewards", phase, who, amount);
+                    }
+                    RuntimeEvent::Staking(event) => {
+                        println!("  • [{}] {:?}", phase, event);
+                    }
+                    RuntimeEvent::Vesting(vesting::VestingEvent::VestingUpdated { account, unvested }) => {
+                        println!("  • [{}] {} has {} tokens still vesting", phase, account, unvested);
+                    }
+                    RuntimeEvent::Vesting(vesting::VestingEvent::VestingCompleted { account }) => {
+                        println!("  • [{}] Vesting of {} completed", phase, account);
                     }
-                    _ => {}
                 }
             }
         }
     }
 }
 
+// The runtime is saved to disk as the state of each pallet, tagged with its storage version
+// Loading state saved by an older runtime migrates each pallet to its current layout
+// The block weight limit is node configuration rather than chain state, so it is not saved
+impl Encode for Runtime {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        let mut state = VersionedState::new(SPEC_VERSION);
+        state.insert(&self.system);
+        state.insert(&self.balances);
+        state.insert(&self.staking);
+        state.insert(&self.vesting);
+        state.insert(&self.history);
+        state.encode_to(dest);
+    }
+}
+
+impl Decode for Runtime {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut state = VersionedState::decode(input)?;
+        if state.spec_version > SPEC_VERSION {
+            return Err(MigrationError::NewerRuntime(state.spec_version).into());
+        }
+        let balances_version = state.pallets.get("Balances").map(|(version, _)| *version);
+        let mut runtime = Runtime {
+            system: state.take()?,
+            balances: state.take()?,
+            staking: state.take()?,
+            // The vesting pallet was added in spec version 3, and state saved before has none
+            vesting: if state.spec_version < 3 { vesting::Pallet::new() } else { state.take()? },
+            history: state.take()?,
+            max_block_weight: MAX_BLOCK_WEIGHT,
+        };
+        // Staked funds were taken out of the balances pallet before version 4 of its storage;
+        // they are reserved now, so give them back to their owners as reserved funds
+        if balances_version < Some(4) {
+            let stakes = runtime
+                .staking
+                .stakes
+                .iter()
+                .map(|(who, stake)| (who.clone(), stake.staked_amount));
+            runtime.balances.import_reserves(stakes);
+        }
+        // Then stakes were reserved until version 5, and are locked in place since
+        if balances_version < Some(5) {
+            for (who, stake) in &runtime.staking.stakes {
+                runtime
+                    .balances
+                    .unreserve(who, stake.staked_amount)
+                    .map_err(|e| DecodeError::Invalid(e.to_string()))?;
+            }
+            // Nothing happened on chain, so the migration leaves no events behind
+            runtime.balances.take_events();
+        }
+        // Stakes were only locked against transfers before spec version 5, and against fees too since
+        if state.spec_version < 5 {
+            for (who, stake) in &runtime.staking.stakes {
+                runtime.balances.set_lock(STAKING_LOCK, who, stake.staked_amount, balances::Reasons::All);
+            }
+        }
+        // Issuance was not tracked before version 3 of the balances storage,
+        // so every token held when the chain was saved is counted instead
+        if balances_version < Some(3) {
+            runtime.balances.set_total_issuance(runtime.total_held());
+        }
+        Ok(runtime)
+    }
+}
+
+// A dispatched call can change the storage of every pallet,
+// so a runtime checkpoint is made up of a checkpoint of each of them
+impl Transactional for Runtime {
+    type Checkpoint = (
+        <system::Pallet<Runtime> as Transactional>::Checkpoint,
+        <balances::Pallet<Runtime> as Transactional>::Checkpoint,
+        <staking::Pallet<Runtime> as Transactional>::Checkpoint,
+        <vesting::Pallet<Runtime> as Transactional>::Checkpoint,
+    );
+
+    fn checkpoint(&self) -> Self::Checkpoint {
+        (
+            self.system.checkpoint(),
+            self.balances.checkpoint(),
+            self.staking.checkpoint(),
+            self.vesting.checkpoint(),
+        )
+    }
+
+    fn restore(&mut self, checkpoint: Self::Checkpoint) {
+        let (system, balances, staking, vesting) = checkpoint;
+        self.system.restore(system);
+        self.balances.restore(balances);
+        self.staking.restore(staking);
+        self.vesting.restore(vesting);
+    }
+}
+
+// The transaction pool validates submitted extrinsics against the runtime's state
+impl TransactionValidator<types::Extrinsic> for Runtime {
+    type AccountId = types::AccountId;
+    type Nonce = types::Nonce;
+
+    fn validate_transaction(
+        &self,
+        extrinsic: &types::Extrinsic,
+    ) -> Result<ValidTransaction<types::AccountId, types::Nonce>, InvalidTransaction> {
+        self.check_signature(extrinsic)?;
+        if extrinsic.nonce < self.system.account_nonce(&extrinsic.caller) {
+            return Err(InvalidTransaction::Stale);
+        }
+        if !self.balances.can_pay_fee(&extrinsic.caller, self.extrinsic_fee(extrinsic)) {
+            return Err(InvalidTransaction::Payment);
+        }
+
+        Ok(ValidTransaction {
+            sender: extrinsic.caller.clone(),
+            nonce: extrinsic.nonce,
+            priority: self.priority(extrinsic),
+        })
+    }
+
+    fn account_nonce(&self, who: &types::AccountId) -> types::Nonce {
+        self.system.account_nonce(who)
+    }
+}
+
+// A runtime call weighs as much as the pallet call it wraps
+impl GetWeight for RuntimeCall {
+    fn weight(&self) -> Weight {
+        match self {
+            RuntimeCall::Balances(call) => call.weight(),
+            RuntimeCall::Staking(call) => call.weight(),
+            RuntimeCall::Vesting(call) => call.weight(),
+        }
+    }
+}
+
 // Implement the Dispatch trait for Runtime
 // This allows the runtime to route calls to the appropriate pallet
 impl support::Dispatch for Runtime {
@@ -343,50 +1018,94 @@ impl support::Dispatch for Runtime {
             RuntimeCall::Balances(call) => {
                 self.balances.dispatch(caller, call)?;  // Route to balances pallet
             }
+            // Calls that move funds need both pallets, so the runtime handles them
+            RuntimeCall::Staking(staking::Call::Stake { validator, amount }) => {
+                self.stake(caller, amount, validator)?;
+            }
+            RuntimeCall::Staking(staking::Call::Unstake) => self.unstake(caller)?,
+            RuntimeCall::Staking(staking::Call::ClaimRewards) => self.claim_rewards(caller)?,
             RuntimeCall::Staking(call) => {
                 self.staking.dispatch(caller, call)?;   // Route to staking pallet
             }
+            // Vesting locks funds in the balances pallet, so its calls are all handled here
+            RuntimeCall::Vesting(vesting::Call::Vest) => self.vest(caller)?,
+            RuntimeCall::Vesting(vesting::Call::VestedTransfer { target, schedule }) => {
+                self.vested_transfer(caller, target, schedule)?;
+            }
         }
         Ok(())
     }
 }
 
 // Transaction types for our simplified API
+// Every transaction is made by an account and carries that account's nonce
 #[derive(Debug, Clone)]
 pub enum Transaction {
     Transfer {
         from: String,
         to: String,
         amount: u128,
-    },
-    SetBalance {
-        who: String,
-        amount: u128,
+        nonce: u32,
     },
     AddValidator {
         validator: String,
         commission: u8,
+        nonce: u32,
     },
     Stake {
         who: String,
         amount: u128,
         validator: String,
+        nonce: u32,
     },
     Unstake {
         who: String,
+        nonce: u32,
     },
     ClaimRewards {
         who: String,
+        nonce: u32,
     },
 }
 
+impl Transaction {
+    // The extrinsic that makes this transaction, signed with its account's development key
+    // The simplified API only drives development chains, where those keys are registered at genesis
+    // Validators register themselves, so they sign their own AddValidator
+    fn to_extrinsic(&self, genesis_hash: [u8; 32]) -> types::Extrinsic {
+        let (caller, call, nonce) = match self.clone() {
+            Transaction::Transfer { from, to, amount, nonce } => {
+                (from, RuntimeCall::Balances(balances::Call::Transfer { to, amount }), nonce)
+            }
+            Transaction::AddValidator { validator, commission, nonce } => (
+                validator.clone(),
+                RuntimeCall::Staking(staking::Call::AddValidator { validator, commission }),
+                nonce,
+            ),
+            Transaction::Stake { who, amount, validator, nonce } => {
+                (who, RuntimeCall::Staking(staking::Call::Stake { validator, amount }), nonce)
+            }
+            Transaction::Unstake { who, nonce } => (who, RuntimeCall::Staking(staking::Call::Unstake), nonce),
+            Transaction::ClaimRewards { who, nonce } => {
+                (who, RuntimeCall::Staking(staking::Call::ClaimRewards), nonce)
+            }
+        };
+        let pair = crypto::Pair::dev(&caller);
+        support::Extrinsic::new_signed(caller, call, nonce, &pair, genesis_hash)
+    }
+}
+
 // Block execution result
 #[derive(Debug)]
 pub struct BlockResult {
     pub block_number: u32,
     pub block_hash: [u8; 32],
+    pub header: types::Header,
     pub successful_transactions: Vec<Transaction>,
     pub failed_transactions: Vec<(Transaction, String)>,
+    pub held_transactions: Vec<Transaction>, // Not included yet - their nonce is in the future
+    pub leftover_transactions: Vec<Transaction>, // Not included - the block weight limit was reached
+    pub block_weight: Weight,
     pub transaction_count: usize,
 }
 
@@ -397,8 +1116,65 @@ fn hex_encode(bytes: &[u8]) -> String {
         .collect::<String>()
 }
 
-fn main() {
-    let mut runtime = Runtime::new();
+// The account of a key that was not registered at genesis: the key itself in hex
+// Accounts created after genesis are addressed this way, so whoever holds the key can spend from them
+fn key_account(key: &crypto::PublicKey) -> types::AccountId {
+    hex_encode(key)
+}
+
+// Parse hex written by `hex_encode`, with or without a leading 0x
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Apply `items` in order, holding back any that report a future nonce
+// Held back items are retried after the others, since those may have used up the
+// nonces they were waiting for; whatever is still in the future at the end is returned
+fn apply_with_hold_back<X: Clone>(
+    items: Vec<X>,
+    mut apply: impl FnMut(X) -> Result<(), InvalidTransaction>,
+) -> Vec<X> {
+    let mut pending = items;
+    loop {
+        let mut held_back = Vec::new();
+        let mut progress = false;
+        for item in pending {
+            match apply(item.clone()) {
+                Ok(()) => progress = true,
+                Err(InvalidTransaction::Future) => held_back.push(item),
+                Err(_) => {}
+            }
+        }
+
+        if !progress || held_back.is_empty() {
+            return held_back;
+        }
+        pending = held_back;
+    }
+}
+
+// Milliseconds since the unix epoch, used as the block timestamp
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// The scripted walkthrough of the runtime, run on a fresh in-memory chain
+fn run_demo() {
+    // The development accounts are funded at genesis, in block 0
+    // The demo registers its validators in a block of their own, so genesis leaves them out
+    let mut genesis = genesis::GenesisConfig::development();
+    genesis.staking.validators.clear();
+    let mut runtime = genesis.build().expect("the development genesis config is valid");
 
     println!("🚀 Starting Blockchain Simulation with Generics");
     println!("===============================================");
@@ -412,33 +1188,10 @@ fn main() {
 
     // Genesis Block - Initial setup
     println!("\n🌱 === GENESIS BLOCK ===");
-    let genesis_transactions = vec![
-        Transaction::SetBalance {
-            who: cheryl.clone(),
-            amount: 10000,  // This is of type u128 (our Balance type)
-        },
-        Transaction::SetBalance {
-            who: femi.clone(),
-            amount: 500,
-        },
-        Transaction::SetBalance {
-            who: temi.clone(),
-            amount: 300,
-        },
-        Transaction::SetBalance {
-            who: nathaniel.clone(),
-            amount: 200,
-        },
-        Transaction::SetBalance {
-            who: faith.clone(),
-            amount: 100,
-        },
-    ];
-
-    let genesis_result = runtime.create_block(genesis_transactions);
     println!(
-        "Genesis block created with {} transactions",
-        genesis_result.transaction_count
+        "Genesis block built with {} funded accounts (hash: {})",
+        genesis.accounts.len(),
+        hex_encode(&runtime.genesis_hash()[..8])
     );
 
     // Block 1 - Transfers
@@ -447,38 +1200,61 @@ fn main() {
             from: cheryl.clone(),
             to: faith.clone(),
             amount: 50,
+            nonce: 0,
         },
         Transaction::Transfer {
             from: cheryl.clone(),
             to: nathaniel.clone(),
             amount: 70,
+            nonce: 1,
         },
         Transaction::Transfer {
             from: femi.clone(),
             to: temi.clone(),
             amount: 100,
+            nonce: 0,
         },
     ];
 
     let block_1_result = runtime.create_block(block_1_transactions);
     println!("Block 1 completed with {} successful transactions", block_1_result.transaction_count);
 
+    // The chain keeps every block with a receipt for each of its transactions
+    if let Some(block) = runtime.history.block_by_hash(&block_1_result.block_hash) {
+        for (index, receipt) in block.receipts.iter().enumerate() {
+            let outcome = match &receipt.result {
+                Ok(()) => "succeeded".to_string(),
+                Err(e) => format!("failed: {}", e),
+            };
+            println!(
+                "🧾 Transaction #{} {} (fee paid: {}, {} events)",
+                index,
+                outcome,
+                receipt.fee_paid,
+                receipt.events.len()
+            );
+        }
+    }
+
     // Block 2 - More transfers
     let block_2_transactions = vec![
         Transaction::Transfer {
             from: cheryl.clone(),
             to: femi.clone(),
             amount: 100,
+            nonce: 2,
         },
         Transaction::Transfer {
             from: faith.clone(),
             to: temi.clone(),
             amount: 20,
+            nonce: 0,
         },
         Transaction::Transfer {
             from: nathaniel.clone(),
             to: femi.clone(),
             amount: 30,
+            nonce: 0,
         },
     ];
 
@@ -491,16 +1267,19 @@ fn main() {
             from: cheryl.clone(),
             to: nathaniel.clone(),
             amount: 9200, // Should fail - insufficient balance
+            nonce: 3,
         },
         Transaction::Transfer {
             from: temi.clone(),
             to: faith.clone(),
             amount: 50,
+            nonce: 0,
         },
         Transaction::Transfer {
             from: femi.clone(),
             to: cheryl.clone(),
             amount: 200,
+            nonce: 1,
         },
     ];
 
@@ -513,10 +1292,12 @@ fn main() {
         Transaction::AddValidator {
             validator: "cheryl".to_string(),
             commission: 5, // 5% commission
+            nonce: 4,
         },
         Transaction::AddValidator {
             validator: "nathaniel".to_string(),
             commission: 10, // 10% commission
+            nonce: 1,
         },
     ];
     let block_4_result = runtime.create_block(block_4_transactions);
@@ -528,11 +1309,13 @@ fn main() {
             who: "femi".to_string(),
             amount: 200,
             validator: "cheryl".to_string(),
+            nonce: 0,
         },
         Transaction::Stake {
             who: "temi".to_string(),
             amount: 150,
             validator: "nathaniel".to_string(),
+            nonce: 1,
         },
     ];
     let block_5_result = runtime.create_block(block_5_transactions);
@@ -548,9 +1331,11 @@ fn main() {
     let block_11_transactions = vec![
         Transaction::ClaimRewards {
             who: "femi".to_string(),
+            nonce: 1,
         },
         Transaction::ClaimRewards {
             who: "temi".to_string(),
+            nonce: 2,
         },
     ];
     let block_11_result = runtime.create_block(block_11_transactions);
@@ -560,6 +1345,7 @@ fn main() {
     let block_12_transactions = vec![
         Transaction::Unstake {
             who: "femi".to_string(),
+            nonce: 2,
         },
     ];
     let block_12_result = runtime.create_block(block_12_transactions);
@@ -575,9 +1361,11 @@ fn main() {
     let block_21_transactions = vec![
         Transaction::Unstake {
             who: "femi".to_string(),
+            nonce: 3,
         },
         Transaction::Unstake {
             who: "temi".to_string(),
+            nonce: 3,
         },
     ];
     let block_21_result = runtime.create_block(block_21_transactions);
@@ -586,28 +1374,275 @@ fn main() {
     // Example using the support framework (like the main branch)
     println!("\n🔧 === USING SUPPORT FRAMEWORK ===");
     
-    // Create a block using the support framework types
-    let support_block = types::Block {
-        header: support::Header { 
-            block_number: runtime.system.block_number() + 1 
-        },
-        extrinsics: vec![
-            support::Extrinsic {
-                caller: cheryl.clone(),
-                call: RuntimeCall::Balances(balances::Call::Transfer {
-                    to: faith.clone(),
-                    amount: 25,
-                }),
-            },
-            support::Extrinsic {
-                caller: "nathaniel".to_string(),
-                call: RuntimeCall::Staking(staking::Call::ClaimRewards),
-            },
-        ],
+    // Two other nodes that have followed the chain up to this point
+    let mut honest_node = runtime.clone();
+    let mut suspicious_node = runtime.clone();
+
+    // Author a block using the support framework types
+    // Every extrinsic is signed by its caller's key over the call, nonce and genesis hash
+    let genesis_hash = runtime.genesis_hash();
+    let cheryl_nonce = runtime.system.account_nonce(&cheryl);
+    let extrinsics = vec![
+        // cheryl's second transfer arrives first - it is held back until the first is applied
+        support::Extrinsic::new_signed(
+            cheryl.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: temi.clone(),
+                amount: 10,
+            }),
+            cheryl_nonce + 1,
+            &crypto::Pair::dev(&cheryl),
+            genesis_hash,
+        ),
+        support::Extrinsic::new_signed(
+            cheryl.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: faith.clone(),
+                amount: 25,
+            }),
+            cheryl_nonce,
+            &crypto::Pair::dev(&cheryl),
+            genesis_hash,
+        ),
+        support::Extrinsic::new_signed(
+            nathaniel.clone(),
+            RuntimeCall::Staking(staking::Call::ClaimRewards),
+            runtime.system.account_nonce(&nathaniel),
+            &crypto::Pair::dev(&nathaniel),
+            genesis_hash,
+        ),
+        // faith tries to spend cheryl's funds using faith's own key - this is dropped
+        support::Extrinsic::new_signed(
+            cheryl.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: faith.clone(),
+                amount: 5000,
+            }),
+            cheryl_nonce + 2,
+            &crypto::Pair::dev(&faith),
+            genesis_hash,
+        ),
+        // Replaying cheryl's first transfer is rejected - its nonce has been used
+        support::Extrinsic::new_signed(
+            cheryl.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: faith.clone(),
+                amount: 25,
+            }),
+            cheryl_nonce,
+            &crypto::Pair::dev(&cheryl),
+            genesis_hash,
+        ),
+        // A nonce far in the future cannot be included yet
+        support::Extrinsic::new_signed(
+            temi.clone(),
+            RuntimeCall::Staking(staking::Call::ClaimRewards),
+            runtime.system.account_nonce(&temi) + 5,
+            &crypto::Pair::dev(&temi),
+            genesis_hash,
+        ),
+    ];
+    let (support_block, held_back) = runtime.author_block(extrinsics);
+    println!(
+        "Authored block #{} (parent: {}, state root: {}, extrinsics root: {})",
+        support_block.header.block_number,
+        hex_encode(&support_block.header.parent_hash[..8]),
+        hex_encode(&support_block.header.state_root[..8]),
+        hex_encode(&support_block.header.extrinsics_root[..8]),
+    );
+    println!(
+        "{} extrinsics included, {} held back for a later block",
+        support_block.extrinsics.len(),
+        held_back.len()
+    );
+
+    // Import the block on another node - it re-executes and checks every header field
+    // The block travels as bytes - the same encoding its hashes are computed over
+    let block_bytes = support_block.encode();
+    let received_block = types::Block::decode_all(&block_bytes).expect("Block decoding failed");
+    println!("📦 Block encoded into {} bytes", block_bytes.len());
+    honest_node
+        .execute_block(received_block)
+        .expect("Block execution failed");
+    println!(
+        "✅ Block imported, hashes match: {}",
+        honest_node.system.current_block_hash() == runtime.system.current_block_hash()
+    );
+
+    // A block whose header lies about the resulting state is rejected
+    let mut tampered_block = support_block;
+    tampered_block.header.state_root = [0u8; 32];
+    if let Err(e) = suspicious_node.execute_block(tampered_block) {
+        println!("❌ Tampered block rejected: {}", e);
+    }
+
+    // Transactions can also be submitted to a pool at any time
+    // The block author pulls the best ready ones from it
+    println!("\n🏊 === TRANSACTION POOL ===");
+    let mut pool = types::TransactionPool::new();
+    let femi_nonce = runtime.system.account_nonce(&femi);
+    let femi_transfer = |nonce| {
+        support::Extrinsic::new_signed(
+            femi.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: temi.clone(),
+                amount: 5,
+            }),
+            nonce,
+            &crypto::Pair::dev(&femi),
+            genesis_hash,
+        )
     };
+    let submissions = vec![
+        femi_transfer(femi_nonce),
+        // Waits in the pool until Femi's nonce catches up
+        femi_transfer(femi_nonce + 2),
+        // The tip puts nathaniel's transfer ahead of Femi's
+        support::Extrinsic::new_signed_with_tip(
+            nathaniel.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: faith.clone(),
+                amount: 5,
+            }),
+            runtime.system.account_nonce(&nathaniel),
+            3,
+            &crypto::Pair::dev(&nathaniel),
+            genesis_hash,
+        ),
+        // Submitting the same transaction twice is refused
+        femi_transfer(femi_nonce),
+    ];
+    for extrinsic in submissions {
+        let caller = extrinsic.caller.clone();
+        match pool.submit(&runtime, extrinsic) {
+            Ok(status) => println!("📥 Transaction from {} is {:?}", caller, status),
+            Err(e) => println!("🚫 Transaction from {} refused: {}", caller, e),
+        }
+    }
 
-    // Execute the block
-    runtime.execute_block(support_block).expect("Block execution failed");
+    let pool_block = runtime.author_block_from_pool(&mut pool);
+    println!(
+        "Authored block #{} with {} extrinsics from the pool, {} left waiting",
+        pool_block.header.block_number,
+        pool_block.extrinsics.len(),
+        pool.len()
+    );
+    if !pool.is_empty() {
+        println!("⏳ The rest of the pool waits for a later block");
+    }
+
+    // Two authors can build on the same parent at once - a node keeps every fork
+    // in a block tree and follows the longest chain
+    println!("\n🍴 === FORKS ===");
+    let mut tree = blocktree::BlockTree::new(runtime.clone());
+    let mut author_a = runtime.clone();
+    let mut author_b = runtime.clone();
+    let cheryl_transfer = |author: &Runtime, amount| {
+        support::Extrinsic::new_signed(
+            cheryl.clone(),
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: faith.clone(),
+                amount,
+            }),
+            author.system.account_nonce(&cheryl),
+            &crypto::Pair::dev(&cheryl),
+            genesis_hash,
+        )
+    };
+    let (fork_a, _) = author_a.author_block(vec![cheryl_transfer(&author_a, 1)]);
+    let (fork_b1, _) = author_b.author_block(vec![cheryl_transfer(&author_b, 2)]);
+    let (fork_b2, _) = author_b.author_block(vec![]);
+    for block in [fork_a, fork_b1, fork_b2] {
+        let number = block.header.block_number;
+        let hash = hex_encode(&block.header.hash()[..8]);
+        match tree.import(block) {
+            Ok(blocktree::ImportOutcome::Reorganized(route)) => {
+                println!(
+                    "🔀 Block #{} ({}) is now best: {} blocks retracted, {} enacted",
+                    number,
+                    hash,
+                    route.retracted.len(),
+                    route.enacted.len()
+                );
+                // Extrinsics of retracted blocks go back to the pool, unless the new chain used their nonce
+                for retracted in &route.retracted {
+                    let extrinsics = tree.block(retracted).map(|block| block.extrinsics.clone());
+                    for extrinsic in extrinsics.unwrap_or_default() {
+                        match pool.submit(tree.best_state(), extrinsic) {
+                            Ok(status) => println!("  ↩️  Extrinsic returned to the pool: {:?}", status),
+                            Err(e) => println!("  🚫 Extrinsic not returned to the pool: {}", e),
+                        }
+                    }
+                }
+            }
+            Ok(outcome) => println!("📥 Block #{} ({}) imported: {:?}", number, hash, outcome),
+            Err(e) => println!("❌ Block #{} ({}) rejected: {}", number, hash, e),
+        }
+    }
+    println!(
+        "Best block: #{} ({}) out of {} forks",
+        tree.best_number(),
+        hex_encode(&tree.best_hash()[..8]),
+        tree.leaves().len()
+    );
+
+    // An account must hold at least the existential deposit - one that falls below it is reaped
+    println!("\n🪦 === EXISTENTIAL DEPOSIT ===");
+    let mut reaping = runtime.clone();
+    reaping.balances.set_existential_deposit(50);
+    let faith_nonce = reaping.system.account_nonce(&faith);
+    let amount = reaping.balances.balance(&faith) - 10;
+    let faith_sends = |call, nonce| {
+        support::Extrinsic::new_signed(faith.clone(), call, nonce, &crypto::Pair::dev(&faith), genesis_hash)
+    };
+    reaping.author_block(vec![
+        // Refused: it would leave faith with 10 tokens, below the deposit of 50
+        faith_sends(
+            RuntimeCall::Balances(balances::Call::TransferKeepAlive { to: cheryl.clone(), amount }),
+            faith_nonce,
+        ),
+        // Goes through, and the 10 tokens left are burned along with faith's account
+        faith_sends(
+            RuntimeCall::Balances(balances::Call::Transfer { to: cheryl.clone(), amount }),
+            faith_nonce + 1,
+        ),
+    ]);
+    reaping.print_events();
+    println!(
+        "faith now holds {} tokens with nonce {}",
+        reaping.balances.balance(&faith),
+        reaping.system.account_nonce(&faith)
+    );
+
+    // Vested funds arrive locked and are released a little every block
+    println!("\n⏳ === VESTING ===");
+    let mut releasing = runtime.clone();
+    let schedule = vesting::VestingInfo {
+        locked: 100,
+        per_block: 25,
+        starting_block: releasing.system.block_number() + 2,
+    };
+    let cheryl_nonce = releasing.system.account_nonce(&cheryl);
+    releasing.author_block(vec![support::Extrinsic::new_signed(
+        cheryl.clone(),
+        RuntimeCall::Vesting(vesting::Call::VestedTransfer { target: faith.clone(), schedule }),
+        cheryl_nonce,
+        &crypto::Pair::dev(&cheryl),
+        genesis_hash,
+    )]);
+    releasing.print_events();
+    // Nothing is released until the starting block, then 25 tokens a block
+    for _ in 0..3 {
+        releasing.author_block(Vec::new());
+    }
+    let faith_nonce = releasing.system.account_nonce(&faith);
+    releasing.author_block(vec![faith_sends(RuntimeCall::Vesting(vesting::Call::Vest), faith_nonce)]);
+    releasing.print_events();
+    println!(
+        "faith holds {} tokens, {} of them usable",
+        releasing.balances.balance(&faith),
+        releasing.balances.usable_balance(&faith)
+    );
 
     // Print final state
     runtime.print_blockchain_state();
@@ -615,16 +1650,34 @@ fn main() {
     // Verify blockchain integrity
     runtime.verify_chain_integrity();
 
+    // Save the chain to disk and start a fresh node from the saved copy
+    println!("💾 === PERSISTENCE ===");
+    let data_dir = std::env::temp_dir().join("rust-state-machine-demo");
+    let saved = store::Store::open(&data_dir).and_then(|store| {
+        store.save(&runtime)?;
+        store.load::<Runtime>()
+    });
+    match saved {
+        Ok(Some(restored)) => println!(
+            "✅ Chain saved to {} and restored at block #{}, state roots match: {}",
+            data_dir.display(),
+            restored.system.block_number(),
+            restored.state_root() == runtime.state_root()
+        ),
+        Ok(None) => println!("❌ Saved chain was not found"),
+        Err(e) => println!("❌ Could not save the chain: {}", e),
+    }
+
     // Demonstrate hash relationships
     println!("🔗 === BLOCK HASH RELATIONSHIPS ===");
     for block_num in 0..=runtime.system.block_number().min(10) { // Limit output for readability
         if let Some(hash) = runtime.system.get_block_hash(block_num) {
             println!("Block #{}: {}", block_num, hex_encode(&hash[..16]));
 
-            if block_num > 0 {
-                if let Some(parent_hash) = runtime.system.get_block_hash(block_num - 1) {
-                    println!("  └─ Parent: {}", hex_encode(&parent_hash[..16]));
-                }
+            if block_num > 0
+                && let Some(parent_hash) = runtime.system.get_block_hash(block_num - 1)
+            {
+                println!("  └─ Parent: {}", hex_encode(&parent_hash[..16]));
             }
         }
     }
@@ -643,4 +1696,900 @@ fn main() {
     );
 
 
-}
\ No newline at end of file
+}
+
+fn main() {
+    let cli = <cli::Cli as clap::Parser>::parse();
+    if let Err(e) = cli::run(cli) {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer_call(to: &str, amount: u128) -> RuntimeCall {
+        RuntimeCall::Balances(balances::Call::Transfer {
+            to: to.to_string(),
+            amount,
+        })
+    }
+
+    // A transfer signed with the sender's development key
+    fn transfer(runtime: &Runtime, from: &str, to: &str, amount: u128) -> types::Extrinsic {
+        transfer_with_nonce(runtime, from, to, amount, 0)
+    }
+
+    fn transfer_with_nonce(
+        runtime: &Runtime,
+        from: &str,
+        to: &str,
+        amount: u128,
+        nonce: u32,
+    ) -> types::Extrinsic {
+        support::Extrinsic::new_signed(
+            from.to_string(),
+            transfer_call(to, amount),
+            nonce,
+            &crypto::Pair::dev(from),
+            runtime.genesis_hash(),
+        )
+    }
+
+    // A runtime with a funded account, plus a copy of it to import blocks into
+    fn author_and_importer() -> (Runtime, Runtime) {
+        let mut author = Runtime::new();
+        let alice = "alice".to_string();
+        author.balances.mint(&alice, 1000).unwrap();
+        author.system.set_account_key(&alice, crypto::Pair::dev("alice").public());
+        let importer = author.clone();
+        (author, importer)
+    }
+
+    #[test]
+    fn authored_block_is_imported() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let (block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+        assert_eq!(block.header.block_number, 1);
+        assert_eq!(block.header.parent_hash, author.genesis_hash());
+
+        assert_eq!(importer.execute_block(block), Ok(()));
+        assert_eq!(importer.balances.balance(&"bob".to_string()), 100);
+        assert_eq!(importer.system.current_block_hash(), author.system.current_block_hash());
+
+        // The next block must build on the one we just imported
+        let (next, _) = author.author_block(vec![]);
+        assert_eq!(next.header.parent_hash, importer.system.current_block_hash().unwrap());
+        assert_eq!(importer.execute_block(next), Ok(()));
+    }
+
+    #[test]
+    fn block_with_wrong_parent_hash_is_rejected() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let (mut block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+        block.header.parent_hash = [1u8; 32];
+
+        assert_eq!(
+            importer.execute_block(block),
+            Err("parent hash does not match the current chain head")
+        );
+        assert_eq!(importer.system.block_number(), 0);
+    }
+
+    #[test]
+    fn block_with_wrong_extrinsics_root_is_rejected() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let (mut block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+        block.extrinsics = vec![transfer(&author, "alice", "bob", 900)];
+
+        assert_eq!(
+            importer.execute_block(block),
+            Err("extrinsics root does not match the block body")
+        );
+    }
+
+    #[test]
+    fn block_with_wrong_state_root_is_rejected_without_side_effects() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let (mut block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+        block.header.state_root = [0u8; 32];
+
+        assert_eq!(
+            importer.execute_block(block),
+            Err("state root does not match after execution")
+        );
+        assert_eq!(importer.system.block_number(), 0);
+        assert_eq!(importer.balances.balance(&"alice".to_string()), 1000);
+        assert_eq!(importer.balances.balance(&"bob".to_string()), 0);
+    }
+
+    #[test]
+    fn unsigned_and_badly_signed_extrinsics_are_rejected() {
+        let (mut author, mut importer) = author_and_importer();
+        let genesis_hash = author.genesis_hash();
+
+        let unsigned = support::Extrinsic {
+            caller: "alice".to_string(),
+            call: transfer_call("bob", 100),
+            nonce: 0,
+            tip: 0,
+            signature: None,
+        };
+        // bob signs a transfer out of alice's account
+        let wrong_key = support::Extrinsic::new_signed(
+            "alice".to_string(),
+            transfer_call("bob", 100),
+            0,
+            &crypto::Pair::dev("bob"),
+            genesis_hash,
+        );
+        // alice's signature, but the amount was changed afterwards
+        let mut tampered = transfer(&author, "alice", "bob", 1);
+        tampered.call = transfer_call("bob", 900);
+        // alice's signature for a different chain
+        let other_chain = support::Extrinsic::new_signed(
+            "alice".to_string(),
+            transfer_call("bob", 100),
+            0,
+            &crypto::Pair::dev("alice"),
+            [9u8; 32],
+        );
+
+        assert_eq!(author.check_signature(&unsigned), Err(InvalidTransaction::Unsigned));
+        assert_eq!(author.check_signature(&wrong_key), Err(InvalidTransaction::WrongSigner));
+        assert_eq!(author.check_signature(&tampered), Err(InvalidTransaction::BadSignature));
+        assert_eq!(author.check_signature(&other_chain), Err(InvalidTransaction::BadSignature));
+
+        // The author leaves invalid extrinsics out of its block
+        let (block, _) = author.author_block(vec![unsigned.clone(), wrong_key, tampered, other_chain]);
+        assert!(block.extrinsics.is_empty());
+        assert_eq!(author.balances.balance(&"bob".to_string()), 0);
+
+        // An importer rejects a block containing one, leaving its state untouched
+        let mut bad_block = block.clone();
+        bad_block.extrinsics = vec![unsigned];
+        let hashes: Vec<[u8; 32]> = bad_block
+            .extrinsics
+            .iter()
+            .map(|extrinsic| support::blake2_256(&extrinsic.encode()))
+            .collect();
+        bad_block.header.extrinsics_root = system::Pallet::<Runtime>::extrinsics_root(&hashes);
+
+        assert_eq!(
+            importer.execute_block(bad_block),
+            Err("block contains an invalid extrinsic")
+        );
+        assert_eq!(importer.system.block_number(), 0);
+        assert_eq!(importer.execute_block(block), Ok(()));
+    }
+
+    #[test]
+    fn replayed_extrinsic_is_rejected() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let first = transfer(&author, "alice", "bob", 100);
+        let (block, _) = author.author_block(vec![first.clone()]);
+        assert_eq!(importer.execute_block(block), Ok(()));
+        assert_eq!(author.system.account_nonce(&"alice".to_string()), 1);
+
+        // The author will not include the same extrinsic again
+        let (replay_block, held_back) = author.author_block(vec![first.clone()]);
+        assert!(replay_block.extrinsics.is_empty());
+        assert!(held_back.is_empty());
+        assert_eq!(author.balances.balance(&"bob".to_string()), 100);
+
+        // And an importer rejects a block that contains it
+        let mut bad_block = replay_block;
+        bad_block.extrinsics = vec![first];
+        bad_block.header.extrinsics_root = system::Pallet::<Runtime>::extrinsics_root(&[
+            support::blake2_256(&bad_block.extrinsics[0].encode()),
+        ]);
+        assert_eq!(
+            importer.execute_block(bad_block),
+            Err("block contains an invalid extrinsic")
+        );
+        assert_eq!(importer.balances.balance(&"bob".to_string()), 100);
+    }
+
+    #[test]
+    fn future_nonces_are_held_back() {
+        let (mut author, mut importer) = author_and_importer();
+
+        let second = transfer_with_nonce(&author, "alice", "bob", 20, 1);
+        let first = transfer_with_nonce(&author, "alice", "bob", 10, 0);
+        let far_future = transfer_with_nonce(&author, "alice", "bob", 30, 5);
+
+        let (block, held_back) = author.author_block(vec![second, first, far_future.clone()]);
+
+        // The out-of-order extrinsic is applied once its predecessor has been
+        assert_eq!(block.extrinsics.len(), 2);
+        assert_eq!(block.extrinsics[0].nonce, 0);
+        assert_eq!(block.extrinsics[1].nonce, 1);
+        assert_eq!(author.system.account_nonce(&"alice".to_string()), 2);
+        assert_eq!(author.balances.balance(&"bob".to_string()), 30);
+
+        // The far future one stays out and untouched
+        assert_eq!(held_back.len(), 1);
+        assert_eq!(held_back[0].nonce, far_future.nonce);
+
+        assert_eq!(importer.execute_block(block), Ok(()));
+    }
+
+    #[test]
+    fn create_block_checks_transaction_nonces() {
+        let mut runtime = Runtime::new();
+        runtime.balances.set_balance(&"alice".to_string(), 1000).unwrap();
+        runtime.system.set_account_key(&"alice".to_string(), crypto::Pair::dev("alice").public());
+        let transfer = |amount, nonce| Transaction::Transfer {
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            amount,
+            nonce,
+        };
+
+        let result = runtime.create_block(vec![
+            transfer(20, 1),
+            transfer(10, 0),
+            transfer(5, 0),
+            transfer(30, 7),
+        ]);
+
+        assert_eq!(result.transaction_count, 2);
+        assert_eq!(result.failed_transactions.len(), 1); // The reused nonce 0
+        assert_eq!(result.held_transactions.len(), 1); // Nonce 7
+        assert_eq!(runtime.balances.balance(&"bob".to_string()), 30);
+        assert_eq!(runtime.system.account_nonce(&"alice".to_string()), 2);
+    }
+
+    #[test]
+    fn state_root_commits_to_every_pallet() {
+        let (node_a, mut node_b) = author_and_importer();
+        assert_eq!(node_a.state_root(), node_b.state_root());
+
+        node_b.system.inc_nonce(&"alice".to_string());
+        assert_ne!(node_a.state_root(), node_b.state_root());
+
+        let mut node_c = node_a.clone();
+        node_c.staking.add_validator("alice".to_string(), 5).unwrap();
+        assert_ne!(node_a.state_root(), node_c.state_root());
+
+        // Building the same state in a different order gives the same root
+        let mut node_d = Runtime::new();
+        node_d.staking.add_validator("alice".to_string(), 5).unwrap();
+        node_d.balances.set_balance(&"alice".to_string(), 1000).unwrap();
+        node_d.system.set_account_key(&"alice".to_string(), crypto::Pair::dev("alice").public());
+        assert_eq!(node_c.state_root(), node_d.state_root());
+    }
+
+    #[test]
+    fn failed_call_leaves_no_partial_state() {
+        let mut runtime = Runtime::new();
+        let alice = "alice".to_string();
+        runtime.balances.set_balance(&alice, 100).unwrap();
+        runtime.staking.add_validator("validator".to_string(), 5).unwrap();
+        let state_root = runtime.state_root();
+
+        let result: Result<(), &str> = with_transaction(&mut runtime, |runtime| {
+            runtime.balances.set_balance(&alice, 0).unwrap();
+            runtime.staking.add_validator("alice".to_string(), 5).unwrap();
+            runtime.system.inc_nonce(&alice);
+            Err("fails after writing to every pallet")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(runtime.balances.balance(&alice), 100);
+        assert_eq!(runtime.system.account_nonce(&alice), 0);
+        assert_eq!(runtime.state_root(), state_root);
+    }
+
+    #[test]
+    fn issuance_counts_every_token_held() {
+        let (mut runtime, _) = author_and_importer();
+        let alice = "alice".to_string();
+        runtime.staking.add_validator("validator".to_string(), 5).unwrap();
+        runtime.balances.set_transaction_fee(2);
+
+        // Staked tokens are locked in alice's balance; rewards are new tokens
+        runtime.stake(alice.clone(), 200, "validator".to_string()).unwrap();
+        assert_eq!(runtime.balances.usable_balance(&alice), 800);
+        runtime.claim_rewards(alice.clone()).unwrap();
+        assert_eq!(runtime.balances.total_issuance(), 1005);
+        assert_eq!(runtime.check_issuance(), Ok(()));
+
+        // Sending to oneself creates nothing, and a fee without a recipient is burned
+        runtime.balances.transfer(alice.clone(), alice.clone(), 300).unwrap();
+        assert_eq!(runtime.balances.balance(&alice), 1003);
+        assert_eq!(runtime.balances.total_issuance(), 1003);
+        assert_eq!(runtime.check_issuance(), Ok(()));
+
+        runtime.balances.set_total_issuance(1);
+        assert!(runtime.check_issuance().is_err());
+    }
+
+    #[test]
+    fn large_transfers_pay_a_share_of_their_amount() {
+        let (mut runtime, _) = author_and_importer();
+        runtime.balances.set_transaction_fee(2);
+        assert_eq!(runtime.balances.calculate_fee(999), 2);
+        assert_eq!(runtime.balances.calculate_fee(5_000), 50);
+        assert_eq!(runtime.balances.get_transfer_cost(50_000), Ok(50_100));
+
+        // The fee a transfer pays sets its priority in the pool
+        let extrinsic = transfer(&runtime, "alice", "bob", 900);
+        assert_eq!(runtime.validate_transaction(&extrinsic).unwrap().priority, 2);
+        runtime.balances.mint(&"alice".to_string(), 9_000).unwrap();
+        let extrinsic = transfer(&runtime, "alice", "bob", 4_000);
+        assert_eq!(runtime.validate_transaction(&extrinsic).unwrap().priority, 40);
+        runtime.author_block(vec![extrinsic]);
+        assert_eq!(runtime.balances.balance(&"alice".to_string()), 5_960);
+    }
+
+    #[test]
+    fn vested_funds_unlock_as_they_vest() {
+        let (mut runtime, _) = author_and_importer();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        let schedule = vesting::VestingInfo { locked: 300, per_block: 100, starting_block: 2 };
+        let vested_transfer = |schedule| RuntimeCall::Vesting(vesting::Call::VestedTransfer { target: bob.clone(), schedule });
+
+        // A transfer that fails takes its schedule with it
+        let too_much = vested_transfer(vesting::VestingInfo { locked: 5000, ..schedule.clone() });
+        assert!(with_transaction(&mut runtime, |runtime| runtime.dispatch(alice.clone(), too_much)).is_err());
+        assert!(runtime.vesting.schedules(&bob).is_empty());
+
+        // Dust cannot fill up bob's schedules
+        let dust = vested_transfer(vesting::VestingInfo { locked: 99, ..schedule.clone() });
+        assert_eq!(
+            runtime.dispatch(alice.clone(), dust),
+            Err(vesting::VestingError::AmountLow.into())
+        );
+        assert!(runtime.vesting.schedules(&bob).is_empty());
+
+        runtime.dispatch(alice.clone(), vested_transfer(schedule)).unwrap();
+        assert_eq!(runtime.balances.balance(&bob), 300);
+        assert_eq!(runtime.balances.usable_balance(&bob), 0);
+        assert_eq!(
+            runtime.balances.transfer(bob.clone(), alice.clone(), 1),
+            Err(balances::BalancesError::LiquidityRestrictions)
+        );
+
+        // Nothing is released before the starting block, then 100 tokens every block
+        for _ in 0..3 {
+            runtime.author_block(Vec::new());
+        }
+        runtime.dispatch(bob.clone(), RuntimeCall::Vesting(vesting::Call::Vest)).unwrap();
+        assert_eq!(runtime.balances.usable_balance(&bob), 100);
+
+        // Once everything has vested, the schedule and the lock are gone
+        runtime.author_block(Vec::new());
+        runtime.author_block(Vec::new());
+        runtime.dispatch(bob.clone(), RuntimeCall::Vesting(vesting::Call::Vest)).unwrap();
+        assert_eq!(runtime.balances.usable_balance(&bob), 300);
+        assert!(runtime.balances.locks(&bob).is_empty());
+        assert_eq!(
+            runtime.dispatch(bob.clone(), RuntimeCall::Vesting(vesting::Call::Vest)),
+            Err(vesting::VestingError::NotVesting.into())
+        );
+    }
+
+    #[test]
+    fn fees_cannot_drain_a_staker() {
+        let (mut author, mut importer) = author_and_importer();
+        let alice = "alice".to_string();
+        for runtime in [&mut author, &mut importer] {
+            runtime.balances.set_transaction_fee(5);
+            runtime.balances.set_existential_deposit(10);
+            runtime.staking.add_validator("bob".to_string(), 5).unwrap();
+            runtime.stake(alice.clone(), 990, "bob".to_string()).unwrap();
+        }
+
+        // alice's transfer leaves her stake untouched, but its fee and a tip would come out of it
+        let tipped = support::Extrinsic::new_signed_with_tip(
+            alice.clone(),
+            RuntimeCall::Staking(staking::Call::ClaimRewards),
+            1,
+            15,
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+        let (block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 10), tipped]);
+        importer.execute_block(block).unwrap();
+
+        // The stake is still all there, locked in alice's account
+        for runtime in [&author, &importer] {
+            assert!(runtime.balances.balance(&alice) >= 990);
+            assert_eq!(runtime.balances.locked_balance(&alice, balances::Reasons::Fee), 990);
+            assert_eq!(runtime.staking.get_stake_info(&alice).unwrap().staked_amount, 990);
+            assert_eq!(runtime.check_issuance(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn a_staker_can_always_unstake() {
+        let (mut author, mut importer) = author_and_importer();
+        let alice = "alice".to_string();
+        for runtime in [&mut author, &mut importer] {
+            runtime.balances.set_transaction_fee(5);
+            runtime.staking.add_validator("bob".to_string(), 5).unwrap();
+            runtime.stake(alice.clone(), 1000, "bob".to_string()).unwrap();
+        }
+
+        // Nothing is left to pay fees with, but unstaking costs nothing
+        let unstake = |runtime: &Runtime, nonce| {
+            support::Extrinsic::new_signed(
+                alice.clone(),
+                RuntimeCall::Staking(staking::Call::Unstake),
+                nonce,
+                &crypto::Pair::dev("alice"),
+                runtime.genesis_hash(),
+            )
+        };
+        assert_eq!(author.validate_transaction(&transfer(&author, "alice", "bob", 1)).err(), Some(InvalidTransaction::Payment));
+        // Only an unstake that can succeed is free, so a staker cannot spam failing ones
+        assert_eq!(author.validate_transaction(&unstake(&author, 0)).err(), Some(InvalidTransaction::Payment));
+        while !author.staking.can_unstake(&alice) {
+            let (block, _) = author.author_block(Vec::new());
+            importer.execute_block(block).unwrap();
+        }
+        let (block, _) = author.author_block(vec![unstake(&author, 0)]);
+        importer.execute_block(block).unwrap();
+
+        for runtime in [&author, &importer] {
+            assert!(runtime.staking.get_stake_info(&alice).is_none());
+            assert_eq!(runtime.balances.usable_balance(&alice), 1000);
+        }
+
+        // Once alice no longer stakes, unstaking pays the base fee like any other call
+        assert_eq!(author.extrinsic_fee(&unstake(&author, 1)), 5);
+    }
+
+    #[test]
+    fn reaped_accounts_lose_their_nonce() {
+        let (mut author, mut importer) = author_and_importer();
+        author.balances.set_existential_deposit(10);
+        importer.balances.set_existential_deposit(10);
+        let alice = "alice".to_string();
+
+        // alice sends all but 5 tokens away, so her account is reaped
+        let (block, _) = author.author_block(vec![
+            transfer(&author, "alice", "bob", 500),
+            transfer_with_nonce(&author, "alice", "bob", 495, 1),
+        ]);
+        assert_eq!(author.balances.balance(&alice), 0);
+        assert_eq!(author.system.nonce.get(&alice), None);
+        assert!(author.system.events().iter().any(|record| matches!(
+            &record.event,
+            RuntimeEvent::Balances(balances::BalancesEvent::AccountReaped { who }) if *who == alice
+        )));
+
+        importer.execute_block(block).unwrap();
+        assert_eq!(importer.state_root(), author.state_root());
+    }
+
+    #[test]
+    fn extrinsics_of_a_reaped_account_cannot_be_replayed() {
+        let (mut author, mut importer) = author_and_importer();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        for runtime in [&mut author, &mut importer] {
+            runtime.balances.set_existential_deposit(10);
+            runtime.system.set_account_key(&bob, crypto::Pair::dev("bob").public());
+        }
+
+        // alice's account is reaped, then bob funds it again
+        let first = transfer(&author, "alice", "bob", 500);
+        let (block, _) = author.author_block(vec![
+            first.clone(),
+            transfer_with_nonce(&author, "alice", "bob", 495, 1),
+        ]);
+        importer.execute_block(block).unwrap();
+        let (block, _) = author.author_block(vec![transfer(&author, "bob", "alice", 600)]);
+        importer.execute_block(block).unwrap();
+        assert_eq!(author.balances.balance(&alice), 600);
+
+        // alice carries on from her old nonce, so her first transfer cannot be replayed
+        assert_eq!(author.system.account_nonce(&alice), 2);
+        assert_eq!(author.validate_extrinsic(&first), Err(InvalidTransaction::Stale));
+        let (block, _) = author.author_block(vec![first]);
+        assert!(block.extrinsics.is_empty());
+        importer.execute_block(block).unwrap();
+        assert_eq!(author.balances.balance(&alice), 600);
+
+        let (block, _) = author.author_block(vec![transfer_with_nonce(&author, "alice", "bob", 100, 2)]);
+        assert_eq!(block.extrinsics.len(), 1);
+        importer.execute_block(block).unwrap();
+        assert_eq!(importer.system.account_nonce(&alice), 3);
+        assert_eq!(importer.state_root(), author.state_root());
+    }
+
+    #[test]
+    fn accounts_are_never_reaped_without_an_existential_deposit() {
+        let (mut author, _) = author_and_importer();
+        let alice = "alice".to_string();
+
+        // alice sends everything away, but keeps her account and her nonce
+        author.author_block(vec![
+            transfer(&author, "alice", "bob", 999),
+            transfer_with_nonce(&author, "alice", "bob", 1, 1),
+        ]);
+        assert_eq!(author.balances.balance(&alice), 0);
+        assert!(author.balances.all_balances().contains_key(&alice));
+        assert_eq!(author.system.nonce.get(&alice), Some(&2));
+        assert!(!author.system.events().iter().any(|record| matches!(
+            &record.event,
+            RuntimeEvent::Balances(balances::BalancesEvent::AccountReaped { .. })
+        )));
+    }
+
+    #[test]
+    fn events_are_recorded_per_extrinsic_and_reset_each_block() {
+        let (mut author, _) = author_and_importer();
+
+        let (block, _) = author.author_block(vec![
+            transfer(&author, "alice", "bob", 100),
+            transfer_with_nonce(&author, "alice", "bob", 5000, 1),
+        ]);
+        assert_eq!(block.extrinsics.len(), 2);
+
+        let events = author.system.events();
+        assert!(events.iter().all(|record| record.block_number == 1));
+        // The genesis balance setup happened outside any extrinsic
+        assert_eq!(events[0].extrinsic_index, None);
+        assert!(matches!(events[0].event, RuntimeEvent::Balances(balances::BalancesEvent::Minted { .. })));
+
+        assert_eq!(events[1].extrinsic_index, Some(0));
+        assert!(matches!(events[1].event, RuntimeEvent::Balances(balances::BalancesEvent::Transfer { amount: 100, .. })));
+        assert_eq!(events[2].extrinsic_index, Some(0));
+        assert!(matches!(events[2].event, RuntimeEvent::System(system::SystemEvent::ExtrinsicSuccess)));
+
+        // The failed transfer only leaves its fee payment and its failure behind
+        assert!(events[3..].iter().all(|record| record.extrinsic_index == Some(1)));
+        assert!(matches!(events[3].event, RuntimeEvent::Balances(balances::BalancesEvent::FeePaid { amount: 50, .. })));
+        assert!(matches!(events[4].event, RuntimeEvent::Balances(balances::BalancesEvent::Burned { amount: 50, .. })));
+        let insufficient = support::DispatchError::from(balances::BalancesError::InsufficientBalance);
+        assert!(matches!(
+            &events[5].event,
+            RuntimeEvent::System(system::SystemEvent::ExtrinsicFailed { error }) if *error == insufficient
+        ));
+        assert_eq!(events.len(), 6);
+
+        author.author_block(vec![]);
+        assert!(author.system.events().is_empty());
+    }
+
+    #[test]
+    fn blocks_stop_at_the_weight_limit() {
+        let (mut author, mut importer) = author_and_importer();
+        author.max_block_weight = 2 * balances::weights::TRANSFER;
+
+        let (block, leftover) = author.author_block(vec![
+            transfer_with_nonce(&author, "alice", "bob", 10, 0),
+            transfer_with_nonce(&author, "alice", "bob", 10, 1),
+            transfer_with_nonce(&author, "alice", "bob", 10, 2),
+        ]);
+        assert_eq!(block.extrinsics.len(), 2);
+        assert_eq!(leftover.len(), 1);
+        assert_eq!(leftover[0].nonce, 2);
+
+        // A node with a lower limit refuses the block
+        let mut strict_importer = importer.clone();
+        strict_importer.max_block_weight = balances::weights::TRANSFER;
+        assert_eq!(
+            strict_importer.execute_block(block.clone()),
+            Err("block contains an invalid extrinsic")
+        );
+        importer.max_block_weight = author.max_block_weight;
+        assert_eq!(importer.execute_block(block), Ok(()));
+
+        // The leftover fits in the next block
+        let (next, leftover) = author.author_block(leftover);
+        assert_eq!(next.extrinsics.len(), 1);
+        assert!(leftover.is_empty());
+    }
+
+    #[test]
+    fn extrinsics_that_cannot_pay_use_no_weight() {
+        let (mut author, _) = author_and_importer();
+        let charlie = "charlie".to_string();
+        author.balances.set_transaction_fee(5);
+        author.balances.set_fee_recipient(Some(charlie.clone()));
+        // With the issuance out of step, charlie's account gets too full to take any fee
+        author.balances.set_total_issuance(0);
+        author.balances.mint(&charlie, u128::MAX).unwrap();
+
+        assert_eq!(
+            author.apply_extrinsic(transfer(&author, "alice", "bob", 10)),
+            Err(InvalidTransaction::Payment)
+        );
+        assert_eq!(author.system.block_weight(), 0);
+        assert_eq!(author.balances.balance(&"alice".to_string()), 1000);
+    }
+
+    #[test]
+    fn create_block_reports_leftover_transactions() {
+        let mut runtime = Runtime::new();
+        runtime.max_block_weight = 2 * staking::weights::ADD_VALIDATOR;
+        for name in ["alice", "bob", "charlie"] {
+            runtime.system.set_account_key(&name.to_string(), crypto::Pair::dev(name).public());
+        }
+
+        let result = runtime.create_block(vec![
+            Transaction::AddValidator { validator: "alice".to_string(), commission: 1, nonce: 0 },
+            Transaction::AddValidator { validator: "bob".to_string(), commission: 2, nonce: 0 },
+            Transaction::AddValidator { validator: "charlie".to_string(), commission: 3, nonce: 0 },
+        ]);
+        assert_eq!(result.transaction_count, 2);
+        assert_eq!(result.block_weight, 2 * staking::weights::ADD_VALIDATOR);
+        assert_eq!(result.leftover_transactions.len(), 1);
+        assert!(!runtime.staking.is_validator(&"charlie".to_string()));
+    }
+
+    #[test]
+    fn create_block_checks_transaction_signatures() {
+        let mut runtime = Runtime::new();
+        runtime.system.set_account_key(&"alice".to_string(), crypto::Pair::dev("alice").public());
+
+        // mallory has no key, so nothing can be signed for them
+        let result = runtime.create_block(vec![
+            Transaction::AddValidator { validator: "mallory".to_string(), commission: 1, nonce: 0 },
+            Transaction::AddValidator { validator: "alice".to_string(), commission: 1, nonce: 0 },
+        ]);
+        assert_eq!(result.transaction_count, 1);
+        assert_eq!(result.failed_transactions.len(), 1);
+        assert_eq!(result.failed_transactions[0].1, InvalidTransaction::WrongSigner.to_string());
+        assert!(!runtime.staking.is_validator(&"mallory".to_string()));
+        assert_eq!(runtime.system.account_nonce(&"mallory".to_string()), 0);
+        assert!(runtime.staking.is_validator(&"alice".to_string()));
+    }
+
+    #[test]
+    fn signatures_cover_the_caller() {
+        let (author, _) = author_and_importer();
+        let alice_key = crypto::Pair::dev("alice").public();
+        let mut runtime = author.clone();
+        runtime.system.set_account_key(&"bob".to_string(), alice_key);
+
+        // alice's signature cannot be moved onto another account holding the same key
+        let mut moved = transfer(&author, "alice", "charlie", 10);
+        moved.caller = "bob".to_string();
+        assert_eq!(runtime.check_signature(&moved), Err(InvalidTransaction::BadSignature));
+    }
+
+    #[test]
+    fn accounts_created_after_genesis_sign_with_the_key_they_are_named_after() {
+        let (mut author, mut importer) = author_and_importer();
+        let pair = crypto::Pair::from_seed([7u8; 32]);
+        let account = key_account(&pair.public());
+
+        // alice funds an account nobody registered a key for
+        let (block, _) = author.author_block(vec![
+            transfer(&author, "alice", &account, 300),
+            transfer_with_nonce(&author, "alice", "zed", 300, 1),
+        ]);
+        assert_eq!(block.extrinsics.len(), 2);
+        assert_eq!(importer.execute_block(block), Ok(()));
+
+        // Only the holder of the key it is named after can spend from it
+        let spend = support::Extrinsic::new_signed(
+            account.clone(),
+            transfer_call("alice", 100),
+            0,
+            &pair,
+            author.genesis_hash(),
+        );
+        let stolen = support::Extrinsic::new_signed(
+            account.clone(),
+            transfer_call("alice", 100),
+            0,
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+        assert_eq!(author.check_signature(&stolen), Err(InvalidTransaction::WrongSigner));
+        let (block, _) = author.author_block(vec![stolen, spend]);
+        assert_eq!(block.extrinsics.len(), 1);
+        assert_eq!(importer.execute_block(block), Ok(()));
+        assert_eq!(importer.balances.balance(&account), 200);
+
+        // An account named anything else has no key behind it
+        let zed = support::Extrinsic::new_signed(
+            "zed".to_string(),
+            transfer_call("alice", 100),
+            0,
+            &pair,
+            author.genesis_hash(),
+        );
+        assert_eq!(author.check_signature(&zed), Err(InvalidTransaction::WrongSigner));
+    }
+
+    #[test]
+    fn pool_orders_by_tip_and_evicts_included_transactions() {
+        let (mut author, mut importer) = author_and_importer();
+        let bob = "bob".to_string();
+        author.balances.set_balance(&bob, 1000).unwrap();
+        author.system.set_account_key(&bob, crypto::Pair::dev("bob").public());
+        importer.balances.set_balance(&bob, 1000).unwrap();
+        importer.system.set_account_key(&bob, crypto::Pair::dev("bob").public());
+
+        let tipped = support::Extrinsic::new_signed_with_tip(
+            bob.clone(),
+            transfer_call("alice", 10),
+            0,
+            7,
+            &crypto::Pair::dev("bob"),
+            author.genesis_hash(),
+        );
+        let mut pool = types::TransactionPool::new();
+        assert_eq!(
+            pool.submit(&author, transfer_with_nonce(&author, "alice", "bob", 10, 1)),
+            Ok(txpool::TransactionStatus::Future)
+        );
+        assert_eq!(
+            pool.submit(&author, transfer(&author, "alice", "bob", 10)),
+            Ok(txpool::TransactionStatus::Ready)
+        );
+        assert_eq!(pool.submit(&author, tipped), Ok(txpool::TransactionStatus::Ready));
+
+        // bob's tip gets his transfer in first; alice's two follow in nonce order
+        let ready = pool.ready(&author);
+        assert_eq!(ready[0].caller, bob);
+        assert_eq!((ready[1].nonce, ready[2].nonce), (0, 1));
+
+        let block = author.author_block_from_pool(&mut pool);
+        assert_eq!(block.extrinsics.len(), 3);
+        assert!(pool.is_empty());
+        // The tip is charged on top of the transfer
+        assert_eq!(author.balances.balance(&bob), 1000 - 10 - 7 + 20);
+
+        assert_eq!(importer.execute_block(block), Ok(()));
+    }
+
+    #[test]
+    fn pool_refuses_transactions_that_cannot_pay_their_tip() {
+        let (author, _) = author_and_importer();
+        let broke = support::Extrinsic::new_signed_with_tip(
+            "alice".to_string(),
+            transfer_call("bob", 10),
+            0,
+            5000,
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+
+        let mut pool = types::TransactionPool::new();
+        assert_eq!(
+            pool.submit(&author, broke),
+            Err(txpool::PoolError::Invalid(InvalidTransaction::Payment))
+        );
+        assert_eq!(
+            pool.submit(&author, transfer_with_nonce(&author, "bob", "alice", 10, 0)),
+            Err(txpool::PoolError::Invalid(InvalidTransaction::WrongSigner))
+        );
+    }
+
+    #[test]
+    fn blocks_and_calls_round_trip_through_bytes() {
+        let (mut author, mut importer) = author_and_importer();
+        let stake = support::Extrinsic::new_signed_with_tip(
+            "alice".to_string(),
+            RuntimeCall::Staking(staking::Call::Stake { validator: "bob".to_string(), amount: 100 }),
+            1,
+            3,
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+        let (block, _) = author.author_block(vec![transfer(&author, "alice", "bob", 100), stake]);
+
+        let bytes = block.encode();
+        let decoded = types::Block::decode_all(&bytes).unwrap();
+        assert_eq!(decoded.encode(), bytes);
+        assert_eq!(decoded.header, block.header);
+        assert_eq!(decoded.extrinsics[1].tip, 3);
+        // Signatures still verify and the decoded block imports like the original
+        assert!(decoded.extrinsics[1].verify_signature(author.genesis_hash()).is_some());
+        assert_eq!(importer.execute_block(decoded), Ok(()));
+
+        for call in [
+            RuntimeCall::Staking(staking::Call::AddValidator { validator: "v".to_string(), commission: 5 }),
+            RuntimeCall::Staking(staking::Call::Unstake),
+            RuntimeCall::Staking(staking::Call::ClaimRewards),
+        ] {
+            assert_eq!(RuntimeCall::decode_all(&call.encode()).unwrap().encode(), call.encode());
+        }
+        assert_eq!(RuntimeCall::decode_all(&[3]).unwrap_err(), DecodeError::InvalidVariant(3));
+        assert!(types::Block::decode_all(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn saved_chain_is_restored_and_keeps_going() {
+        let (mut author, _) = author_and_importer();
+        author.system.set_account_key(&"bob".to_string(), crypto::Pair::dev("bob").public());
+        author.staking.add_validator("bob".to_string(), 5).unwrap();
+        author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = store::Store::open(dir.path()).unwrap();
+        store.save(&author).unwrap();
+        let mut restored: Runtime = store.load().unwrap().unwrap();
+
+        assert_eq!(restored.state_root(), author.state_root());
+        assert_eq!(restored.system.block_number(), 1);
+        assert_eq!(restored.system.all_block_hashes(), author.system.all_block_hashes());
+        assert_eq!(restored.system.get_header(1), author.system.get_header(1));
+        assert_eq!(restored.genesis_hash(), author.genesis_hash());
+
+        // The restored node follows the chain the original keeps building
+        let (block, _) = author.author_block(vec![transfer_with_nonce(&author, "alice", "bob", 5, 1)]);
+        assert_eq!(restored.execute_block(block), Ok(()));
+        assert_eq!(restored.system.current_block_hash(), author.system.current_block_hash());
+    }
+
+    #[test]
+    fn state_saved_with_an_old_layout_is_migrated() {
+        let (mut author, _) = author_and_importer();
+        author.author_block(vec![transfer(&author, "alice", "bob", 100)]);
+        author.staking.add_validator("validator".to_string(), 5).unwrap();
+        author.stake("alice".to_string(), 200, "validator".to_string()).unwrap();
+
+        // Save the system pallet the way it was stored before it was made generic
+        let mut state = VersionedState::decode_all(&author.encode()).unwrap();
+        let old_system = (author.system.block_number, (author.system.nonce.clone(), author.system.block_hashes.clone()));
+        state.pallets.insert("System".to_string(), (0, old_system.encode()));
+        // ...and the balances pallet as it was before it had an existential deposit, tracked
+        // issuance, reserves or locks - staked funds were only in the staking pallet then
+        let mut old_free = author.balances.all_balances().clone();
+        *old_free.get_mut("alice").unwrap() -= 200;
+        let old_balances = (old_free, (0u128, None::<String>));
+        state.pallets.insert("Balances".to_string(), (1, old_balances.encode()));
+        // Runtimes before spec version 3 had no vesting pallet at all
+        state.pallets.remove("Vesting");
+        state.spec_version = 2;
+
+        let migrated = Runtime::decode_all(&state.encode()).unwrap();
+        assert_eq!(migrated.system.block_number(), 1);
+        assert_eq!(migrated.system.account_nonce(&"alice".to_string()), 1);
+        assert_eq!(migrated.system.all_block_hashes(), author.system.all_block_hashes());
+        assert_eq!(migrated.balances.balance(&"bob".to_string()), 100);
+        assert_eq!(migrated.balances.existential_deposit(), 0);
+        // alice's stake is back in her balance, locked
+        assert_eq!(migrated.balances.balance(&"alice".to_string()), 900);
+        assert_eq!(migrated.balances.usable_balance(&"alice".to_string()), 700);
+        assert_eq!(migrated.balances.reserved_balance(&"alice".to_string()), 0);
+        assert_eq!(migrated.balances.total_issuance(), 1000);
+        assert!(migrated.balances.events.is_empty());
+        assert!(migrated.vesting.vesting.is_empty());
+        // Saving it again writes the current layout
+        let saved = VersionedState::decode_all(&migrated.encode()).unwrap();
+        assert_eq!(saved.pallets["System"].0, system::Pallet::<Runtime>::STORAGE_VERSION);
+
+        // State saved by a newer runtime is refused rather than misread
+        state.spec_version = SPEC_VERSION + 1;
+        assert!(matches!(Runtime::decode_all(&state.encode()), Err(DecodeError::Invalid(_))));
+    }
+
+    #[test]
+    fn failed_extrinsic_uses_its_nonce_and_pays_its_fee() {
+        let (mut author, _) = author_and_importer();
+        let alice = "alice".to_string();
+
+        // Alice cannot afford this, so the transfer fails but stays in the block,
+        // and its fee of 1% and tip are kept
+        let failing = support::Extrinsic::new_signed_with_tip(
+            alice.clone(),
+            transfer_call("bob", 5000),
+            0,
+            7,
+            &crypto::Pair::dev("alice"),
+            author.genesis_hash(),
+        );
+        let (block, _) = author.author_block(vec![failing]);
+        assert_eq!(block.extrinsics.len(), 1);
+        assert_eq!(author.system.account_nonce(&alice), 1);
+        assert_eq!(author.balances.balance(&alice), 1000 - 50 - 7);
+        assert_eq!(author.check_issuance(), Ok(()));
+        assert_eq!(author.balances.balance(&"bob".to_string()), 0);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+// Runtime metadata: a description of everything a client needs to talk to the runtime
+// without reading its source - the pallets, the calls they accept and how to encode them,
+// the events they emit and the errors they can fail with.
+//
+// Indices are the variant indices used by the encoding, so a client can build an
+// encoded call from the metadata alone: the pallet's call index, then the call's
+// index, then its fields in order. Field types name an entry of `types` or a primitive
+//
+// Exported as JSON with camelCase keys, like the RPC responses
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeMetadata {
+    pub spec_name: &'static str,
+    pub spec_version: u32,
+    pub types: BTreeMap<&'static str, &'static str>, // Runtime type name -> its encoded type
+    pub extrinsic: ExtrinsicMetadata,
+    pub pallets: Vec<PalletMetadata>,
+}
+
+// How an extrinsic is laid out and what its signature covers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtrinsicMetadata {
+    pub fields: Vec<FieldMetadata>,
+    pub signed_payload: Vec<&'static str>, // Encoded one after another and signed with ed25519
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PalletMetadata {
+    pub name: &'static str,
+    pub call_index: Option<u8>, // Index in `RuntimeCall`, None for pallets without calls
+    pub event_index: u8,        // Index in `RuntimeEvent`
+    pub calls: Vec<VariantMetadata>,
+    pub events: Vec<VariantMetadata>,
+    pub errors: Vec<VariantMetadata>,
+}
+
+// A call, event or error
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantMetadata {
+    pub index: u8,
+    pub name: String,
+    pub fields: Vec<FieldMetadata>,
+    pub docs: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldMetadata {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+}
+
+// Implemented by every pallet to describe what it adds to the runtime
+pub trait PalletInfo {
+    fn calls() -> Vec<VariantMetadata> {
+        Vec::new()
+    }
+    fn events() -> Vec<VariantMetadata>;
+    fn errors() -> Vec<VariantMetadata> {
+        Vec::new()
+    }
+}
+
+impl PalletMetadata {
+    // Describe pallet `P`, placed at these indices of the runtime's call and event enums
+    pub fn of<P: PalletInfo>(name: &'static str, call_index: Option<u8>, event_index: u8) -> Self {
+        Self {
+            name,
+            call_index,
+            event_index,
+            calls: P::calls(),
+            events: P::events(),
+            errors: P::errors(),
+        }
+    }
+}
+
+// Describe a call or event variant; `fields` are (name, type) pairs in encoding order
+pub fn variant(index: u8, name: &str, fields: &[(&'static str, &'static str)], docs: &str) -> VariantMetadata {
+    VariantMetadata {
+        index,
+        name: name.to_string(),
+        fields: fields.iter().map(|&(name, ty)| FieldMetadata { name, ty }).collect(),
+        docs: docs.to_string(),
+    }
+}
+
+// Describe a pallet's errors from every variant of its error type, in declaration order
+// The names match the `error` of a `DispatchError::Module` and the docs its `message`
+pub fn errors<E: std::fmt::Debug + std::fmt::Display>(variants: &[E]) -> Vec<VariantMetadata> {
+    variants
+        .iter()
+        .enumerate()
+        .map(|(index, error)| VariantMetadata {
+            index: index as u8,
+            name: format!("{:?}", error),
+            fields: Vec::new(),
+            docs: error.to_string(),
+        })
+        .collect()
+}
+
+impl RuntimeMetadata {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("metadata only holds strings, numbers and lists")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Encode;
+    use crate::support::DispatchError;
+    use crate::{balances, staking, system, types, Runtime, RuntimeCall, RuntimeEvent};
+
+    fn pallet<'a>(metadata: &'a RuntimeMetadata, name: &str) -> &'a PalletMetadata {
+        metadata.pallets.iter().find(|pallet| pallet.name == name).unwrap()
+    }
+
+    fn index(variants: &[VariantMetadata], name: &str) -> u8 {
+        variants.iter().find(|variant| variant.name == name).unwrap().index
+    }
+
+    // The first two bytes of an encoded call or event must be the indices the metadata gives
+    #[test]
+    fn metadata_matches_the_encoding() {
+        let metadata = Runtime::metadata();
+        let (balances_pallet, staking_pallet) = (pallet(&metadata, "Balances"), pallet(&metadata, "Staking"));
+
+        let call = RuntimeCall::Staking(staking::Call::ClaimRewards);
+        assert_eq!(call.encode()[..2], [staking_pallet.call_index.unwrap(), index(&staking_pallet.calls, "ClaimRewards")]);
+        let call = RuntimeCall::Balances(balances::Call::Transfer { to: "bob".to_string(), amount: 1 });
+        assert_eq!(call.encode()[..2], [balances_pallet.call_index.unwrap(), index(&balances_pallet.calls, "Transfer")]);
+
+        let event = RuntimeEvent::Staking(staking::StakingEvent::RewardsPaid { who: "bob".to_string(), amount: 1 });
+        assert_eq!(event.encode()[..2], [staking_pallet.event_index, index(&staking_pallet.events, "RewardsPaid")]);
+        let system_pallet = pallet(&metadata, "System");
+        let event = RuntimeEvent::System(system::SystemEvent::ExtrinsicFailed { error: "oops".into() });
+        assert_eq!(event.encode()[..2], [system_pallet.event_index, index(&system_pallet.events, "ExtrinsicFailed")]);
+        assert_eq!(system_pallet.call_index, None);
+    }
+
+    #[test]
+    fn errors_are_named_like_dispatch_errors() {
+        let metadata = Runtime::metadata();
+        let DispatchError::Module { error, message, .. } = DispatchError::from(staking::StakingError::NotValidator) else {
+            panic!("pallet errors are module errors");
+        };
+        let described = pallet(&metadata, "Staking").errors.iter().find(|e| e.name == error).unwrap();
+        assert_eq!(described.docs, message);
+        assert_eq!(pallet(&metadata, "Balances").errors.len(), 9);
+
+        let json = metadata.to_json();
+        assert_eq!(json["pallets"][2]["calls"][1]["fields"][1], serde_json::json!({ "name": "amount", "type": "Balance" }));
+    }
+
+    // A client that signs the fields in the order the metadata lists them must sign what the runtime checks
+    #[test]
+    fn signed_payload_matches_the_signing_payload() {
+        let (caller, nonce, tip, genesis_hash) = ("alice".to_string(), 3u32, 7u128, [9u8; 32]);
+        let call = RuntimeCall::Balances(balances::Call::Transfer { to: "bob".to_string(), amount: 1 });
+
+        let mut payload = Vec::new();
+        for name in Runtime::metadata().extrinsic.signed_payload {
+            match name {
+                "caller" => caller.encode_to(&mut payload),
+                "call" => call.encode_to(&mut payload),
+                "nonce" => nonce.encode_to(&mut payload),
+                "tip" => tip.encode_to(&mut payload),
+                "genesis hash" => genesis_hash.encode_to(&mut payload),
+                _ => panic!("unknown signed payload field {}", name),
+            }
+        }
+        assert_eq!(payload, types::Extrinsic::signing_payload(&caller, &call, &nonce, &tip, genesis_hash));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use crate::codec::{Decode, DecodeError, Encode};
+
+// Runtime versioning and storage migrations
+//
+// Every pallet declares the version of its storage layout. Saved state records the version
+// each pallet was written with, so when a pallet's layout changes its version is bumped and
+// an `on_runtime_upgrade` step is added to convert state saved with the old layout.
+// Loading older state runs those steps one version at a time before decoding it.
+//
+// Version 0 of a pallet is the layout of the original pallet, before it was made generic;
+// the layouts in use today are version 1.
+
+pub type StorageVersion = u16;
+
+// Which runtime is running: its logic and the storage layout of each pallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeVersion {
+    pub spec_name: &'static str,
+    pub spec_version: u32, // Bumped whenever the runtime's behaviour changes
+    pub storage_versions: Vec<(&'static str, StorageVersion)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationError {
+    NewerRuntime(u32),                     // The state was saved by a newer runtime than this one
+    NewerStorage(&'static str, StorageVersion), // A pallet's state uses a layout newer than its code
+    NoMigration(&'static str, StorageVersion),  // No step converts a pallet's state from this version
+    MissingPallet(&'static str),           // The saved state has nothing for a pallet
+    Decode(DecodeError),                   // A pallet's state does not decode in its layout
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::NewerRuntime(version) => {
+                write!(f, "State was saved by runtime version {}, which is newer than this one", version)
+            }
+            MigrationError::NewerStorage(pallet, version) => {
+                write!(f, "{} storage version {} is newer than this runtime supports", pallet, version)
+            }
+            MigrationError::NoMigration(pallet, version) => {
+                write!(f, "No migration for {} storage version {}", pallet, version)
+            }
+            MigrationError::MissingPallet(pallet) => write!(f, "Saved state has no {} storage", pallet),
+            MigrationError::Decode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<DecodeError> for MigrationError {
+    fn from(error: DecodeError) -> Self {
+        MigrationError::Decode(error)
+    }
+}
+
+// Errors while loading saved state are reported as decode errors
+impl From<MigrationError> for DecodeError {
+    fn from(error: MigrationError) -> Self {
+        match error {
+            MigrationError::Decode(e) => e,
+            e => DecodeError::Invalid(e.to_string()),
+        }
+    }
+}
+
+// A pallet whose storage can be saved and brought up to date from older layouts
+pub trait OnRuntimeUpgrade: Encode + Decode {
+    const NAME: &'static str;
+    // The layout this code reads and writes
+    const STORAGE_VERSION: StorageVersion;
+
+    // Convert `state`, saved with storage version `from`, to the layout of version `from + 1`
+    fn on_runtime_upgrade(from: StorageVersion, _state: Vec<u8>) -> Result<Vec<u8>, MigrationError> {
+        Err(MigrationError::NoMigration(Self::NAME, from))
+    }
+}
+
+// Saved runtime state: the spec version that saved it and each pallet's encoded
+// storage along with the storage version it was encoded with
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VersionedState {
+    pub spec_version: u32,
+    pub pallets: BTreeMap<String, (StorageVersion, Vec<u8>)>,
+}
+
+impl VersionedState {
+    pub fn new(spec_version: u32) -> Self {
+        Self {
+            spec_version,
+            pallets: BTreeMap::new(),
+        }
+    }
+
+    // Add a pallet's state in its current layout
+    pub fn insert<P: OnRuntimeUpgrade>(&mut self, pallet: &P) {
+        self.pallets
+            .insert(P::NAME.to_string(), (P::STORAGE_VERSION, pallet.encode()));
+    }
+
+    // Take a pallet's state out, migrating it to the current layout first if needed
+    pub fn take<P: OnRuntimeUpgrade>(&mut self) -> Result<P, MigrationError> {
+        let (mut version, mut state) = self
+            .pallets
+            .remove(P::NAME)
+            .ok_or(MigrationError::MissingPallet(P::NAME))?;
+        if version > P::STORAGE_VERSION {
+            return Err(MigrationError::NewerStorage(P::NAME, version));
+        }
+        while version < P::STORAGE_VERSION {
+            state = P::on_runtime_upgrade(version, state)?;
+            version += 1;
+        }
+        Ok(P::decode_all(&state)?)
+    }
+}
+
+impl Encode for VersionedState {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.spec_version.encode_to(dest);
+        self.pallets.encode_to(dest);
+    }
+}
+
+impl Decode for VersionedState {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            spec_version: Decode::decode(input)?,
+            pallets: Decode::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A pallet that stored one u32 in version 0 and a pair of u32s since version 1
+    #[derive(Debug, PartialEq)]
+    struct Counter(u32, u32);
+
+    impl Encode for Counter {
+        fn encode_to(&self, dest: &mut Vec<u8>) {
+            (self.0, self.1).encode_to(dest);
+        }
+    }
+
+    impl Decode for Counter {
+        fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+            let (a, b) = Decode::decode(input)?;
+            Ok(Counter(a, b))
+        }
+    }
+
+    impl OnRuntimeUpgrade for Counter {
+        const NAME: &'static str = "Counter";
+        const STORAGE_VERSION: StorageVersion = 1;
+
+        fn on_runtime_upgrade(from: StorageVersion, state: Vec<u8>) -> Result<Vec<u8>, MigrationError> {
+            match from {
+                0 => Ok((u32::decode_all(&state)?, 0u32).encode()),
+                _ => Err(MigrationError::NoMigration(Self::NAME, from)),
+            }
+        }
+    }
+
+    fn saved(version: StorageVersion, state: Vec<u8>) -> VersionedState {
+        let mut saved = VersionedState::new(1);
+        saved.pallets.insert("Counter".to_string(), (version, state));
+        saved
+    }
+
+    #[test]
+    fn current_state_is_read_back_unchanged() {
+        let mut state = VersionedState::new(1);
+        state.insert(&Counter(3, 4));
+        let mut decoded = VersionedState::decode_all(&state.encode()).unwrap();
+        assert_eq!(decoded.take::<Counter>(), Ok(Counter(3, 4)));
+        assert_eq!(decoded.take::<Counter>(), Err(MigrationError::MissingPallet("Counter")));
+    }
+
+    #[test]
+    fn old_state_is_migrated_on_load() {
+        assert_eq!(saved(0, 7u32.encode()).take::<Counter>(), Ok(Counter(7, 0)));
+
+        // State from a newer layout, or an old one no step can convert, is refused
+        assert_eq!(
+            saved(2, Vec::new()).take::<Counter>(),
+            Err(MigrationError::NewerStorage("Counter", 2))
+        );
+        assert!(matches!(
+            saved(0, vec![1, 2]).take::<Counter>(),
+            Err(MigrationError::Decode(_))
+        ));
+    }
+}
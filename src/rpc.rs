@@ -0,0 +1,440 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::cli::Node;
+use crate::codec::{Decode, Encode};
+use crate::history::Receipt;
+use crate::store::Store;
+use crate::{hex_decode, hex_encode, support, types, Runtime};
+
+// A JSON-RPC 2.0 server for a running node
+//
+// Requests and responses are JSON objects written one per line over a TCP connection,
+// so any tool that can open a socket can talk to the node:
+//   {"jsonrpc":"2.0","id":1,"method":"state_getBalance","params":["cheryl"]}
+// Hashes and encoded extrinsics are 0x-prefixed hex, and balances are decimal strings
+// since they can be larger than a JSON number holds exactly
+
+// Error codes from the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+// The transaction pool refused a submitted extrinsic (the code Substrate uses)
+const INVALID_TRANSACTION: i64 = 1010;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+// Answer one line of input: a request, or a batch of them in an array
+// Returns None when nothing needs to be sent back (only notifications were received)
+pub fn handle_request(node: &mut Node, store: &Store, line: &str) -> Option<String> {
+    let response = match serde_json::from_str::<Value>(line) {
+        Err(e) => Some(error_response(Value::Null, RpcError::new(PARSE_ERROR, e.to_string()))),
+        Ok(Value::Array(batch)) if batch.is_empty() => Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "Empty batch"),
+        )),
+        Ok(Value::Array(batch)) => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|request| handle_call(node, store, request))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(request) => handle_call(node, store, request),
+    };
+    response.map(|response| response.to_string())
+}
+
+fn handle_call(node: &mut Node, store: &Store, request: Value) -> Option<Value> {
+    let Value::Object(request) = request else {
+        return Some(error_response(Value::Null, RpcError::new(INVALID_REQUEST, "Request must be an object")));
+    };
+    // A request without an id is a notification and gets no response
+    let id = request.get("id").cloned();
+    let result = match (request.get("jsonrpc"), request.get("method")) {
+        (Some(Value::String(version)), Some(Value::String(method))) if version == "2.0" => {
+            let params = match request.get("params") {
+                None => Ok(Vec::new()),
+                Some(Value::Array(params)) => Ok(params.clone()),
+                Some(_) => Err(RpcError::invalid_params("Params must be an array")),
+            };
+            params.and_then(|params| call(node, store, method, &params))
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "Expected jsonrpc \"2.0\" and a method")),
+    };
+
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, e),
+    })
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn call(node: &mut Node, store: &Store, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+    let runtime = &node.runtime;
+    match method {
+        "chain_getBlockHash" => {
+            let number = optional_block_number(params)?.unwrap_or(runtime.system.block_number());
+            Ok(runtime
+                .system
+                .get_block_hash(number)
+                .map_or(Value::Null, |hash| hex_value(&hash)))
+        }
+        "chain_getBlock" => {
+            let block = match params.first() {
+                None | Some(Value::Null) => runtime.history.block_by_number(runtime.system.block_number()),
+                Some(hash) => runtime.history.block_by_hash(&parse_hash(hash)?),
+            };
+            Ok(block.map_or(Value::Null, |block| {
+                let extrinsics: Vec<Value> = block.extrinsics.iter().map(|extrinsic| hex_value(extrinsic)).collect();
+                json!({ "header": header_json(&block.header), "extrinsics": extrinsics })
+            }))
+        }
+        "chain_getReceipt" => {
+            let number = optional_block_number(params)?
+                .ok_or_else(|| RpcError::invalid_params("Expected a block number"))?;
+            let index = params
+                .get(1)
+                .and_then(Value::as_u64)
+                .and_then(|index| u32::try_from(index).ok())
+                .ok_or_else(|| RpcError::invalid_params("Expected an extrinsic index"))?;
+            Ok(runtime.history.receipt(number, index).map_or(Value::Null, receipt_json))
+        }
+        "state_getMetadata" => Ok(Runtime::metadata().to_json()),
+        "state_getRuntimeVersion" => {
+            let version = Runtime::version();
+            let storage_versions: serde_json::Map<String, Value> = version
+                .storage_versions
+                .iter()
+                .map(|(pallet, storage_version)| (pallet.to_string(), json!(storage_version)))
+                .collect();
+            Ok(json!({
+                "specName": version.spec_name,
+                "specVersion": version.spec_version,
+                "storageVersions": storage_versions,
+            }))
+        }
+        "state_getBalance" => {
+            let who = account_param(params)?;
+            Ok(Value::String(runtime.balances.balance(&who).to_string()))
+        }
+        "state_getReservedBalance" => {
+            let who = account_param(params)?;
+            Ok(Value::String(runtime.balances.reserved_balance(&who).to_string()))
+        }
+        "state_getTotalIssuance" => Ok(Value::String(runtime.balances.total_issuance().to_string())),
+        "system_accountNonce" => {
+            let who = account_param(params)?;
+            Ok(json!(runtime.system.account_nonce(&who)))
+        }
+        "staking_getStakeInfo" => {
+            let who = account_param(params)?;
+            Ok(runtime.staking.get_stake_info(&who).map_or(Value::Null, |info| {
+                json!({
+                    "stakedAmount": info.staked_amount.to_string(),
+                    "validator": info.validator,
+                    "stakeBlock": info.stake_block,
+                    "lastRewardBlock": info.last_reward_block,
+                    "totalRewards": info.total_rewards.to_string(),
+                })
+            }))
+        }
+        "author_submitExtrinsic" => {
+            let bytes = params
+                .first()
+                .and_then(Value::as_str)
+                .and_then(hex_decode)
+                .ok_or_else(|| RpcError::invalid_params("Expected a hex encoded extrinsic"))?;
+            let extrinsic = types::Extrinsic::decode_all(&bytes)
+                .map_err(|e| RpcError::invalid_params(format!("Extrinsic does not decode: {}", e)))?;
+            let hash = support::blake2_256(&extrinsic.encode());
+
+            node.pool
+                .submit(&node.runtime, extrinsic)
+                .map_err(|e| RpcError::new(INVALID_TRANSACTION, e.to_string()))?;
+            store
+                .save(node)
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?;
+            Ok(hex_value(&hash))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+fn account_param(params: &[Value]) -> Result<types::AccountId, RpcError> {
+    match params.first() {
+        Some(Value::String(who)) => Ok(who.clone()),
+        _ => Err(RpcError::invalid_params("Expected an account name")),
+    }
+}
+
+fn optional_block_number(params: &[Value]) -> Result<Option<types::BlockNumber>, RpcError> {
+    match params.first() {
+        None | Some(Value::Null) => Ok(None),
+        Some(number) => number
+            .as_u64()
+            .and_then(|number| types::BlockNumber::try_from(number).ok())
+            .map(Some)
+            .ok_or_else(|| RpcError::invalid_params("Expected a block number")),
+    }
+}
+
+fn parse_hash(value: &Value) -> Result<[u8; 32], RpcError> {
+    value
+        .as_str()
+        .and_then(hex_decode)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| RpcError::invalid_params("Expected a 32 byte hex encoded hash"))
+}
+
+fn hex_value(bytes: &[u8]) -> Value {
+    Value::String(format!("0x{}", hex_encode(bytes)))
+}
+
+fn header_json(header: &types::Header) -> Value {
+    json!({
+        "number": header.block_number,
+        "hash": hex_value(&header.hash()),
+        "parentHash": hex_value(&header.parent_hash),
+        "stateRoot": hex_value(&header.state_root),
+        "extrinsicsRoot": hex_value(&header.extrinsics_root),
+        "timestamp": header.timestamp,
+    })
+}
+
+// Events are written in their debug form, e.g. "Balances(FeePaid { who: \"cheryl\", amount: 1 })"
+fn receipt_json(receipt: &Receipt) -> Value {
+    let events: Vec<String> = receipt.events.iter().map(|event| format!("{:?}", event)).collect();
+    json!({
+        "extrinsicHash": hex_value(&receipt.extrinsic_hash),
+        "success": receipt.is_success(),
+        "error": receipt.result.as_ref().err().map(|e| e.to_string()),
+        "feePaid": receipt.fee_paid.to_string(),
+        "events": events,
+    })
+}
+
+// A panic while one connection held the node leaves it as it was, so carry on with it
+fn lock(node: &Mutex<Node>) -> MutexGuard<'_, Node> {
+    node.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Serve requests until the listener fails, each connection on its own thread
+// With a block time set, a block is also authored from the pool at that interval
+pub fn serve(
+    listener: TcpListener,
+    node: Node,
+    store: Store,
+    block_time: Option<Duration>,
+) -> std::io::Result<()> {
+    let node = Arc::new(Mutex::new(node));
+    let store = Arc::new(store);
+
+    if let Some(block_time) = block_time {
+        let node = Arc::clone(&node);
+        let store = Arc::clone(&store);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(block_time);
+            let mut node = lock(&node);
+            let node = &mut *node;
+            let block = node.runtime.author_block_from_pool(&mut node.pool);
+            println!(
+                "📦 Block #{} produced with {} extrinsics",
+                block.header.block_number,
+                block.extrinsics.len()
+            );
+            if let Err(e) = store.save(node) {
+                eprintln!("❌ Could not save block #{}: {}", block.header.block_number, e);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let node = Arc::clone(&node);
+        let store = Arc::clone(&store);
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &node, &store) {
+                eprintln!("❌ RPC connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, node: &Mutex<Node>, store: &Store) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_request(&mut lock(node), store, &line);
+        if let Some(response) = response {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::GenesisConfig;
+    use crate::{balances, crypto, RuntimeCall};
+
+    fn node() -> Node {
+        Node {
+            runtime: GenesisConfig::development().build().unwrap(),
+            pool: types::TransactionPool::new(),
+        }
+    }
+
+    fn request(node: &mut Node, store: &Store, method: &str, params: Value) -> Value {
+        let line = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = handle_request(node, store, &line.to_string()).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    fn transfer_hex(node: &Node, amount: types::Balance) -> String {
+        let extrinsic: types::Extrinsic = support::Extrinsic::new_signed(
+            "cheryl".to_string(),
+            RuntimeCall::Balances(balances::Call::Transfer { to: "faith".to_string(), amount }),
+            0,
+            &crypto::Pair::dev("cheryl"),
+            node.runtime.genesis_hash(),
+        );
+        format!("0x{}", hex_encode(&extrinsic.encode()))
+    }
+
+    #[test]
+    fn state_is_queried_through_the_getters() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        let mut node = node();
+
+        let balance = request(&mut node, &store, "state_getBalance", json!(["cheryl"]));
+        assert_eq!(balance["result"], json!("10000"));
+        let reserved = request(&mut node, &store, "state_getReservedBalance", json!(["cheryl"]));
+        assert_eq!(reserved["result"], json!("0"));
+        let issuance = request(&mut node, &store, "state_getTotalIssuance", json!([]));
+        assert_eq!(issuance["result"], json!("11100"));
+        let nonce = request(&mut node, &store, "system_accountNonce", json!(["cheryl"]));
+        assert_eq!(nonce["result"], json!(0));
+        let stake = request(&mut node, &store, "staking_getStakeInfo", json!(["cheryl"]));
+        assert_eq!(stake["result"], Value::Null);
+        let version = request(&mut node, &store, "state_getRuntimeVersion", json!([]));
+        assert_eq!(version["result"]["storageVersions"]["System"], json!(2));
+        let metadata = request(&mut node, &store, "state_getMetadata", json!([]));
+        assert_eq!(metadata["result"]["pallets"][1]["calls"][0]["name"], json!("Transfer"));
+
+        let genesis = format!("0x{}", hex_encode(&node.runtime.genesis_hash()));
+        let hash = request(&mut node, &store, "chain_getBlockHash", json!([0]));
+        assert_eq!(hash["result"], json!(genesis));
+        let block = request(&mut node, &store, "chain_getBlock", json!([genesis]));
+        assert_eq!(block["result"]["header"]["number"], json!(0));
+        assert_eq!(block["result"]["extrinsics"], json!([]));
+        assert_eq!(request(&mut node, &store, "chain_getBlockHash", json!([7]))["result"], Value::Null);
+    }
+
+    #[test]
+    fn submitted_extrinsics_enter_the_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        let mut node = node();
+
+        let extrinsic = transfer_hex(&node, 25);
+        let submitted = request(&mut node, &store, "author_submitExtrinsic", json!([extrinsic]));
+        assert!(submitted["result"].as_str().unwrap().starts_with("0x"));
+        assert_eq!(node.pool.len(), 1);
+        // The pool is saved, so it survives a restart
+        assert_eq!(store.load::<Node>().unwrap().unwrap().pool.len(), 1);
+
+        let again = request(&mut node, &store, "author_submitExtrinsic", json!([extrinsic]));
+        assert_eq!(again["error"]["code"], json!(INVALID_TRANSACTION));
+
+        let garbage = request(&mut node, &store, "author_submitExtrinsic", json!(["0x0102"]));
+        assert_eq!(garbage["error"]["code"], json!(INVALID_PARAMS));
+
+        // Once included, the block body and the receipt can be looked up
+        node.runtime.author_block_from_pool(&mut node.pool);
+        let block = request(&mut node, &store, "chain_getBlock", json!([]));
+        assert_eq!(block["result"]["extrinsics"], json!([extrinsic]));
+        let receipt = request(&mut node, &store, "chain_getReceipt", json!([1, 0]));
+        assert_eq!(receipt["result"]["extrinsicHash"], submitted["result"]);
+        assert_eq!(receipt["result"]["success"], json!(true));
+        assert_eq!(request(&mut node, &store, "chain_getReceipt", json!([1, 1]))["result"], Value::Null);
+    }
+
+    #[test]
+    fn malformed_requests_get_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        let mut node = node();
+
+        let parse = handle_request(&mut node, &store, "{not json").unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&parse).unwrap()["error"]["code"], json!(PARSE_ERROR));
+
+        let unknown = request(&mut node, &store, "state_getEverything", json!([]));
+        assert_eq!(unknown["error"]["code"], json!(METHOD_NOT_FOUND));
+        let missing = request(&mut node, &store, "state_getBalance", json!([]));
+        assert_eq!(missing["error"]["code"], json!(INVALID_PARAMS));
+
+        // Notifications get no response, even inside a batch
+        let notification = r#"{"jsonrpc":"2.0","method":"system_accountNonce","params":["faith"]}"#;
+        assert_eq!(handle_request(&mut node, &store, notification), None);
+        let batch = format!(
+            r#"[{}, {{"jsonrpc":"2.0","id":"a","method":"system_accountNonce","params":["faith"]}}]"#,
+            notification
+        );
+        let responses: Value = serde_json::from_str(&handle_request(&mut node, &store, &batch).unwrap()).unwrap();
+        assert_eq!(responses, json!([{ "jsonrpc": "2.0", "id": "a", "result": 0 }]));
+    }
+
+    #[test]
+    fn requests_are_served_over_tcp() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, node(), store, None));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        writeln!(stream, r#"{{"jsonrpc":"2.0","id":1,"method":"state_getBalance","params":["faith"]}}"#).unwrap();
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&response).unwrap(),
+            json!({ "jsonrpc": "2.0", "id": 1, "result": "100" })
+        );
+    }
+}
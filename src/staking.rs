@@ -1,9 +1,15 @@
 use std::collections::BTreeMap;
-use num::traits::{CheckedAdd, CheckedSub, Zero, One};
+use num::traits::{CheckedAdd, CheckedSub, Zero};
+use crate::codec::{read_variant, Decode, DecodeError, Encode};
+use crate::metadata::{errors, variant, PalletInfo, VariantMetadata};
+use crate::migrations::{OnRuntimeUpgrade, StorageVersion};
+use crate::support::{
+    storage_key, DispatchError, GetWeight, StorageEntries, StorageEntry, Transactional, Weight,
+};
 
 // Staking Config trait - extends the system Config with staking-specific types
 pub trait Config: crate::system::Config {
-    type Balance: CheckedAdd + CheckedSub + Zero + Copy + PartialOrd;
+    type Balance: CheckedAdd + CheckedSub + Zero + Copy + PartialOrd + Encode + Decode;
 }
 
 // Custom Result enum for staking operations - with additional methods
@@ -102,6 +108,12 @@ impl std::fmt::Display for StakingError {
     }
 }
 
+impl From<StakingError> for DispatchError {
+    fn from(error: StakingError) -> Self {
+        DispatchError::module("Staking", error)
+    }
+}
+
 // Staking Info for each account - now generic over Config types
 #[derive(Debug, Clone)]
 pub struct StakeInfo<T: Config> {
@@ -112,6 +124,28 @@ pub struct StakeInfo<T: Config> {
     pub total_rewards: T::Balance,
 }
 
+impl<T: Config> Encode for StakeInfo<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.staked_amount.encode_to(dest);
+        self.validator.encode_to(dest);
+        self.stake_block.encode_to(dest);
+        self.last_reward_block.encode_to(dest);
+        self.total_rewards.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for StakeInfo<T> {
+    fn decode(input: &mut &[u8]) -> std::result::Result<Self, DecodeError> {
+        Ok(Self {
+            staked_amount: Decode::decode(input)?,
+            validator: Decode::decode(input)?,
+            stake_block: Decode::decode(input)?,
+            last_reward_block: Decode::decode(input)?,
+            total_rewards: Decode::decode(input)?,
+        })
+    }
+}
+
 // Validator Info - generic over Config types
 #[derive(Debug, Clone)]
 pub struct ValidatorInfo<T: Config> {
@@ -122,7 +156,30 @@ pub struct ValidatorInfo<T: Config> {
     pub blocks_produced: u32,
 }
 
+impl<T: Config> Encode for ValidatorInfo<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.total_stake.encode_to(dest);
+        self.commission_rate.encode_to(dest);
+        self.is_active.encode_to(dest);
+        self.nominators_count.encode_to(dest);
+        self.blocks_produced.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for ValidatorInfo<T> {
+    fn decode(input: &mut &[u8]) -> std::result::Result<Self, DecodeError> {
+        Ok(Self {
+            total_stake: Decode::decode(input)?,
+            commission_rate: Decode::decode(input)?,
+            is_active: Decode::decode(input)?,
+            nominators_count: Decode::decode(input)?,
+            blocks_produced: Decode::decode(input)?,
+        })
+    }
+}
+
 // Staking events - generic over Config types
+// Collected by the runtime into the system pallet
 #[derive(Debug, Clone)]
 pub enum StakingEvent<T: Config> {
     Staked {
@@ -150,8 +207,75 @@ pub enum StakingEvent<T: Config> {
     },
 }
 
+impl<T: Config> Encode for StakingEvent<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            StakingEvent::Staked { who, amount, validator } => {
+                dest.push(0);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+                validator.encode_to(dest);
+            }
+            StakingEvent::Unstaked { who, amount } => {
+                dest.push(1);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            StakingEvent::ValidatorAdded { validator } => {
+                dest.push(2);
+                validator.encode_to(dest);
+            }
+            StakingEvent::ValidatorRemoved { validator } => {
+                dest.push(3);
+                validator.encode_to(dest);
+            }
+            StakingEvent::RewardsPaid { who, amount } => {
+                dest.push(4);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            StakingEvent::SlashApplied { who, amount } => {
+                dest.push(5);
+                who.encode_to(dest);
+                amount.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Config> Decode for StakingEvent<T> {
+    fn decode(input: &mut &[u8]) -> std::result::Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(StakingEvent::Staked {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+                validator: Decode::decode(input)?,
+            }),
+            1 => Ok(StakingEvent::Unstaked {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            2 => Ok(StakingEvent::ValidatorAdded {
+                validator: Decode::decode(input)?,
+            }),
+            3 => Ok(StakingEvent::ValidatorRemoved {
+                validator: Decode::decode(input)?,
+            }),
+            4 => Ok(StakingEvent::RewardsPaid {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            5 => Ok(StakingEvent::SlashApplied {
+                who: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
 // Generic Staking Pallet
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pallet<T: Config> {
     pub stakes: BTreeMap<T::AccountId, StakeInfo<T>>,
     pub validators: BTreeMap<T::AccountId, ValidatorInfo<T>>,
@@ -235,7 +359,7 @@ impl<T: Config> Pallet<T> {
         self.validators.insert(validator.clone(), validator_info);
         
         let event = StakingEvent::ValidatorAdded {
-            validator: validator,
+            validator,
         };
         self.events.push(event);
         
@@ -404,6 +528,13 @@ impl<T: Config> Pallet<T> {
         // In a real implementation, this would be called at specific intervals
     }
 
+    /// Check whether `who` has a stake that can be unstaked now
+    pub fn can_unstake(&self, who: &T::AccountId) -> bool {
+        self.stakes.get(who).is_some_and(|stake_info| {
+            self.current_block >= self.add_block_numbers(stake_info.stake_block, self.unstaking_period)
+        })
+    }
+
     /// Get staking info for an account
     pub fn get_stake_info(&self, who: &T::AccountId) -> Option<&StakeInfo<T>> {
         self.stakes.get(who)
@@ -436,14 +567,9 @@ impl<T: Config> Pallet<T> {
         self.validators.contains_key(who)
     }
 
-    // Get staking events
-    pub fn get_events(&self) -> &Vec<StakingEvent<T>> {
-        &self.events
-    }
-
-    // Clear events (should be called after each block)
-    pub fn clear_events(&mut self) {
-        self.events.clear();
+    // Take the staking events emitted since they were last taken
+    pub fn take_events(&mut self) -> Vec<StakingEvent<T>> {
+        std::mem::take(&mut self.events)
     }
 
     pub fn get_staking_stats(&self) -> StakingStats<T> {
@@ -479,6 +605,7 @@ pub struct StakingStats<T: Config> {
 }
 
 // Staking calls enum
+#[derive(Debug, Clone)]
 pub enum Call<T: Config> {
     AddValidator {
         validator: T::AccountId,
@@ -492,6 +619,172 @@ pub enum Call<T: Config> {
     ClaimRewards,
 }
 
+// Weights of the staking calls
+pub mod weights {
+    use crate::support::Weight;
+
+    pub const ADD_VALIDATOR: Weight = 100;
+    pub const STAKE: Weight = 150;
+    pub const UNSTAKE: Weight = 150;
+    pub const CLAIM_REWARDS: Weight = 150;
+}
+
+impl<T: Config> GetWeight for Call<T> {
+    fn weight(&self) -> Weight {
+        match self {
+            Call::AddValidator { .. } => weights::ADD_VALIDATOR,
+            Call::Stake { .. } => weights::STAKE,
+            Call::Unstake => weights::UNSTAKE,
+            Call::ClaimRewards => weights::CLAIM_REWARDS,
+        }
+    }
+}
+
+// Nearly everything in the staking pallet is storage a call can change,
+// so the checkpoint is simply a copy of the whole pallet
+impl<T: Config> Transactional for Pallet<T>
+where
+    Self: Clone,
+{
+    type Checkpoint = Self;
+
+    fn checkpoint(&self) -> Self::Checkpoint {
+        self.clone()
+    }
+
+    fn restore(&mut self, checkpoint: Self::Checkpoint) {
+        *self = checkpoint;
+    }
+}
+
+// The staking pallet commits to stakes, validators and the total amount staked
+impl<T: Config> StorageEntries for Pallet<T> {
+    fn storage_entries(&self) -> Vec<StorageEntry> {
+        let stakes = self
+            .stakes
+            .iter()
+            .map(|(who, info)| (storage_key("Staking", "Stakes", who), info.encode()));
+        let validators = self
+            .validators
+            .iter()
+            .map(|(who, info)| (storage_key("Staking", "Validators", who), info.encode()));
+        let total_staked = (storage_key("Staking", "TotalStaked", &()), self.total_staked.encode());
+
+        stakes.chain(validators).chain(std::iter::once(total_staked)).collect()
+    }
+}
+
+// The whole pallet is encoded when the runtime state is saved to disk
+// Events are not saved - they only live until the block that emitted them is done
+impl<T: Config> Encode for Pallet<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.stakes.encode_to(dest);
+        self.validators.encode_to(dest);
+        self.minimum_stake.encode_to(dest);
+        self.reward_rate.encode_to(dest);
+        self.unstaking_period.encode_to(dest);
+        self.max_validators.encode_to(dest);
+        self.total_staked.encode_to(dest);
+        self.current_block.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for Pallet<T> {
+    fn decode(input: &mut &[u8]) -> std::result::Result<Self, DecodeError> {
+        Ok(Self {
+            stakes: Decode::decode(input)?,
+            validators: Decode::decode(input)?,
+            minimum_stake: Decode::decode(input)?,
+            reward_rate: Decode::decode(input)?,
+            unstaking_period: Decode::decode(input)?,
+            max_validators: Decode::decode(input)?,
+            total_staked: Decode::decode(input)?,
+            current_block: Decode::decode(input)?,
+            events: Vec::new(),
+        })
+    }
+}
+
+impl<T: Config> PalletInfo for Pallet<T> {
+    fn calls() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "AddValidator", &[("validator", "AccountId"), ("commission", "u8")], "Register `validator`, keeping `commission` percent of its rewards"),
+            variant(1, "Stake", &[("validator", "AccountId"), ("amount", "Balance")], "Stake `amount` with `validator`"),
+            variant(2, "Unstake", &[], "Withdraw the whole stake once the unstaking period has passed"),
+            variant(3, "ClaimRewards", &[], "Pay out the rewards earned so far"),
+        ]
+    }
+
+    fn events() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "Staked", &[("who", "AccountId"), ("amount", "Balance"), ("validator", "AccountId")], "Funds were staked with a validator"),
+            variant(1, "Unstaked", &[("who", "AccountId"), ("amount", "Balance")], "A stake was withdrawn"),
+            variant(2, "ValidatorAdded", &[("validator", "AccountId")], "A validator was registered"),
+            variant(3, "ValidatorRemoved", &[("validator", "AccountId")], "A validator was removed"),
+            variant(4, "RewardsPaid", &[("who", "AccountId"), ("amount", "Balance")], "Staking rewards were paid out"),
+            variant(5, "SlashApplied", &[("who", "AccountId"), ("amount", "Balance")], "A stake was slashed"),
+        ]
+    }
+
+    fn errors() -> Vec<VariantMetadata> {
+        errors(&[
+            StakingError::InsufficientBalance,
+            StakingError::NotStaked,
+            StakingError::AlreadyStaked,
+            StakingError::MinimumStakeNotMet,
+            StakingError::InvalidValidator,
+            StakingError::TooManyValidators,
+            StakingError::NotValidator,
+            StakingError::AlreadyValidator,
+            StakingError::RewardCalculationError,
+            StakingError::UnstakingPeriodNotMet,
+        ])
+    }
+}
+
+impl<T: Config> OnRuntimeUpgrade for Pallet<T> {
+    const NAME: &'static str = "Staking";
+    const STORAGE_VERSION: StorageVersion = 1;
+}
+
+// Calls are encoded as a variant index followed by their fields
+impl<T: Config> Encode for Call<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Call::AddValidator { validator, commission } => {
+                dest.push(0);
+                validator.encode_to(dest);
+                commission.encode_to(dest);
+            }
+            Call::Stake { validator, amount } => {
+                dest.push(1);
+                validator.encode_to(dest);
+                amount.encode_to(dest);
+            }
+            Call::Unstake => dest.push(2),
+            Call::ClaimRewards => dest.push(3),
+        }
+    }
+}
+
+impl<T: Config> Decode for Call<T> {
+    fn decode(input: &mut &[u8]) -> std::result::Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(Call::AddValidator {
+                validator: Decode::decode(input)?,
+                commission: Decode::decode(input)?,
+            }),
+            1 => Ok(Call::Stake {
+                validator: Decode::decode(input)?,
+                amount: Decode::decode(input)?,
+            }),
+            2 => Ok(Call::Unstake),
+            3 => Ok(Call::ClaimRewards),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
 // Implement dispatch for the staking pallet
 impl<T: Config> crate::support::Dispatch for Pallet<T> {
     type Call = Call<T>;
@@ -505,22 +798,18 @@ impl<T: Config> crate::support::Dispatch for Pallet<T> {
         match call {
             Call::AddValidator { validator, commission } => {
                 // Convert custom Result to std::result::Result and use map_err
-                self.add_validator(validator, commission)
-                    .into_std_result()
-                    .map_err(|_| "Failed to add validator")?;
+                self.add_validator(validator, commission).into_std_result()?;
             }
             Call::Stake { validator: _, amount: _ } => {
                 // This would need access to balance pallet for balance checking
                 // For now, we'll return an error
-                return Err("Staking through dispatch not implemented yet");
+                return Err("Staking through dispatch not implemented yet".into());
             }
             Call::Unstake => {
-                self.unstake(caller)
-                    .map_err(|_| "Failed to unstake")?;
+                self.unstake(caller)?;
             }
             Call::ClaimRewards => {
-                self.claim_rewards(caller)
-                    .map_err(|_| "Failed to claim rewards")?;
+                self.claim_rewards(caller)?;
             }
         }
         Ok(())
@@ -538,6 +827,7 @@ mod tests {
         type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = ();
     }
 
     impl Config for TestConfig {
@@ -636,4 +926,23 @@ mod tests {
         assert_eq!(staking.unstake("user1".to_string()), Ok(200));
         assert!(!staking.is_staking(&"user1".to_string()));
     }
+
+    #[test]
+    fn dispatch_reports_the_staking_error() {
+        use crate::support::Dispatch;
+        let mut staking = Pallet::<TestConfig>::new_with_config(100, 5, 10, 10);
+
+        assert_eq!(
+            staking.dispatch("user1".to_string(), Call::Unstake),
+            Err(DispatchError::from(StakingError::NotStaked))
+        );
+        assert_eq!(
+            staking.dispatch("user1".to_string(), Call::ClaimRewards),
+            Err(DispatchError::Module {
+                pallet: "Staking".to_string(),
+                error: "NotStaked".to_string(),
+                message: "Account is not staking".to_string(),
+            })
+        );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,152 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::codec::{Decode, DecodeError, Encode};
+use crate::support::blake2_256;
+
+// Every state file starts with these bytes, so we never mistake another file for one
+const MAGIC: &[u8; 8] = b"RSMSTATE";
+// Bumped whenever the layout of the state file changes
+// Changes to the storage layout of a pallet are handled by migrations instead
+const FORMAT_VERSION: u32 = 3;
+
+const STATE_FILE: &str = "state.bin";
+const TEMP_FILE: &str = "state.bin.tmp";
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),      // Reading or writing the data directory failed
+    Corrupt(&'static str),   // The state file is damaged or is not a state file
+    Decode(DecodeError),     // The state file is intact but its contents don't decode
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "I/O error: {}", e),
+            StoreError::Corrupt(reason) => write!(f, "Corrupt state file: {}", reason),
+            StoreError::Decode(e) => write!(f, "Could not decode saved state: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(error: std::io::Error) -> Self {
+        StoreError::Io(error)
+    }
+}
+
+// A data directory holding the latest saved state
+//
+// The state file is laid out as:
+//   magic (8 bytes) | format version (u32) | encoded state | BLAKE2b-256 of the encoded state
+//
+// Saving is crash safe: the new state is written to a temporary file and flushed to disk,
+// then renamed over the old state file. A rename replaces the file in one step, so after a
+// crash the directory holds either the complete old state or the complete new one
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    // Open a data directory, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    // Replace the saved state with `state`
+    pub fn save<S: Encode>(&self, state: &S) -> Result<(), StoreError> {
+        let payload = state.encode();
+        let mut contents = Vec::with_capacity(MAGIC.len() + 4 + payload.len() + 32);
+        contents.extend_from_slice(MAGIC);
+        FORMAT_VERSION.encode_to(&mut contents);
+        contents.extend_from_slice(&payload);
+        contents.extend_from_slice(&blake2_256(&payload));
+
+        let temp_path = self.dir.join(TEMP_FILE);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, self.dir.join(STATE_FILE))?;
+
+        // Make the rename itself durable
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+
+    // Load the saved state, or None if nothing has been saved yet
+    pub fn load<S: Decode>(&self) -> Result<Option<S>, StoreError> {
+        let contents = match fs::read(self.dir.join(STATE_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let Some(rest) = contents.strip_prefix(MAGIC.as_slice()) else {
+            return Err(StoreError::Corrupt("missing magic bytes"));
+        };
+        if rest.len() < 4 + 32 {
+            return Err(StoreError::Corrupt("file is truncated"));
+        }
+        let (version, rest) = rest.split_at(4);
+        if u32::decode_all(version).map_err(StoreError::Decode)? != FORMAT_VERSION {
+            return Err(StoreError::Corrupt("unsupported format version"));
+        }
+        let (payload, checksum) = rest.split_at(rest.len() - 32);
+        if blake2_256(payload) != checksum {
+            return Err(StoreError::Corrupt("checksum does not match"));
+        }
+
+        S::decode_all(payload).map(Some).map_err(StoreError::Decode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_state_is_loaded_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        assert!(store.load::<Vec<u32>>().unwrap().is_none());
+
+        store.save(&vec![1u32, 2, 3]).unwrap();
+        store.save(&vec![4u32, 5]).unwrap();
+        assert_eq!(store.load::<Vec<u32>>().unwrap(), Some(vec![4, 5]));
+        assert!(!dir.path().join(TEMP_FILE).exists());
+    }
+
+    #[test]
+    fn damaged_state_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        store.save(&vec![1u32, 2, 3]).unwrap();
+
+        let path = dir.path().join(STATE_FILE);
+        let mut contents = fs::read(&path).unwrap();
+        contents[MAGIC.len() + 5] ^= 0xff;
+        fs::write(&path, &contents).unwrap();
+        assert!(matches!(store.load::<Vec<u32>>(), Err(StoreError::Corrupt(_))));
+
+        fs::write(&path, &contents[..10]).unwrap();
+        assert!(matches!(store.load::<Vec<u32>>(), Err(StoreError::Corrupt(_))));
+    }
+
+    #[test]
+    fn interrupted_save_leaves_previous_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        store.save(&vec![1u32]).unwrap();
+
+        // A crash half way through writing the next state leaves a partial temp file
+        fs::write(dir.path().join(TEMP_FILE), b"RSMSTA").unwrap();
+        assert_eq!(store.load::<Vec<u32>>().unwrap(), Some(vec![1]));
+
+        // The next save simply overwrites it
+        store.save(&vec![2u32]).unwrap();
+        assert_eq!(store.load::<Vec<u32>>().unwrap(), Some(vec![2]));
+    }
+}
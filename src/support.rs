@@ -1,5 +1,11 @@
+use crate::codec::{read_variant, Decode, DecodeError, Encode};
+use crate::crypto::{verify, Pair, PublicKey, Signature};
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+
 // A representation of a block in our blockchain
 // Generic over Header and Extrinsic types - this makes it flexible
+#[derive(Debug, Clone)]
 pub struct Block<Header, Extrinsic> {
     // The block header contains metadata about the block
     pub header: Header,
@@ -7,25 +13,305 @@ pub struct Block<Header, Extrinsic> {
     pub extrinsics: Vec<Extrinsic>,
 }
 
+// A block is encoded as its header followed by its extrinsics
+impl<Header: Encode, Extrinsic: Encode> Encode for Block<Header, Extrinsic> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.header.encode_to(dest);
+        self.extrinsics.encode_to(dest);
+    }
+}
+
+impl<Header: Decode, Extrinsic: Decode> Decode for Block<Header, Extrinsic> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            header: Header::decode(input)?,
+            extrinsics: Decode::decode(input)?,
+        })
+    }
+}
+
 // Header struct that contains metadata of the block
 // Generic over BlockNumber type - can be u32, u64, etc.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Header<BlockNumber> {
     pub block_number: BlockNumber,
-    // Future additions could include:
-    // pub parent_hash: [u8; 32],
-    // pub state_root: [u8; 32],
-    // pub timestamp: u64,
+    pub parent_hash: [u8; 32],     // Hash of the previous block (zero for the first block)
+    pub state_root: [u8; 32],      // Commitment to the state after executing this block
+    pub extrinsics_root: [u8; 32], // Commitment to the extrinsics in this block
+    pub timestamp: u64,            // Milliseconds since the unix epoch
+}
+
+// Headers are encoded field by field in declaration order
+impl<BlockNumber: Encode> Encode for Header<BlockNumber> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.block_number.encode_to(dest);
+        self.parent_hash.encode_to(dest);
+        self.state_root.encode_to(dest);
+        self.extrinsics_root.encode_to(dest);
+        self.timestamp.encode_to(dest);
+    }
+}
+
+impl<BlockNumber: Decode> Decode for Header<BlockNumber> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            block_number: BlockNumber::decode(input)?,
+            parent_hash: Decode::decode(input)?,
+            state_root: Decode::decode(input)?,
+            extrinsics_root: Decode::decode(input)?,
+            timestamp: Decode::decode(input)?,
+        })
+    }
+}
+
+impl<BlockNumber: Encode> Header<BlockNumber> {
+    // The block hash is the BLAKE2b-256 hash of the encoded header
+    pub fn hash(&self) -> [u8; 32] {
+        blake2_256(&self.encode())
+    }
 }
 
 // Extrinsic struct that contains information about the transaction to execute
-// Generic over Caller and Call types - flexible for different account and call types
-pub struct Extrinsic<Caller, Call> {
+// Generic over Caller, Call, Nonce and Tip types - flexible for different account and call types
+#[derive(Debug, Clone)]
+pub struct Extrinsic<Caller, Call, Nonce, Tip> {
     pub caller: Caller, // Who is making the transaction
     pub call: Call,     // What action they want to perform
+    pub nonce: Nonce,   // The caller's transaction index, part of the signed payload
+    pub tip: Tip,       // Extra payment to get the extrinsic included sooner
+    pub signature: Option<ExtrinsicSignature>, // None for an unsigned extrinsic
+}
+
+// Proof that the extrinsic was authorised by the holder of the signer's key
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtrinsicSignature {
+    pub signer: PublicKey,
+    pub signature: Signature,
+}
+
+impl Encode for ExtrinsicSignature {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.signer.encode_to(dest);
+        self.signature.encode_to(dest);
+    }
+}
+
+impl Decode for ExtrinsicSignature {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            signer: Decode::decode(input)?,
+            signature: Decode::decode(input)?,
+        })
+    }
+}
+
+impl<Caller: Encode, Call: Encode, Nonce: Encode, Tip: Encode + Default> Extrinsic<Caller, Call, Nonce, Tip> {
+    // Create an extrinsic signed by `pair` that pays no tip
+    pub fn new_signed(
+        caller: Caller,
+        call: Call,
+        nonce: Nonce,
+        pair: &Pair,
+        genesis_hash: [u8; 32],
+    ) -> Self {
+        Self::new_signed_with_tip(caller, call, nonce, Tip::default(), pair, genesis_hash)
+    }
+
+    // Create an extrinsic signed by `pair` that pays `tip` on top of its fees
+    pub fn new_signed_with_tip(
+        caller: Caller,
+        call: Call,
+        nonce: Nonce,
+        tip: Tip,
+        pair: &Pair,
+        genesis_hash: [u8; 32],
+    ) -> Self {
+        let payload = Self::signing_payload(&caller, &call, &nonce, &tip, genesis_hash);
+        let signature = ExtrinsicSignature {
+            signer: pair.public(),
+            signature: pair.sign(&payload),
+        };
+        Self {
+            caller,
+            call,
+            nonce,
+            tip,
+            signature: Some(signature),
+        }
+    }
+
+    // The bytes that get signed: the caller, the call, the nonce, the tip and the genesis hash
+    // Including the caller binds the signature to the account it spends from,
+    // and including the genesis hash stops an extrinsic from being replayed on another chain
+    pub fn signing_payload(
+        caller: &Caller,
+        call: &Call,
+        nonce: &Nonce,
+        tip: &Tip,
+        genesis_hash: [u8; 32],
+    ) -> Vec<u8> {
+        let mut payload = caller.encode();
+        call.encode_to(&mut payload);
+        nonce.encode_to(&mut payload);
+        tip.encode_to(&mut payload);
+        genesis_hash.encode_to(&mut payload);
+        payload
+    }
+
+    // Check the signature against `genesis_hash`
+    // Returns the signer's public key, or None if the extrinsic is unsigned or badly signed
+    pub fn verify_signature(&self, genesis_hash: [u8; 32]) -> Option<PublicKey> {
+        let ExtrinsicSignature { signer, signature } = self.signature.as_ref()?;
+        let payload = Self::signing_payload(&self.caller, &self.call, &self.nonce, &self.tip, genesis_hash);
+        verify(signer, &payload, signature).then_some(*signer)
+    }
+}
+
+// Extrinsics are encoded so they can be committed to in the block hash
+impl<Caller: Encode, Call: Encode, Nonce: Encode, Tip: Encode> Encode
+    for Extrinsic<Caller, Call, Nonce, Tip>
+{
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.caller.encode_to(dest);
+        self.call.encode_to(dest);
+        self.nonce.encode_to(dest);
+        self.tip.encode_to(dest);
+        self.signature.encode_to(dest);
+    }
+}
+
+impl<Caller: Decode, Call: Decode, Nonce: Decode, Tip: Decode> Decode
+    for Extrinsic<Caller, Call, Nonce, Tip>
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            caller: Caller::decode(input)?,
+            call: Call::decode(input)?,
+            nonce: Nonce::decode(input)?,
+            tip: Tip::decode(input)?,
+            signature: Decode::decode(input)?,
+        })
+    }
+}
+
+// Hash arbitrary bytes into a 32-byte digest using BLAKE2b-256
+// This is the same hash function Substrate uses for block hashes
+pub fn blake2_256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2b::<U32>::new();
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+// Reasons an extrinsic is not valid for inclusion in a block
+// Unlike a dispatch error, an invalid extrinsic is never executed and never touches state
+#[derive(Debug, PartialEq, Clone)]
+pub enum InvalidTransaction {
+    Unsigned,     // The extrinsic carries no signature
+    BadSignature, // The signature does not match the signed payload
+    WrongSigner,  // The signer is not the caller's key, registered or named after
+    Stale,        // The nonce has already been used
+    Future,       // The nonce is ahead of the caller's next nonce
+    Payment,      // The caller cannot pay the tip
+    ExhaustsResources, // The block has no weight left for the extrinsic
+}
+
+impl std::fmt::Display for InvalidTransaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidTransaction::Unsigned => write!(f, "Extrinsic is not signed"),
+            InvalidTransaction::BadSignature => write!(f, "Bad signature"),
+            InvalidTransaction::WrongSigner => write!(f, "Signer is not the caller's registered key"),
+            InvalidTransaction::Stale => write!(f, "Nonce has already been used"),
+            InvalidTransaction::Future => write!(f, "Nonce is too far in the future"),
+            InvalidTransaction::Payment => write!(f, "Caller cannot pay the tip"),
+            InvalidTransaction::ExhaustsResources => write!(f, "Block weight limit reached"),
+        }
+    }
+}
+
+// Weight measures how much work executing a call takes
+pub type Weight = u64;
+
+// Every call declares its weight so blocks can be kept within a weight limit
+pub trait GetWeight {
+    fn weight(&self) -> Weight;
+}
+
+// Why a dispatched call failed
+// It owns its strings so it can be stored in block receipts and read back from disk
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispatchError {
+    // An error returned by a pallet, named by the pallet and the error variant
+    Module {
+        pallet: String,
+        error: String,
+        message: String,
+    },
+    // A failure that does not come from a pallet error type
+    Other(String),
+}
+
+impl DispatchError {
+    // Wrap the concrete error of a pallet
+    pub fn module<E: core::fmt::Debug + core::fmt::Display>(pallet: &str, error: E) -> Self {
+        DispatchError::Module {
+            pallet: pallet.to_string(),
+            error: format!("{:?}", error),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<&'static str> for DispatchError {
+    fn from(message: &'static str) -> Self {
+        DispatchError::Other(message.to_string())
+    }
+}
+
+impl Encode for DispatchError {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            DispatchError::Module { pallet, error, message } => {
+                dest.push(0);
+                pallet.encode_to(dest);
+                error.encode_to(dest);
+                message.encode_to(dest);
+            }
+            DispatchError::Other(message) => {
+                dest.push(1);
+                message.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl Decode for DispatchError {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(DispatchError::Module {
+                pallet: Decode::decode(input)?,
+                error: Decode::decode(input)?,
+                message: Decode::decode(input)?,
+            }),
+            1 => Ok(DispatchError::Other(Decode::decode(input)?)),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+impl std::fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchError::Module { pallet, error, message } => {
+                write!(f, "{}::{}: {}", pallet, error, message)
+            }
+            DispatchError::Other(message) => write!(f, "{}", message),
+        }
+    }
 }
 
 // Result type for runtime operations
-pub type DispatchResult = Result<(), &'static str>;
+pub type DispatchResult = Result<(), DispatchError>;
 
 // A trait for handling incoming extrinsics
 // This is the core dispatch mechanism - any pallet that can handle transactions implements this
@@ -39,6 +325,94 @@ pub trait Dispatch {
     fn dispatch(&mut self, caller: Self::Caller, call: Self::Call) -> DispatchResult;
 }
 
+// A trait for anything whose storage can be saved and restored
+// A checkpoint only needs to hold the storage a dispatched call can change
+pub trait Transactional {
+    type Checkpoint;
+
+    // Save the current storage
+    fn checkpoint(&self) -> Self::Checkpoint;
+
+    // Put back storage saved by `checkpoint`, discarding every change made since
+    fn restore(&mut self, checkpoint: Self::Checkpoint);
+}
+
+// Run `f` as a single storage transaction
+// If it returns an error, every change it made is reverted, so the state is either
+// fully updated or left exactly as it was. Transactions can be nested.
+pub fn with_transaction<S, R, E>(
+    state: &mut S,
+    f: impl FnOnce(&mut S) -> Result<R, E>,
+) -> Result<R, E>
+where
+    S: Transactional,
+{
+    let checkpoint = state.checkpoint();
+    let result = f(state);
+    if result.is_err() {
+        state.restore(checkpoint);
+    }
+    result
+}
+
+// A single entry of pallet storage: an encoded key and its encoded value
+pub type StorageEntry = (Vec<u8>, Vec<u8>);
+
+// A trait for pallets whose storage is committed to in the state root
+// Every stored item must be listed, so two nodes with the same entries have the same state
+pub trait StorageEntries {
+    fn storage_entries(&self) -> Vec<StorageEntry>;
+}
+
+// Build a storage key: "Pallet:Item:" followed by the encoded map key
+// The prefix keeps items of different pallets apart once all entries are sorted together
+pub fn storage_key<K: Encode + ?Sized>(pallet: &str, item: &str, key: &K) -> Vec<u8> {
+    let mut storage_key = format!("{}:{}:", pallet, item).into_bytes();
+    key.encode_to(&mut storage_key);
+    storage_key
+}
+
+// Compute the root of a binary Merkle tree over the given leaves
+// Pairs of nodes are hashed together level by level; an odd node out is carried up unchanged
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut node = left.to_vec();
+                    node.extend_from_slice(right);
+                    blake2_256(&node)
+                }
+                [single] => *single,
+                _ => unreachable!("chunks(2) yields one or two nodes"),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+// Compute the state root over storage entries from any number of pallets
+// Entries are sorted by key first, so the root does not depend on the order they were collected in
+pub fn state_root(mut entries: Vec<StorageEntry>) -> [u8; 32] {
+    entries.sort();
+    let leaves: Vec<[u8; 32]> = entries
+        .iter()
+        .map(|(key, value)| {
+            let mut leaf = Vec::new();
+            key.encode_to(&mut leaf);
+            value.encode_to(&mut leaf);
+            blake2_256(&leaf)
+        })
+        .collect();
+    merkle_root(&leaves)
+}
+
 /*
 EXPLANATION OF GENERICS IN THIS FILE:
 
@@ -51,10 +425,12 @@ EXPLANATION OF GENERICS IN THIS FILE:
    - Can work with u32, u64, or any other number type
    - Example: Header<u32> or Header<u64>
 
-3. Extrinsic<Caller, Call>:
+3. Extrinsic<Caller, Call, Nonce, Tip>:
    - Can work with any caller type (String, u32, AccountId, etc.)
    - Can work with any call type (different pallets have different calls)
-   - Example: Extrinsic<String, BalanceCall> or Extrinsic<u32, SystemCall>
+   - Can work with any nonce type (u32, u64, etc.)
+   - Can work with any tip type (usually the chain's balance type)
+   - Example: Extrinsic<String, BalanceCall, u32, u128> or Extrinsic<u32, SystemCall, u64, u64>
 
 4. Dispatch trait:
    - Associated types (type Caller, type Call) let implementers specify their types
@@ -67,3 +443,61 @@ WHY USE GENERICS?
 - Flexibility: Easy to change types without rewriting code
 - Performance: No runtime overhead - generics are compiled away
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips_through_bytes() {
+        let header = Header {
+            block_number: 7u32,
+            parent_hash: [1u8; 32],
+            state_root: [2u8; 32],
+            extrinsics_root: [3u8; 32],
+            timestamp: 1_000,
+        };
+        let bytes = header.encode();
+        assert_eq!(bytes.len(), 4 + 3 * 32 + 8);
+        assert_eq!(Header::decode_all(&bytes), Ok(header));
+    }
+
+    #[test]
+    fn merkle_root_of_small_trees() {
+        let a = blake2_256(b"a");
+        let b = blake2_256(b"b");
+        let c = blake2_256(b"c");
+
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+        assert_eq!(merkle_root(&[a]), a);
+
+        let ab = blake2_256(&[a, b].concat());
+        assert_eq!(merkle_root(&[a, b]), ab);
+        // The odd leaf is carried up to be paired at the next level
+        assert_eq!(merkle_root(&[a, b, c]), blake2_256(&[ab, c].concat()));
+        assert_ne!(merkle_root(&[a, b]), merkle_root(&[b, a]));
+    }
+
+    #[test]
+    fn state_root_is_independent_of_entry_order() {
+        let alice = (storage_key("Balances", "Balance", "alice"), 100u128.encode());
+        let bob = (storage_key("Balances", "Balance", "bob"), 50u128.encode());
+
+        assert_eq!(
+            state_root(vec![alice.clone(), bob.clone()]),
+            state_root(vec![bob.clone(), alice.clone()])
+        );
+
+        let bob_changed = (storage_key("Balances", "Balance", "bob"), 51u128.encode());
+        assert_ne!(state_root(vec![alice.clone(), bob]), state_root(vec![alice, bob_changed]));
+    }
+
+    #[test]
+    fn storage_keys_are_prefixed_by_pallet_and_item() {
+        assert_ne!(
+            storage_key("System", "Nonce", "alice"),
+            storage_key("Balances", "Balance", "alice")
+        );
+        assert!(storage_key("System", "Nonce", "alice").starts_with(b"System:Nonce:"));
+    }
+}
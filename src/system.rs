@@ -1,18 +1,82 @@
 use std::collections::BTreeMap;
 use num::traits::{One, Zero};
+use core::fmt::Debug;
 use core::ops::AddAssign;
+use crate::codec::{read_variant, Decode, DecodeError, Encode};
+use crate::crypto::PublicKey;
+use crate::metadata::{variant, PalletInfo, VariantMetadata};
+use crate::migrations::{MigrationError, OnRuntimeUpgrade, StorageVersion};
+use crate::support::{
+    blake2_256, storage_key, DispatchError, Header, InvalidTransaction, StorageEntries, StorageEntry,
+    Transactional, Weight,
+};
 
 pub trait Config {
-    type AccountId: Ord + Clone;                    
-    type BlockNumber: Zero + One + AddAssign + Copy + PartialOrd + Ord; 
-    type Nonce: Zero + One + Copy;                 
+    type AccountId: Ord + Clone + Encode + Decode;
+    type BlockNumber: Zero + One + AddAssign + Copy + PartialOrd + Ord + Debug + Encode + Decode;
+    type Nonce: Zero + One + Copy + Ord + Encode + Decode;
+    // The runtime's aggregate of every pallet's events
+    type RuntimeEvent: Debug + Clone;
 }
 
-#[derive(Debug)]
+// Events emitted by the system pallet itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum SystemEvent {
+    ExtrinsicSuccess,
+    ExtrinsicFailed { error: DispatchError },
+}
+
+// Events are encoded like calls, as a variant index followed by their fields
+impl Encode for SystemEvent {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            SystemEvent::ExtrinsicSuccess => dest.push(0),
+            SystemEvent::ExtrinsicFailed { error } => {
+                dest.push(1);
+                error.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl Decode for SystemEvent {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(SystemEvent::ExtrinsicSuccess),
+            1 => Ok(SystemEvent::ExtrinsicFailed {
+                error: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+// An event along with the block and extrinsic that emitted it
+// Events emitted while initializing or finalizing a block have no extrinsic index
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord<Event, BlockNumber> {
+    pub block_number: BlockNumber,
+    pub extrinsic_index: Option<u32>,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone)]
 pub struct Pallet<T: Config> {
     pub block_number: T::BlockNumber,
     pub nonce: BTreeMap<T::AccountId, T::Nonce>,
+    // Next nonce of each reaped account, so its old extrinsics cannot be replayed once it is funded again
+    pub nonce_floor: BTreeMap<T::AccountId, T::Nonce>,
+    pub account_keys: BTreeMap<T::AccountId, PublicKey>, // Key allowed to sign for each account
     pub block_hashes: BTreeMap<T::BlockNumber, [u8; 32]>,
+    pub headers: BTreeMap<T::BlockNumber, Header<T::BlockNumber>>,
+    // Events of the current block, cleared when the next block starts
+    events: Vec<EventRecord<T::RuntimeEvent, T::BlockNumber>>,
+    // Header data for the block being built
+    extrinsic_hashes: Vec<[u8; 32]>,
+    state_root: [u8; 32],
+    timestamp: u64,
+    // Weight used by the extrinsics of the block being built
+    block_weight: Weight,
 }
 
 impl<T: Config> Pallet<T> {
@@ -21,7 +85,15 @@ impl<T: Config> Pallet<T> {
         Self {
             block_number: T::BlockNumber::zero(),
             nonce: BTreeMap::new(),
+            nonce_floor: BTreeMap::new(),
+            account_keys: BTreeMap::new(),
             block_hashes: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            events: Vec::new(),
+            extrinsic_hashes: Vec::new(),
+            state_root: [0u8; 32],
+            timestamp: 0,
+            block_weight: 0,
         }
     }
 
@@ -37,61 +109,167 @@ impl<T: Config> Pallet<T> {
 
     /// Increase the nonce value of the caller `who`
     pub fn inc_nonce(&mut self, who: &T::AccountId) {
-        let nonce = *self.nonce.get(who).unwrap_or(&T::Nonce::zero());
+        let nonce = self.account_nonce(who);
         let new_nonce = nonce + T::Nonce::one();
         self.nonce.insert(who.clone(), new_nonce);
+        self.nonce_floor.remove(who);
     }
 
-    /// Generate block hash based on block number and nonce data
-    fn generate_block_hash(&self) -> [u8; 32] {
-        let mut hash = [0u8; 32];
+    /// Forget the nonce of `who`, whose account no longer exists
+    /// Their key is kept, so the account can still sign once it is funded again,
+    /// and their nonce is kept as a floor, so it carries on from where it was rather than from zero
+    pub fn kill_account(&mut self, who: &T::AccountId) {
+        if let Some(nonce) = self.nonce.remove(who) {
+            self.nonce_floor.insert(who.clone(), nonce);
+        }
+    }
+
+    /// Get the next nonce `who` must use
+    pub fn account_nonce(&self, who: &T::AccountId) -> T::Nonce {
+        let nonce = self.nonce.get(who).or_else(|| self.nonce_floor.get(who));
+        *nonce.unwrap_or(&T::Nonce::zero())
+    }
 
-        // Convert block number to a simple representation for hashing
-        // This is a simplified approach - in reality you'd use proper serialization
-        let block_bytes = if self.block_number == T::BlockNumber::zero() {
-            0u32.to_be_bytes()
+    /// Check a nonce carried by an extrinsic from `who`
+    /// Only the account's next nonce is valid: lower ones were already used,
+    /// higher ones have to wait until the nonces before them are used
+    pub fn check_nonce(&self, who: &T::AccountId, nonce: T::Nonce) -> Result<(), InvalidTransaction> {
+        let expected = self.account_nonce(who);
+        if nonce < expected {
+            Err(InvalidTransaction::Stale)
+        } else if nonce > expected {
+            Err(InvalidTransaction::Future)
         } else {
-            // For simplicity, we'll use the count of all block hashes as a proxy
-            (self.block_hashes.len() as u32).to_be_bytes()
-        };
-        
-        hash[0..4].copy_from_slice(&block_bytes);
+            Ok(())
+        }
+    }
 
-        // Include nonce data in hash
-        let nonce_count = self.nonce.len() as u32;
-        let nonce_bytes = nonce_count.to_be_bytes();
-        hash[4..8].copy_from_slice(&nonce_bytes);
+    /// Register the public key that signs extrinsics for `who`
+    pub fn set_account_key(&mut self, who: &T::AccountId, key: PublicKey) {
+        self.account_keys.insert(who.clone(), key);
+    }
 
-        // Include previous block hash if available
-        if let Some(parent_hash) = self.get_parent_hash_for_generation() {
-            hash[8..16].copy_from_slice(&parent_hash[0..8]);
-        }
+    /// Get the public key registered for `who`, if any
+    pub fn account_key(&self, who: &T::AccountId) -> Option<PublicKey> {
+        self.account_keys.get(who).copied()
+    }
+
+    /// Record an extrinsic executed in the current block and return its index
+    /// Only its hash is kept - that is all the block hash needs to commit to it
+    pub fn note_extrinsic(&mut self, encoded_extrinsic: &[u8]) -> u32 {
+        self.extrinsic_hashes.push(blake2_256(encoded_extrinsic));
+        (self.extrinsic_hashes.len() - 1) as u32
+    }
 
-        // Fill the rest with a pattern
-        for i in 16..32 {
-            hash[i] = ((i + nonce_count as usize) % 256) as u8;
+    /// Use up `weight` of the current block if it still fits under `max_block_weight`
+    pub fn register_weight(
+        &mut self,
+        weight: Weight,
+        max_block_weight: Weight,
+    ) -> Result<(), InvalidTransaction> {
+        self.check_weight(weight, max_block_weight)?;
+        self.block_weight = self.block_weight.saturating_add(weight);
+        Ok(())
+    }
+
+    /// Check that `weight` still fits in the block being built, without using it up
+    pub fn check_weight(&self, weight: Weight, max_block_weight: Weight) -> Result<(), InvalidTransaction> {
+        if self.block_weight.saturating_add(weight) > max_block_weight {
+            return Err(InvalidTransaction::ExhaustsResources);
         }
+        Ok(())
+    }
 
-        hash
+    /// Get the weight used so far by the block being built
+    pub fn block_weight(&self) -> Weight {
+        self.block_weight
     }
 
-    /// Helper function to get parent hash during generation
-    fn get_parent_hash_for_generation(&self) -> Option<[u8; 32]> {
-        if self.block_hashes.is_empty() {
-            None
-        } else {
-            // Get the most recent hash
-            self.block_hashes.values().last().copied()
+    /// Record an event in the current block
+    pub fn deposit_event(
+        &mut self,
+        extrinsic_index: Option<u32>,
+        event: impl Into<T::RuntimeEvent>,
+    ) {
+        self.events.push(EventRecord {
+            block_number: self.block_number,
+            extrinsic_index,
+            event: event.into(),
+        });
+    }
+
+    /// Get the events recorded in the current block
+    pub fn events(&self) -> &[EventRecord<T::RuntimeEvent, T::BlockNumber>] {
+        &self.events
+    }
+
+    /// Clear the events - called at the start of every block
+    pub fn reset_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// Compute the root committing to an ordered list of extrinsic hashes
+    pub fn extrinsics_root(extrinsic_hashes: &[[u8; 32]]) -> [u8; 32] {
+        blake2_256(&extrinsic_hashes.encode())
+    }
+
+    /// Set the timestamp of the block being built
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+    }
+
+    /// Get the timestamp of the block being built (or of the last finalized block)
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Record the state root reached after executing the block being built
+    pub fn note_state_root(&mut self, state_root: [u8; 32]) {
+        self.state_root = state_root;
+    }
+
+    /// Build the header of the block being built from the data noted so far
+    pub fn current_header(&self) -> Header<T::BlockNumber> {
+        Header {
+            block_number: self.block_number,
+            parent_hash: self.parent_block_hash().unwrap_or([0u8; 32]),
+            state_root: self.state_root,
+            extrinsics_root: Self::extrinsics_root(&self.extrinsic_hashes),
+            timestamp: self.timestamp,
         }
     }
 
     /// Finalize the current block and generate its hash
+    /// The hash commits to every header field, including the noted extrinsics
     pub fn finalize_block(&mut self) -> [u8; 32] {
-        let hash = self.generate_block_hash();
+        let header = self.current_header();
+        let hash = header.hash();
+
+        self.extrinsic_hashes.clear();
+        self.block_weight = 0;
+        self.headers.insert(self.block_number, header);
         self.block_hashes.insert(self.block_number, hash);
         hash
     }
 
+    /// Recompute the hash of a finalized block and check it against the stored one
+    /// Also checks that the stored header points at the previous block
+    pub fn verify_block_hash(&self, block_number: T::BlockNumber) -> bool {
+        let (Some(stored_hash), Some(header)) =
+            (self.get_block_hash(block_number), self.get_header(block_number))
+        else {
+            return false;
+        };
+
+        let parent_hash = self.previous_block_hash(block_number).unwrap_or([0u8; 32]);
+        header.parent_hash == parent_hash && header.hash() == stored_hash
+    }
+
+    /// Get the header of a finalized block
+    pub fn get_header(&self, block_number: T::BlockNumber) -> Option<&Header<T::BlockNumber>> {
+        self.headers.get(&block_number)
+    }
+
     /// Get block hash for a specific block number
     pub fn get_block_hash(&self, block_number: T::BlockNumber) -> Option<[u8; 32]> {
         self.block_hashes.get(&block_number).copied()
@@ -104,30 +282,15 @@ impl<T: Config> Pallet<T> {
 
     /// Get the hash of the parent block
     pub fn parent_block_hash(&self) -> Option<[u8; 32]> {
-        if self.block_number > T::BlockNumber::zero() {
-            // Find the previous block number
-            let mut prev_block = None;
-            for &block_num in self.block_hashes.keys() {
-                if block_num < self.block_number {
-                    match prev_block {
-                        None => prev_block = Some(block_num),
-                        Some(current_prev) => {
-                            if block_num > current_prev {
-                                prev_block = Some(block_num);
-                            }
-                        }
-                    }
-                }
-            }
-            
-            if let Some(prev) = prev_block {
-                self.get_block_hash(prev)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        self.previous_block_hash(self.block_number)
+    }
+
+    /// Get the hash of the closest finalized block below `block_number`
+    fn previous_block_hash(&self, block_number: T::BlockNumber) -> Option<[u8; 32]> {
+        self.block_hashes
+            .range(..block_number)
+            .next_back()
+            .map(|(_, hash)| *hash)
     }
 
     /// Get all block hashes
@@ -141,6 +304,121 @@ impl<T: Config> Pallet<T> {
     }
 }
 
+// Dispatched calls can only change account data; block history is left alone
+impl<T: Config> Transactional for Pallet<T> {
+    type Checkpoint = (
+        BTreeMap<T::AccountId, T::Nonce>,
+        BTreeMap<T::AccountId, T::Nonce>,
+        BTreeMap<T::AccountId, PublicKey>,
+    );
+
+    fn checkpoint(&self) -> Self::Checkpoint {
+        (self.nonce.clone(), self.nonce_floor.clone(), self.account_keys.clone())
+    }
+
+    fn restore(&mut self, checkpoint: Self::Checkpoint) {
+        (self.nonce, self.nonce_floor, self.account_keys) = checkpoint;
+    }
+}
+
+// The whole pallet is encoded when the runtime state is saved to disk
+// State is only saved between blocks, so the data of a block being built
+// (extrinsic hashes, used weight and events) is not included
+impl<T: Config> Encode for Pallet<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.block_number.encode_to(dest);
+        self.nonce.encode_to(dest);
+        self.account_keys.encode_to(dest);
+        self.block_hashes.encode_to(dest);
+        self.headers.encode_to(dest);
+        self.state_root.encode_to(dest);
+        self.timestamp.encode_to(dest);
+        self.nonce_floor.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for Pallet<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            block_number: Decode::decode(input)?,
+            nonce: Decode::decode(input)?,
+            account_keys: Decode::decode(input)?,
+            block_hashes: Decode::decode(input)?,
+            headers: Decode::decode(input)?,
+            events: Vec::new(),
+            extrinsic_hashes: Vec::new(),
+            state_root: Decode::decode(input)?,
+            timestamp: Decode::decode(input)?,
+            block_weight: 0,
+            nonce_floor: Decode::decode(input)?,
+        })
+    }
+}
+
+// The system pallet has no calls; its events report the outcome of every extrinsic
+impl<T: Config> PalletInfo for Pallet<T> {
+    fn events() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "ExtrinsicSuccess", &[], "An extrinsic's call succeeded"),
+            variant(1, "ExtrinsicFailed", &[("error", "DispatchError")], "An extrinsic's call failed and was reverted"),
+        ]
+    }
+}
+
+// Version 0 is the pallet before it was made generic, which stored only the
+// block number, account nonces and block hashes. Its blocks had no headers and
+// its accounts no signing keys, so those start out empty
+// Version 2 added the nonce floors of reaped accounts; none were kept before
+impl<T: Config> OnRuntimeUpgrade for Pallet<T> {
+    const NAME: &'static str = "System";
+    const STORAGE_VERSION: StorageVersion = 2;
+
+    fn on_runtime_upgrade(from: StorageVersion, state: Vec<u8>) -> Result<Vec<u8>, MigrationError> {
+        match from {
+            0 => {
+                let mut input = state.as_slice();
+                let mut pallet = Self::new();
+                pallet.block_number = Decode::decode(&mut input)?;
+                pallet.nonce = Decode::decode(&mut input)?;
+                pallet.block_hashes = Decode::decode(&mut input)?;
+                if !input.is_empty() {
+                    return Err(DecodeError::TrailingBytes(input.len()).into());
+                }
+                // The version 1 layout is the current one without the nonce floors at the end
+                let mut state = pallet.encode();
+                state.truncate(state.len() - pallet.nonce_floor.encode().len());
+                Ok(state)
+            }
+            1 => {
+                let mut state = state;
+                BTreeMap::<T::AccountId, T::Nonce>::new().encode_to(&mut state);
+                Ok(state)
+            }
+            _ => Err(MigrationError::NoMigration(Self::NAME, from)),
+        }
+    }
+}
+
+// The system pallet commits to every account nonce, nonce floor and signing key
+impl<T: Config> StorageEntries for Pallet<T> {
+    fn storage_entries(&self) -> Vec<StorageEntry> {
+        let nonces = self
+            .nonce
+            .iter()
+            .map(|(who, nonce)| (storage_key("System", "Nonce", who), nonce.encode()));
+        let floors = self
+            .nonce_floor
+            .iter()
+            .map(|(who, nonce)| (storage_key("System", "NonceFloor", who), nonce.encode()));
+        let keys = self
+            .account_keys
+            .iter()
+            .map(|(who, key)| (storage_key("System", "AccountKey", who), key.encode()));
+
+        nonces.chain(floors).chain(keys).collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,6 +429,7 @@ mod tests {
         type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = SystemEvent;
     }
 
     #[test]
@@ -165,6 +444,20 @@ mod tests {
         assert_eq!(system.nonce.get("Faithful"), None);
     }
 
+    #[test]
+    fn check_nonce() {
+        let mut system = Pallet::<TestConfig>::new();
+        let temi = "Temi".to_string();
+
+        assert_eq!(system.check_nonce(&temi, 0), Ok(()));
+        assert_eq!(system.check_nonce(&temi, 1), Err(InvalidTransaction::Future));
+
+        system.inc_nonce(&temi);
+        assert_eq!(system.account_nonce(&temi), 1);
+        assert_eq!(system.check_nonce(&temi, 0), Err(InvalidTransaction::Stale));
+        assert_eq!(system.check_nonce(&temi, 1), Ok(()));
+    }
+
     #[test]
     fn test_block_hash_generation() {
         let mut system = Pallet::<TestConfig>::new();
@@ -214,12 +507,24 @@ mod tests {
         let genesis_hash = system.finalize_block();
         
         system.inc_block_number();
-        let block_1_hash = system.finalize_block();
+        let _block_1_hash = system.finalize_block();
         
         // Now block 1 should have genesis as parent
         assert_eq!(system.parent_block_hash(), Some(genesis_hash));
     }
 
+    #[test]
+    fn test_parent_is_not_the_current_block() {
+        let mut system = Pallet::<TestConfig>::new();
+
+        system.finalize_block();
+        system.inc_block_number();
+        let block_1_hash = system.finalize_block();
+
+        // The parent of block 1 is genesis, never block 1 itself
+        assert_ne!(system.parent_block_hash(), Some(block_1_hash));
+    }
+
     #[test]
     fn test_all_block_hashes() {
         let mut system = Pallet::<TestConfig>::new();
@@ -239,4 +544,92 @@ mod tests {
         assert_eq!(all_hashes.get(&1), Some(&hash_1));
         assert_eq!(all_hashes.get(&2), Some(&hash_2));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_block_hash_commits_to_extrinsics() {
+        let mut system_a = Pallet::<TestConfig>::new();
+        let mut system_b = Pallet::<TestConfig>::new();
+
+        system_a.note_extrinsic(b"alice pays bob 10");
+        system_b.note_extrinsic(b"alice pays bob 11");
+
+        // Same block number and account count, different extrinsics
+        assert_ne!(system_a.finalize_block(), system_b.finalize_block());
+    }
+
+    #[test]
+    fn test_verify_block_hash() {
+        let mut system = Pallet::<TestConfig>::new();
+
+        system.finalize_block();
+        system.inc_block_number();
+        system.note_extrinsic(b"transfer");
+        system.finalize_block();
+
+        assert!(system.verify_block_hash(0));
+        assert!(system.verify_block_hash(1));
+        assert!(!system.verify_block_hash(2));
+
+        // Tampering with a stored hash is detected
+        system.block_hashes.insert(1, [0u8; 32]);
+        assert!(!system.verify_block_hash(1));
+    }
+
+    #[test]
+    fn test_finalized_header_fields() {
+        let mut system = Pallet::<TestConfig>::new();
+
+        let genesis_hash = system.finalize_block();
+        system.inc_block_number();
+        system.set_timestamp(1_000);
+        system.note_state_root([7u8; 32]);
+        system.note_extrinsic(b"transfer");
+        let block_1_hash = system.finalize_block();
+
+        let header = system.get_header(1).unwrap();
+        assert_eq!(header.block_number, 1);
+        assert_eq!(header.parent_hash, genesis_hash);
+        assert_eq!(header.state_root, [7u8; 32]);
+        assert_eq!(header.timestamp, 1_000);
+        assert_eq!(header.hash(), block_1_hash);
+        assert_ne!(header.extrinsics_root, system.get_header(0).unwrap().extrinsics_root);
+
+        // Every header field is committed to in the hash
+        let mut tampered = header.clone();
+        tampered.timestamp += 1;
+        assert_ne!(tampered.hash(), block_1_hash);
+    }
+
+    #[test]
+    fn events_record_block_and_extrinsic() {
+        let mut system = Pallet::<TestConfig>::new();
+        system.inc_block_number();
+
+        let index = system.note_extrinsic(b"transfer");
+        system.deposit_event(Some(index), SystemEvent::ExtrinsicSuccess);
+        system.deposit_event(None, SystemEvent::ExtrinsicFailed { error: "oops".into() });
+
+        assert_eq!(
+            system.events()[0],
+            EventRecord { block_number: 1, extrinsic_index: Some(0), event: SystemEvent::ExtrinsicSuccess }
+        );
+        assert_eq!(system.events()[1].extrinsic_index, None);
+
+        system.reset_events();
+        assert!(system.events().is_empty());
+    }
+
+    #[test]
+    fn block_weight_is_limited() {
+        let mut system = Pallet::<TestConfig>::new();
+
+        assert_eq!(system.register_weight(60, 100), Ok(()));
+        assert_eq!(system.register_weight(60, 100), Err(InvalidTransaction::ExhaustsResources));
+        assert_eq!(system.register_weight(40, 100), Ok(()));
+        assert_eq!(system.block_weight(), 100);
+
+        // The next block starts empty
+        system.finalize_block();
+        assert_eq!(system.block_weight(), 0);
+    }
+}
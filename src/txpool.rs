@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use core::ops::Add;
+use num::traits::One;
+use crate::codec::Encode;
+use crate::support::InvalidTransaction;
+
+// Priority of a transaction in the pool - higher priority is included first
+pub type TransactionPriority = u64;
+
+// Most transactions the pool holds at once, ready or not
+pub const MAX_POOL_SIZE: usize = 1024;
+// Most transactions one sender may have waiting on earlier nonces
+pub const MAX_FUTURE_PER_SENDER: usize = 16;
+
+// What validating a transaction against the current state tells the pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidTransaction<AccountId, Nonce> {
+    pub sender: AccountId,
+    pub nonce: Nonce,
+    pub priority: TransactionPriority,
+}
+
+// The chain state the pool checks transactions against
+// This is implemented by the runtime so the pool does not need to know about pallets
+pub trait TransactionValidator<Extrinsic> {
+    type AccountId: Ord + Clone;
+    type Nonce: Ord + Copy + One + Add<Output = Self::Nonce>;
+
+    // Check everything except whether the nonce is the sender's next one:
+    // used nonces are invalid, but nonces in the future are left for the pool to queue
+    fn validate_transaction(
+        &self,
+        extrinsic: &Extrinsic,
+    ) -> Result<ValidTransaction<Self::AccountId, Self::Nonce>, InvalidTransaction>;
+
+    // The next nonce `who` must use
+    fn account_nonce(&self, who: &Self::AccountId) -> Self::Nonce;
+}
+
+// Where a transaction ended up after being submitted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionStatus {
+    Ready,  // It can go in the next block
+    Future, // It waits for transactions with lower nonces from the same sender
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolError {
+    Invalid(InvalidTransaction), // The transaction can never be included
+    AlreadyImported,             // The exact same transaction is already in the pool
+    TooLowPriority,              // Another transaction with this nonce pays at least as much
+    Full,                        // The pool already holds `MAX_POOL_SIZE` transactions
+    TooManyFuture,               // The sender already has `MAX_FUTURE_PER_SENDER` transactions waiting
+}
+
+impl std::fmt::Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolError::Invalid(e) => write!(f, "Invalid transaction: {}", e),
+            PoolError::AlreadyImported => write!(f, "Transaction is already in the pool"),
+            PoolError::TooLowPriority => {
+                write!(f, "Priority is too low to replace the transaction with the same nonce")
+            }
+            PoolError::Full => write!(f, "Transaction pool is full"),
+            PoolError::TooManyFuture => {
+                write!(f, "Sender has too many transactions waiting on earlier nonces")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PooledTransaction<Extrinsic> {
+    extrinsic: Extrinsic,
+    priority: TransactionPriority,
+    insertion: u64, // Submission order, used to break priority ties
+}
+
+// A pool of submitted transactions waiting to be included in a block
+// Transactions are kept per sender and ordered by nonce, so a sender's transactions
+// are always handed out in nonce order while different senders compete on priority
+#[derive(Debug, Clone)]
+pub struct TransactionPool<AccountId, Nonce, Extrinsic> {
+    by_sender: BTreeMap<AccountId, BTreeMap<Nonce, PooledTransaction<Extrinsic>>>,
+    next_insertion: u64,
+}
+
+impl<AccountId, Nonce, Extrinsic> TransactionPool<AccountId, Nonce, Extrinsic>
+where
+    AccountId: Ord + Clone,
+    Nonce: Ord + Copy + One + Add<Output = Nonce>,
+    Extrinsic: Clone + Encode,
+{
+    pub fn new() -> Self {
+        Self {
+            by_sender: BTreeMap::new(),
+            next_insertion: 0,
+        }
+    }
+
+    // Validate a transaction and add it to the pool
+    // A transaction with the same sender and nonce as one already in the pool
+    // only replaces it if it has a higher priority. Anything else has to fit in the pool,
+    // and if it waits on earlier nonces, in its sender's share of waiting transactions
+    pub fn submit<V>(&mut self, validator: &V, extrinsic: Extrinsic) -> Result<TransactionStatus, PoolError>
+    where
+        V: TransactionValidator<Extrinsic, AccountId = AccountId, Nonce = Nonce>,
+    {
+        let valid = validator
+            .validate_transaction(&extrinsic)
+            .map_err(PoolError::Invalid)?;
+
+        let replaced = self
+            .by_sender
+            .get(&valid.sender)
+            .and_then(|queue| queue.get(&valid.nonce));
+        if let Some(existing) = replaced {
+            if existing.extrinsic.encode() == extrinsic.encode() {
+                return Err(PoolError::AlreadyImported);
+            }
+            if existing.priority >= valid.priority {
+                return Err(PoolError::TooLowPriority);
+            }
+        } else if self.len() >= MAX_POOL_SIZE {
+            return Err(PoolError::Full);
+        }
+        let replacing = replaced.is_some();
+
+        let queue = self.by_sender.entry(valid.sender.clone()).or_default();
+        queue.insert(
+            valid.nonce,
+            PooledTransaction {
+                extrinsic,
+                priority: valid.priority,
+                insertion: self.next_insertion,
+            },
+        );
+        let ready_count = Self::ready_queue(queue, validator.account_nonce(&valid.sender)).len();
+        let is_ready = queue
+            .keys()
+            .take(ready_count)
+            .any(|nonce| *nonce == valid.nonce);
+        if !is_ready && !replacing && queue.len() - ready_count > MAX_FUTURE_PER_SENDER {
+            queue.remove(&valid.nonce);
+            return Err(PoolError::TooManyFuture);
+        }
+        self.next_insertion += 1;
+
+        Ok(if is_ready {
+            TransactionStatus::Ready
+        } else {
+            TransactionStatus::Future
+        })
+    }
+
+    // The transactions that can be included on top of the current state, best first
+    // Each sender's transactions stay in nonce order; between senders the one
+    // whose next transaction has the highest priority goes first
+    pub fn ready<V>(&self, validator: &V) -> Vec<Extrinsic>
+    where
+        V: TransactionValidator<Extrinsic, AccountId = AccountId, Nonce = Nonce>,
+    {
+        let mut queues: Vec<Vec<&PooledTransaction<Extrinsic>>> = self
+            .by_sender
+            .iter()
+            .map(|(sender, queue)| Self::ready_queue(queue, validator.account_nonce(sender)))
+            .filter(|queue| !queue.is_empty())
+            .collect();
+        // Reverse each queue so its next transaction can be popped off the end
+        for queue in &mut queues {
+            queue.reverse();
+        }
+
+        let mut ready = Vec::new();
+        loop {
+            let best = queues
+                .iter()
+                .enumerate()
+                .filter_map(|(i, queue)| queue.last().map(|head| (i, head)))
+                .max_by(|(_, a), (_, b)| {
+                    a.priority
+                        .cmp(&b.priority)
+                        .then(b.insertion.cmp(&a.insertion))
+                })
+                .map(|(i, _)| i);
+            let Some(i) = best else {
+                return ready;
+            };
+            if let Some(pooled) = queues[i].pop() {
+                ready.push(pooled.extrinsic.clone());
+            }
+        }
+    }
+
+    // Re-validate every transaction against the current state, typically after a block
+    // Transactions that were included (their nonce is now used) or that became
+    // invalid are evicted. Returns how many were removed
+    pub fn prune<V>(&mut self, validator: &V) -> usize
+    where
+        V: TransactionValidator<Extrinsic, AccountId = AccountId, Nonce = Nonce>,
+    {
+        let before = self.len();
+        for queue in self.by_sender.values_mut() {
+            queue.retain(|_, pooled| validator.validate_transaction(&pooled.extrinsic).is_ok());
+        }
+        self.by_sender.retain(|_, queue| !queue.is_empty());
+        before - self.len()
+    }
+
+    // Every transaction in the pool, ready or not, by sender and then nonce
+    pub fn transactions(&self) -> Vec<Extrinsic> {
+        self.by_sender
+            .values()
+            .flat_map(|queue| queue.values().map(|pooled| pooled.extrinsic.clone()))
+            .collect()
+    }
+
+    // Number of transactions in the pool, ready or not
+    pub fn len(&self) -> usize {
+        self.by_sender.values().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The run of consecutive nonces starting at the sender's next nonce
+    fn ready_queue(
+        queue: &BTreeMap<Nonce, PooledTransaction<Extrinsic>>,
+        account_nonce: Nonce,
+    ) -> Vec<&PooledTransaction<Extrinsic>> {
+        let mut expected = account_nonce;
+        let mut ready = Vec::new();
+        for (nonce, pooled) in queue.range(account_nonce..) {
+            if *nonce != expected {
+                break;
+            }
+            ready.push(pooled);
+            expected = expected + Nonce::one();
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A stand-in for the runtime: extrinsics are (sender, nonce, priority)
+    // and any nonce below the sender's account nonce is stale
+    struct TestState {
+        nonces: BTreeMap<String, u32>,
+    }
+
+    type TestExtrinsic = (String, (u32, u64));
+
+    impl TransactionValidator<TestExtrinsic> for TestState {
+        type AccountId = String;
+        type Nonce = u32;
+
+        fn validate_transaction(
+            &self,
+            (sender, (nonce, priority)): &TestExtrinsic,
+        ) -> Result<ValidTransaction<String, u32>, InvalidTransaction> {
+            if *nonce < self.account_nonce(sender) {
+                return Err(InvalidTransaction::Stale);
+            }
+            Ok(ValidTransaction {
+                sender: sender.clone(),
+                nonce: *nonce,
+                priority: *priority,
+            })
+        }
+
+        fn account_nonce(&self, who: &String) -> u32 {
+            *self.nonces.get(who).unwrap_or(&0)
+        }
+    }
+
+    fn xt(sender: &str, nonce: u32, priority: u64) -> TestExtrinsic {
+        (sender.to_string(), (nonce, priority))
+    }
+
+    #[test]
+    fn ready_transactions_are_ordered_by_priority_and_nonce() {
+        let state = TestState { nonces: BTreeMap::new() };
+        let mut pool = TransactionPool::new();
+
+        assert_eq!(pool.submit(&state, xt("alice", 1, 50)), Ok(TransactionStatus::Future));
+        assert_eq!(pool.submit(&state, xt("alice", 0, 1)), Ok(TransactionStatus::Ready));
+        assert_eq!(pool.submit(&state, xt("bob", 0, 10)), Ok(TransactionStatus::Ready));
+        assert_eq!(pool.submit(&state, xt("bob", 2, 99)), Ok(TransactionStatus::Future));
+
+        // bob's 0 pays more than alice's 0; alice's 1 only becomes available after her 0
+        assert_eq!(
+            pool.ready(&state),
+            vec![xt("bob", 0, 10), xt("alice", 0, 1), xt("alice", 1, 50)]
+        );
+        assert_eq!(pool.len(), 4);
+    }
+
+    #[test]
+    fn replacement_needs_a_higher_priority() {
+        let state = TestState { nonces: BTreeMap::new() };
+        let mut pool = TransactionPool::new();
+
+        pool.submit(&state, xt("alice", 0, 5)).unwrap();
+        assert_eq!(pool.submit(&state, xt("alice", 0, 5)), Err(PoolError::AlreadyImported));
+        assert_eq!(pool.submit(&state, xt("alice", 0, 4)), Err(PoolError::TooLowPriority));
+        assert_eq!(pool.submit(&state, xt("alice", 0, 6)), Ok(TransactionStatus::Ready));
+        assert_eq!(pool.ready(&state), vec![xt("alice", 0, 6)]);
+    }
+
+    #[test]
+    fn stale_transactions_are_rejected_and_pruned() {
+        let mut state = TestState { nonces: BTreeMap::new() };
+        let mut pool = TransactionPool::new();
+        pool.submit(&state, xt("alice", 0, 1)).unwrap();
+        pool.submit(&state, xt("alice", 1, 1)).unwrap();
+
+        // A block used alice's nonce 0
+        state.nonces.insert("alice".to_string(), 1);
+        assert_eq!(
+            pool.submit(&state, xt("alice", 0, 9)),
+            Err(PoolError::Invalid(InvalidTransaction::Stale))
+        );
+        assert_eq!(pool.prune(&state), 1);
+        assert_eq!(pool.ready(&state), vec![xt("alice", 1, 1)]);
+
+        state.nonces.insert("alice".to_string(), 2);
+        pool.prune(&state);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn one_sender_cannot_fill_the_pool() {
+        let state = TestState { nonces: BTreeMap::new() };
+        let mut pool = TransactionPool::new();
+
+        // alice's nonce 0 is missing, so everything she sends waits on it
+        for nonce in 1..=MAX_FUTURE_PER_SENDER as u32 {
+            assert_eq!(pool.submit(&state, xt("alice", nonce, 1)), Ok(TransactionStatus::Future));
+        }
+        let next = MAX_FUTURE_PER_SENDER as u32 + 1;
+        assert_eq!(pool.submit(&state, xt("alice", next, 1)), Err(PoolError::TooManyFuture));
+        assert_eq!(pool.len(), MAX_FUTURE_PER_SENDER);
+
+        // Replacing a waiting transaction and filling the gap are still fine
+        assert_eq!(pool.submit(&state, xt("alice", 1, 2)), Ok(TransactionStatus::Future));
+        assert_eq!(pool.submit(&state, xt("alice", 0, 1)), Ok(TransactionStatus::Ready));
+        assert_eq!(pool.submit(&state, xt("alice", next, 1)), Ok(TransactionStatus::Ready));
+    }
+
+    #[test]
+    fn a_full_pool_only_takes_replacements() {
+        let state = TestState { nonces: BTreeMap::new() };
+        let mut pool = TransactionPool::new();
+        for sender in 0..MAX_POOL_SIZE {
+            pool.submit(&state, xt(&sender.to_string(), 0, 1)).unwrap();
+        }
+
+        assert_eq!(pool.submit(&state, xt("alice", 0, 1)), Err(PoolError::Full));
+        assert_eq!(pool.submit(&state, xt("0", 0, 2)), Ok(TransactionStatus::Ready));
+        assert_eq!(pool.len(), MAX_POOL_SIZE);
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use num::traits::{CheckedAdd, CheckedMul, CheckedSub, Saturating, Zero};
+use crate::codec::{read_variant, Decode, DecodeError, Encode};
+use crate::metadata::{errors, variant, PalletInfo, VariantMetadata};
+use crate::migrations::{OnRuntimeUpgrade, StorageVersion};
+use crate::support::{
+    storage_key, DispatchError, GetWeight, StorageEntries, StorageEntry, Transactional, Weight,
+};
+
+// Vesting Config trait - block numbers convert into balances to work out how much has vested
+pub trait Config: crate::system::Config {
+    type Balance: CheckedAdd + CheckedSub + CheckedMul + Saturating + Zero + Copy + PartialOrd + Debug + Encode + Decode
+        + From<Self::BlockNumber>;
+    // Least a vested transfer has to lock, so that anyone cannot fill another
+    // account's schedules with dust
+    const MIN_VESTED_TRANSFER: Self::Balance;
+}
+
+// Most schedules one account can be vesting under at once
+pub const MAX_VESTING_SCHEDULES: usize = 4;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum VestingError {
+    NotVesting,
+    AtMaxVestingSchedules,
+    InvalidSchedule,
+    AmountLow,
+}
+
+impl std::fmt::Display for VestingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VestingError::NotVesting => write!(f, "Account has no vesting schedule"),
+            VestingError::AtMaxVestingSchedules => write!(f, "Account already has the most vesting schedules allowed"),
+            VestingError::InvalidSchedule => write!(f, "Vesting schedule must lock funds and release some every block"),
+            VestingError::AmountLow => write!(f, "Vested transfer is below the minimum amount"),
+        }
+    }
+}
+
+impl From<VestingError> for DispatchError {
+    fn from(error: VestingError) -> Self {
+        DispatchError::module("Vesting", error)
+    }
+}
+
+// `locked` tokens released `per_block` from `starting_block` on
+// Nothing is released before the starting block, so a later start works as a cliff
+#[derive(Debug, Clone)]
+pub struct VestingInfo<T: Config> {
+    pub locked: T::Balance,
+    pub per_block: T::Balance,
+    pub starting_block: T::BlockNumber,
+}
+
+impl<T: Config> VestingInfo<T> {
+    // How much of the schedule is still locked at block `now`
+    pub fn locked_at(&self, now: T::BlockNumber) -> T::Balance {
+        if now < self.starting_block {
+            return self.locked;
+        }
+        let elapsed = T::Balance::from(now)
+            .checked_sub(&T::Balance::from(self.starting_block))
+            .unwrap_or_else(T::Balance::zero);
+        // Released more than was ever locked: nothing is left
+        let vested = elapsed.checked_mul(&self.per_block).unwrap_or(self.locked);
+        self.locked.checked_sub(&vested).unwrap_or_else(T::Balance::zero)
+    }
+}
+
+impl<T: Config> Encode for VestingInfo<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.locked.encode_to(dest);
+        self.per_block.encode_to(dest);
+        self.starting_block.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for VestingInfo<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            locked: Decode::decode(input)?,
+            per_block: Decode::decode(input)?,
+            starting_block: Decode::decode(input)?,
+        })
+    }
+}
+
+// Vesting events - collected by the runtime into the system pallet
+#[derive(Debug, Clone)]
+pub enum VestingEvent<T: Config> {
+    VestingUpdated {
+        account: T::AccountId,
+        unvested: T::Balance,
+    },
+    VestingCompleted {
+        account: T::AccountId,
+    },
+}
+
+impl<T: Config> Encode for VestingEvent<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            VestingEvent::VestingUpdated { account, unvested } => {
+                dest.push(0);
+                account.encode_to(dest);
+                unvested.encode_to(dest);
+            }
+            VestingEvent::VestingCompleted { account } => {
+                dest.push(1);
+                account.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Config> Decode for VestingEvent<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(VestingEvent::VestingUpdated {
+                account: Decode::decode(input)?,
+                unvested: Decode::decode(input)?,
+            }),
+            1 => Ok(VestingEvent::VestingCompleted {
+                account: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+// The vesting pallet only keeps the schedules and works out what they still lock
+// The funds themselves stay in the balances pallet, held back by a lock the runtime keeps
+// at the amount `vest` returns
+#[derive(Debug, Clone)]
+pub struct Pallet<T: Config> {
+    pub vesting: BTreeMap<T::AccountId, Vec<VestingInfo<T>>>,
+    pub events: Vec<VestingEvent<T>>,
+}
+
+impl<T: Config> Pallet<T> {
+    pub fn new() -> Self {
+        Self {
+            vesting: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn schedules(&self, who: &T::AccountId) -> &[VestingInfo<T>] {
+        self.vesting.get(who).map_or(&[], Vec::as_slice)
+    }
+
+    // Add a schedule to `who`'s vesting
+    // The caller is responsible for `who` actually holding the funds it locks
+    pub fn add_vesting_schedule(
+        &mut self,
+        who: &T::AccountId,
+        schedule: VestingInfo<T>,
+    ) -> Result<(), VestingError> {
+        if schedule.locked.is_zero() || schedule.per_block.is_zero() {
+            return Err(VestingError::InvalidSchedule);
+        }
+        let schedules = self.vesting.entry(who.clone()).or_default();
+        if schedules.len() >= MAX_VESTING_SCHEDULES {
+            return Err(VestingError::AtMaxVestingSchedules);
+        }
+        schedules.push(schedule);
+        Ok(())
+    }
+
+    // How much of `who`'s funds their schedules still lock at block `now`
+    // A total too large for the balance type saturates, so it never locks less than it should
+    pub fn vesting_balance(&self, who: &T::AccountId, now: T::BlockNumber) -> T::Balance {
+        self.schedules(who)
+            .iter()
+            .fold(T::Balance::zero(), |total, schedule| {
+                total.saturating_add(schedule.locked_at(now))
+            })
+    }
+
+    // Release what has vested by block `now`, dropping schedules that are done,
+    // and return how much is still locked
+    pub fn vest(&mut self, who: &T::AccountId, now: T::BlockNumber) -> Result<T::Balance, VestingError> {
+        let schedules = self.vesting.get_mut(who).ok_or(VestingError::NotVesting)?;
+        schedules.retain(|schedule| !schedule.locked_at(now).is_zero());
+        if schedules.is_empty() {
+            self.vesting.remove(who);
+            self.events.push(VestingEvent::VestingCompleted { account: who.clone() });
+            return Ok(T::Balance::zero());
+        }
+
+        let unvested = self.vesting_balance(who, now);
+        self.events.push(VestingEvent::VestingUpdated {
+            account: who.clone(),
+            unvested,
+        });
+        Ok(unvested)
+    }
+
+    // Take the vesting events emitted since they were last taken
+    pub fn take_events(&mut self) -> Vec<VestingEvent<T>> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+// Vesting calls enum
+#[derive(Debug, Clone)]
+pub enum Call<T: Config> {
+    Vest,
+    VestedTransfer {
+        target: T::AccountId,
+        schedule: VestingInfo<T>,
+    },
+}
+
+// Weights of the vesting calls
+pub mod weights {
+    use crate::support::Weight;
+
+    pub const VEST: Weight = 100;
+    pub const VESTED_TRANSFER: Weight = 150;
+}
+
+impl<T: Config> GetWeight for Call<T> {
+    fn weight(&self) -> Weight {
+        match self {
+            Call::Vest => weights::VEST,
+            Call::VestedTransfer { .. } => weights::VESTED_TRANSFER,
+        }
+    }
+}
+
+// The schedules are all the storage there is, so the checkpoint is a copy of the pallet
+impl<T: Config> Transactional for Pallet<T>
+where
+    Self: Clone,
+{
+    type Checkpoint = Self;
+
+    fn checkpoint(&self) -> Self::Checkpoint {
+        self.clone()
+    }
+
+    fn restore(&mut self, checkpoint: Self::Checkpoint) {
+        *self = checkpoint;
+    }
+}
+
+// The vesting pallet commits to every account's schedules
+impl<T: Config> StorageEntries for Pallet<T> {
+    fn storage_entries(&self) -> Vec<StorageEntry> {
+        self.vesting
+            .iter()
+            .map(|(who, schedules)| (storage_key("Vesting", "Vesting", who), schedules.encode()))
+            .collect()
+    }
+}
+
+// Events are not saved - they only live until the block that emitted them is done
+impl<T: Config> Encode for Pallet<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.vesting.encode_to(dest);
+    }
+}
+
+impl<T: Config> Decode for Pallet<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            vesting: Decode::decode(input)?,
+            events: Vec::new(),
+        })
+    }
+}
+
+impl<T: Config> PalletInfo for Pallet<T> {
+    fn calls() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "Vest", &[], "Unlock the caller's funds that have vested so far"),
+            variant(1, "VestedTransfer", &[("target", "AccountId"), ("schedule", "VestingInfo")], "Transfer `schedule.locked` to `target`, released to them on `schedule`"),
+        ]
+    }
+
+    fn events() -> Vec<VariantMetadata> {
+        vec![
+            variant(0, "VestingUpdated", &[("account", "AccountId"), ("unvested", "Balance")], "Vested funds were unlocked, `unvested` are still locked"),
+            variant(1, "VestingCompleted", &[("account", "AccountId")], "Every schedule of an account has fully vested"),
+        ]
+    }
+
+    fn errors() -> Vec<VariantMetadata> {
+        errors(&[
+            VestingError::NotVesting,
+            VestingError::AtMaxVestingSchedules,
+            VestingError::InvalidSchedule,
+            VestingError::AmountLow,
+        ])
+    }
+}
+
+impl<T: Config> OnRuntimeUpgrade for Pallet<T> {
+    const NAME: &'static str = "Vesting";
+    const STORAGE_VERSION: StorageVersion = 1;
+}
+
+// Calls are encoded as a variant index followed by their fields
+impl<T: Config> Encode for Call<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Call::Vest => dest.push(0),
+            Call::VestedTransfer { target, schedule } => {
+                dest.push(1);
+                target.encode_to(dest);
+                schedule.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Config> Decode for Call<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_variant(input)? {
+            0 => Ok(Call::Vest),
+            1 => Ok(Call::VestedTransfer {
+                target: Decode::decode(input)?,
+                schedule: Decode::decode(input)?,
+            }),
+            index => Err(DecodeError::InvalidVariant(index)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestConfig;
+
+    impl crate::system::Config for TestConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+    }
+
+    impl Config for TestConfig {
+        type Balance = u128;
+        const MIN_VESTED_TRANSFER: u128 = 10;
+    }
+
+    fn schedule(locked: u128, per_block: u128, starting_block: u32) -> VestingInfo<TestConfig> {
+        VestingInfo { locked, per_block, starting_block }
+    }
+
+    #[test]
+    fn schedules_release_linearly_after_their_start() {
+        let cliff = schedule(100, 30, 10);
+        assert_eq!(cliff.locked_at(0), 100);
+        assert_eq!(cliff.locked_at(10), 100);
+        assert_eq!(cliff.locked_at(11), 70);
+        assert_eq!(cliff.locked_at(13), 10);
+        assert_eq!(cliff.locked_at(14), 0);
+        assert_eq!(schedule(100, u128::MAX, 0).locked_at(2), 0);
+    }
+
+    #[test]
+    fn vesting_drops_finished_schedules() {
+        let mut vesting = Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        assert_eq!(vesting.vest(&alice, 0), Err(VestingError::NotVesting));
+        assert_eq!(vesting.add_vesting_schedule(&alice, schedule(0, 1, 0)), Err(VestingError::InvalidSchedule));
+
+        vesting.add_vesting_schedule(&alice, schedule(50, 10, 0)).unwrap();
+        vesting.add_vesting_schedule(&alice, schedule(100, 10, 5)).unwrap();
+        assert_eq!(vesting.vesting_balance(&alice, 2), 130);
+        assert_eq!(vesting.vest(&alice, 5), Ok(100));
+        assert_eq!(vesting.schedules(&alice).len(), 1);
+        assert_eq!(vesting.vest(&alice, 15), Ok(0));
+        assert!(vesting.schedules(&alice).is_empty());
+        assert!(matches!(
+            vesting.take_events()[..],
+            [VestingEvent::VestingUpdated { unvested: 100, .. }, VestingEvent::VestingCompleted { .. }]
+        ));
+
+        for _ in 0..MAX_VESTING_SCHEDULES {
+            vesting.add_vesting_schedule(&alice, schedule(10, 1, 0)).unwrap();
+        }
+        assert_eq!(
+            vesting.add_vesting_schedule(&alice, schedule(10, 1, 0)),
+            Err(VestingError::AtMaxVestingSchedules)
+        );
+    }
+
+    #[test]
+    fn vesting_balance_saturates() {
+        let mut vesting = Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        vesting.add_vesting_schedule(&alice, schedule(u128::MAX - 5, 1, 0)).unwrap();
+        vesting.add_vesting_schedule(&alice, schedule(100, 1, 0)).unwrap();
+        assert_eq!(vesting.vesting_balance(&alice, 0), u128::MAX);
+    }
+}